@@ -1,9 +1,11 @@
-use std::{io::{self, stdin, stdout, Write}, collections::HashMap, error::Error, num::ParseFloatError};
+use std::{io::{self, stdin, stdout, Write}, collections::HashMap, error::Error, fmt::Display, num::ParseFloatError};
 
-use crate::{exp_interpreter::{Definitions, Value, calculate}, structs::Matrix, math};
+use crate::{exp_interpreter::{Definitions, UserFunctions, Typed, Value, Mode, calculate, define_function, assign_slice}, structs::Matrix, math::{self, Compatibility}, number::{is_prime, set_tolerance, tolerance, Complex, Number, Tolerance}, errors::EvalError, lexer::{tokenize, TokenKind}};
 
 pub struct App {
     definitions: Definitions,
+    functions: UserFunctions,
+    mode: Mode,
 }
 
 impl App {
@@ -14,7 +16,9 @@ impl App {
             (String::from("B"), Value::Matrix(Matrix::new_from(2, 2, &[&[3.0, 4.5], &[8.0, 2.0]]).unwrap())),
             (String::from("C"), Value::Matrix(Matrix::new_empty(1, 1))),
             (String::from("PI"), Value::Scalar(3.1415)),
-        ]))}
+        ])),
+        functions: UserFunctions::default(),
+        mode: Mode::Decimal }
     }
 
     pub fn start(&mut self) -> io::Result<()> {
@@ -30,10 +34,17 @@ impl App {
             match elements[0] {
                 "salir" => break,
                 "ayuda" => ayuda(),
-                "var" => declare_var(elements.as_slice(), &mut self.definitions),
-                "mostrar" => show_var(elements.as_slice(), &mut self.definitions),
-                "ecu" => solve_equation(elements.as_slice(), &self.definitions),
-                "ecsis" => system_solve(),
+                "var" => declare_var(elements.as_slice(), &mut self.definitions, self.mode),
+                "mostrar" => show_var(elements.as_slice(), &self.definitions, &self.functions),
+                "ecu" => solve_equation(elements.as_slice(), &mut self.definitions, &self.functions, self.mode),
+                "func" => declare_function(user_input.trim()["func".len()..].trim(), &mut self.functions),
+                "qr" => factor_qr(&elements[1..], &mut self.definitions, &self.functions, self.mode),
+                "lu" => factor_lu(&elements[1..], &mut self.definitions, &self.functions, self.mode),
+                "escalonar" => row_reduce(&elements[1..], &mut self.definitions, &self.functions, self.mode),
+                "ecsis" => system_solve(&elements[1..], &mut self.definitions, self.mode),
+                "modo" => change_mode(&elements[1..], &mut self.mode, &mut self.definitions),
+                "tolerancia" => change_tolerance(&elements[1..]),
+                _ if user_input.contains('=') => assign_var(user_input.trim(), &mut self.definitions, &self.functions, self.mode),
                 _ => println!("Entrada inválida: {}", user_input),
             }
         }
@@ -42,7 +53,10 @@ impl App {
     }
 }
 
-static FORBIDDEN_IDS: [&str; 8] = [
+// Variable donde se guarda el resultado del último `ecu`
+static LAST_RESULT_ID: &str = "ans";
+
+static FORBIDDEN_IDS: [&str; 10] = [
     "+" ,
     "-" ,
     "*" ,
@@ -51,6 +65,8 @@ static FORBIDDEN_IDS: [&str; 8] = [
     "^" ,
     "INV",
     "T",
+    "H",
+    LAST_RESULT_ID,
 ];
 
 fn greeting() {
@@ -70,13 +86,15 @@ Para mas información acerca del funcionamiento de la calculadora, ingrese `ayud
     println!("{}", message);
 }
 
-fn show_var(elements: &[&str], definitions: &mut Definitions) {
+fn show_var(elements: &[&str], definitions: &Definitions, functions: &UserFunctions) {
     if elements.len() > 1 {
         for i in 1..elements.len() {
             if let Some((k, v)) = definitions.0.get_key_value(elements[i]) {
                 print!("{} = ", k);
                 print_value(v);
                 println!();
+            } else if let Some(function) = functions.0.get(elements[i]) {
+                println!("{}({}) = {}", elements[i], function.params.join(", "), function.body);
             } else {
                 println!("La variable `{}` no está definida", elements[i]);
             }
@@ -87,35 +105,179 @@ fn show_var(elements: &[&str], definitions: &mut Definitions) {
             print_value(v);
             println!();
         }
+        for (name, function) in &functions.0 {
+            println!("{}({}) = {}", name, function.params.join(", "), function.body);
+        }
+    }
+}
+
+fn solve_equation(command: &[&str], definitions: &mut Definitions, functions: &UserFunctions, mode: Mode) {
+    let expression = command[1..].join(" ");
+    match calculate(&expression, definitions, functions, mode) {
+        Ok(result) => {
+            match &result {
+                Value::Scalar(scalar) => println!("Resultado: {}", rounded(scalar)),
+                Value::Rational(scalar) => println!("Resultado: {}", scalar),
+                Value::Modular(scalar) => println!("Resultado: {}", scalar),
+                Value::Complex(scalar) => println!("Resultado: {}", rounded_complex(scalar)),
+                Value::Matrix(matrix) => {
+                    println!("Resultado:");
+                    print_matrix(&matrix.map(rounded));
+                }
+                Value::RationalMatrix(matrix) => {
+                    println!("Resultado:");
+                    print_matrix(matrix);
+                }
+                Value::ModularMatrix(matrix) => {
+                    println!("Resultado:");
+                    print_matrix(matrix);
+                }
+                Value::ComplexMatrix(matrix) => {
+                    println!("Resultado:");
+                    print_matrix(&matrix.map(rounded_complex));
+                }
+            }
+            definitions.0.insert(LAST_RESULT_ID.to_string(), result);
+        }
+        Err(error) => print_error(&expression, &error),
+    }
+}
+
+// Muestra el error y señala con `^` la parte de la expresión que lo causó
+fn print_error(expression: &str, error: &EvalError) {
+    println!("Error: {}", error);
+    if let Some(span) = error.span() {
+        println!("    {}", expression);
+        println!("    {}{}", " ".repeat(span.start), "^".repeat((span.end - span.start).max(1)));
     }
 }
 
-fn solve_equation(command: &[&str], definitions: &Definitions) {
-    if let Ok(result) = calculate(&command[1..].join(" "), definitions) {
-        if let Some(scalar) = result.as_scalar() {
-            println!("Resultado: {}", *scalar);
-            return
-        } else if let Some(matrix) = result.as_matrix() {
-            println!("Resultado:");
-            print_matrix(matrix);
-            return
+// Un identificador es válido si puede usarse luego en una expresión
+fn is_valid_id(id: &str) -> bool {
+    matches!(tokenize(id).as_deref(), Ok([token]) if matches!(token.kind, TokenKind::Ident(_)))
+}
+
+// `X = <expresión>`: guarda el resultado de la expresión en la variable `X`.
+// `X[1, :] = <expresión>` reemplaza solo una parte de la matriz `X`
+fn assign_var(input: &str, definitions: &mut Definitions, functions: &UserFunctions, mode: Mode) {
+    let (id, expression) = match input.split_once('=') {
+        Some((id, expression)) => (id.trim(), expression.trim()),
+        None => return,
+    };
+    let name = id.split('[').next().unwrap_or(id).trim();
+    if FORBIDDEN_IDS.contains(&name) {
+        println!("Identificador reservado");
+        return;
+    }
+    if id.contains('[') {
+        match assign_slice(input, definitions, functions, mode) {
+            Ok((name, valor)) => {
+                print!("{} = ", name);
+                print_value(&valor);
+            }
+            Err(error) => print_error(input, &error),
+        }
+        return;
+    }
+    if !is_valid_id(id) {
+        println!("Identificador inválido: `{}`", id);
+        return;
+    }
+    match calculate(expression, definitions, functions, mode) {
+        Ok(valor) => {
+            print!("{} = ", id);
+            print_value(&valor);
+            if let Some(anterior) = definitions.0.insert(id.to_string(), valor) {
+                println!("Valor anterior:");
+                print_value(&anterior);
+            }
+        }
+        Err(error) => print_error(expression, &error),
+    }
+}
+
+// `func NOMBRE(X, Y) = <expresión>`: declara una función que luego puede llamarse desde `ecu`
+fn declare_function(definition: &str, functions: &mut UserFunctions) {
+    match define_function(definition) {
+        Ok((name, function)) => {
+            println!("{}({}) = {}", name, function.params.join(", "), function.body);
+            if functions.0.insert(name, function).is_some() {
+                println!("La definición anterior fue reemplazada");
+            }
         }
+        Err(error) => print_error(definition, &error),
     }
-    println!("Ocurrió un error");
 }
 
-fn declare_var(command: &[&str], definitions: &mut Definitions) {
+// `modo exacto` hace que los números escritos sean fracciones exactas, `modo modular P` enteros módulo el primo
+// `P`, y `modo decimal` vuelve a los decimales. Las variables guardadas se convierten al nuevo modo
+fn change_mode(args: &[&str], current: &mut Mode, definitions: &mut Definitions) {
+    let mode = match args {
+        ["exacto"] => Mode::Exact,
+        ["decimal"] => Mode::Decimal,
+        ["modular", modulus] => match modulus.parse::<i64>() {
+            // Los productos se calculan en i128, así que el módulo debe entrar en 32 bits
+            Ok(modulus) if is_prime(modulus) && modulus <= u32::MAX as i64 => Mode::Modular(modulus),
+            _ => {
+                println!("El módulo debe ser un número primo: `{}`", modulus);
+                return;
+            }
+        },
+        [] => *current,
+        _ => {
+            println!("Modo inválido: `{}`. Use `exacto`, `decimal` o `modular P`", args.join(" "));
+            return;
+        }
+    };
+    *current = mode;
+    for value in definitions.0.values_mut() {
+        *value = value.clone().into_mode(mode);
+    }
+    match mode {
+        Mode::Exact => println!("Modo exacto: los resultados se muestran como fracciones"),
+        Mode::Decimal => println!("Modo decimal"),
+        Mode::Modular(modulus) => println!("Modo modular: las operaciones son módulo {}", modulus),
+    }
+}
+
+// `tolerancia <absoluta> [relativa]`: cambia cuánto pueden diferir dos decimales para considerarse iguales, lo que
+// decide por ejemplo si una matriz es singular. Sin argumentos muestra la tolerancia actual
+fn change_tolerance(args: &[&str]) {
+    let current = tolerance();
+    let values: Result<Vec<f64>, _> = args.iter().map(|arg| arg.parse::<f64>()).collect();
+    match values.as_deref() {
+        Ok(values) if values.iter().any(|x| !x.is_finite() || *x < 0.0) => {
+            println!("La tolerancia debe ser un número no negativo");
+            return;
+        }
+        Ok([]) => {}
+        Ok([absolute]) => set_tolerance(Tolerance { absolute: *absolute, ..current }),
+        Ok([absolute, relative]) => set_tolerance(Tolerance { absolute: *absolute, relative: *relative }),
+        _ => {
+            println!("Comando inválido. Uso: `tolerancia <absoluta> [relativa]`");
+            return;
+        }
+    }
+    let tolerance = tolerance();
+    println!("Tolerancia: absoluta {:e}, relativa {:e}", tolerance.absolute, tolerance.relative);
+}
+
+fn declare_var(command: &[&str], definitions: &mut Definitions, mode: Mode) {
     if let Some(id) = command.get(1) {
         if FORBIDDEN_IDS.contains(id) {
             println!("Identificador reservado");
             return;
         }
+        if !is_valid_id(id) {
+            println!("Identificador inválido: `{}`", id);
+            return;
+        }
         if let Some(tipo) = command.get(2) {
             match *tipo {
                 "ESCALAR" => {
                     if let Some(valor) = command.get(3) {
-                        if let Ok(valor) = valor.parse::<f32>() {
-                            let valor = Value::Scalar(valor);
+                        if let Ok(valor) = valor.parse::<f64>() {
+                            let valor = Value::Scalar(valor).into_mode(mode);
                             if let Some(anterior) = definitions.0.insert(id.to_string(), valor) {
                                 println!("Valor anterior:");
                                 print_value(&anterior);
@@ -133,7 +295,7 @@ fn declare_var(command: &[&str], definitions: &mut Definitions) {
                         if let (Ok(m), Ok(n)) = (m.parse::<usize>(), n.parse::<usize>()) {
                             let valor;
                             if let Ok(matrix) = read_matrix(m, n) {
-                                valor = Value::Matrix(matrix);
+                                valor = Value::Matrix(matrix).into_mode(mode);
                             } else {
                                 return;
                             }
@@ -169,9 +331,37 @@ Uso:
         * Ejemplos:
             - `var PI ESCALAR 3.14`
             - `var MAT MATRIZ 2 2` El programa pedirá ingresar los datos separados por espacios y saltos de linea
+    * `<NOMBRE> = <expresión>`: Guarda el resultado de una expresión en una variable
+        * Ejemplo: `X = ( A + B ) INV`
+    * `<NOMBRE>[filas, columnas] = <expresión>`: Reemplaza una parte de una matriz. Ejemplo: `A[1, :] = [5 6]`
     * `mostrar [identificador]`: Sin argumentos, muestra los detalles de todas las variables declaradas. Filtra por los nombres dados
     * `ecu`: Resolver una ecuación. La sintaxis para ecuaciones se detalla en el archivo README.md
-    * `ecsis`: Ingresar un sistema de ecuaciones para determinar la compatibilidad del sistema
+        * El resultado queda guardado en la variable `ans`. Ejemplo: `ecu ans T * ans`
+        * Números complejos: `ecu (3+2i) * [1 i; -i 2] H`, donde `i` es la unidad imaginaria y `H` la transpuesta conjugada
+    * `func <NOMBRE>(<parámetros>) = <expresión>`: Declara una función que puede usarse en `ecu`
+        * Ejemplo: `func P(X) = X * (X T * X) INV * X T`, luego `ecu P(A)`
+        * Los parámetros solo existen dentro de la función y no modifican las variables
+    * `lu <expresión>`: Factoriza la matriz como `P A = L U` y guarda los factores en `P`, `L` y `U`
+        * `ecu lusolve(P, L, U, b)` resuelve luego `A x = b` para cada columna de `b` sin volver a factorizar
+    * `qr <expresión>`: Factoriza la matriz como `A = Q R`, con `Q` ortogonal, y guarda los factores en `Q` y `R`
+        * `ecu qr(A)` da los factores uno al lado del otro, `[Q R]`
+        * `ecu cholesky(A)` factoriza una matriz simétrica definida positiva como `A = L L T`
+        * `is_symmetric`, `is_posdef`, `is_orthogonal`, `is_diagonal` e `is_triangular` dan 1 o 0 según la matriz
+    * `escalonar [final] <expresión>`: Muestra cada operación de filas que lleva la matriz a su forma escalonada
+      reducida, como `F2 <- F2 - 3·F1`. Con `final` muestra solo el resultado y las columnas pivote
+    * `ecsis [NOMBRE]`: Ingresar un sistema de ecuaciones para determinar la compatibilidad del sistema y resolverlo
+        * La solución queda guardada en `NOMBRE`, o en `ans` si no se indica
+        * Si hay incógnitas libres, como `x3`, se guarda una solución particular en `NOMBRE` y en `NOMBRE_x3` el vector
+          que multiplica a `x3`
+    * `ecsis minimos [NOMBRE]`: Solución por mínimos cuadrados, la que minimiza la norma de `b - A x`, aunque el
+      sistema sea incompatible. El residuo queda guardado en `NOMBRE_residuo`
+        * Desde `ecu`: `lstsq(A, b)` da la solución y `lstsq_residuo(A, b)` el residuo
+    * `modo [exacto | decimal]`: En modo exacto los números son fracciones y `det`, `inv`, `adj` y `ecsis` no
+      redondean: `ecu [1 2; 3 4] INV` da `-2 1; 3/2 -1/2`. Sin argumentos muestra el modo actual
+    * `tolerancia [absoluta] [relativa]`: Cuánto pueden diferir dos decimales para considerarse iguales, por
+      ejemplo al decidir si una matriz es singular. Sin argumentos muestra la tolerancia actual
+    * `modo modular <P>`: Los números son enteros módulo el primo P. Ejemplo: con `modo modular 7`,
+      `ecu [1 2; 3 4] INV` da `5 1; 5 3`
     * `salir`: Termina el programa
 ";
     print!("{}", message);
@@ -183,7 +373,12 @@ fn prompt(input: &mut String) -> Result<usize, io::Error> {
     stdin().read_line(input)
 }
 
-fn print_matrix(mat: &Matrix) {
+fn print_matrix<T: Display>(mat: &Matrix<T>) {
+    // Como la base de un subespacio nulo, que no tiene vectores
+    if mat.m == 0 || mat.n == 0 {
+        println!("Matriz vacía ({}x{})", mat.m, mat.n);
+        return;
+    }
     for row in 0..mat.m {
         for col in 0..mat.n {
             print!("{} ", mat[row][col]);
@@ -192,14 +387,45 @@ fn print_matrix(mat: &Matrix) {
     }
 }
 
+// Los decimales se muestran con 12 cifras significativas, así `0.1 + 0.2` se ve como `0.3`. Sumar cero convierte
+// `-0` en `0`, que de otro modo se muestra como `-0`, o como `1+-0i` en un complejo
+fn rounded(x: &f64) -> f64 {
+    format!("{:.11e}", x).parse().unwrap_or(*x) + 0.0
+}
+
+fn rounded_complex(z: &Complex) -> Complex {
+    Complex::new(rounded(&z.re), rounded(&z.im))
+}
+
 fn print_value(value: &Value) {
     if let Some(matrix) = value.as_matrix() {
+        if matrix.m > 1 {
+            println!()
+        }
+        print_matrix(&matrix.map(rounded));
+    } else if let Some(matrix) = value.as_rational_matrix() {
         if matrix.m > 1 {
             println!()
         }
         print_matrix(matrix);
     } else if let Some(scalar) = value.as_scalar() {
-        println!("{}", *scalar);
+        println!("{}", rounded(scalar));
+    } else if let Some(matrix) = value.as_modular_matrix() {
+        if matrix.m > 1 {
+            println!()
+        }
+        print_matrix(matrix);
+    } else if let Some(scalar) = value.as_rational() {
+        println!("{}", scalar);
+    } else if let Some(matrix) = value.as_complex_matrix() {
+        if matrix.m > 1 {
+            println!()
+        }
+        print_matrix(&matrix.map(rounded_complex));
+    } else if let Some(scalar) = value.as_modular() {
+        println!("{}", scalar);
+    } else if let Some(scalar) = value.as_complex() {
+        println!("{}", rounded_complex(scalar));
     }
 }
 
@@ -211,7 +437,7 @@ fn read_matrix(m: usize, n: usize) -> Result<Matrix, Box<dyn Error>> {
         print!("Fila {}: ", i);
         stdout().flush()?;
         stdin().read_line(&mut line)?;
-        let values: Vec<Result<f32, ParseFloatError>> = line.split_ascii_whitespace().map(|x| x.parse::<f32>()).collect();
+        let values: Vec<Result<f64, ParseFloatError>> = line.split_ascii_whitespace().map(|x| x.parse::<f64>()).collect();
         if values.len() != n {
             println!("Numero de columnas incorrecto");
             return Err("Numero de columnas incorrecto")?;
@@ -229,7 +455,138 @@ fn read_matrix(m: usize, n: usize) -> Result<Matrix, Box<dyn Error>> {
     return Ok(mat);
 }
 
-fn system_solve() {
+// `lu <expresión>`: factoriza la matriz como `P A = L U` y guarda los factores en `P`, `L` y `U`, que luego sirven
+// para resolver `A x = b` con `lusolve(P, L, U, b)` sin volver a escalonar
+fn factor_lu(args: &[&str], definitions: &mut Definitions, functions: &UserFunctions, mode: Mode) {
+    let expression = args.join(" ");
+    let factors = match calculate(&expression, definitions, functions, mode) {
+        Ok(Value::Matrix(matrix)) => lu_values(math::lu(&matrix)),
+        Ok(Value::RationalMatrix(matrix)) => lu_values(math::lu(&matrix)),
+        Ok(Value::ModularMatrix(matrix)) => lu_values(math::lu(&matrix)),
+        Ok(Value::ComplexMatrix(matrix)) => lu_values(math::lu(&matrix)),
+        Ok(_) => return println!("`lu` requiere una matriz"),
+        Err(error) => return print_error(&expression, &error),
+    };
+    for (name, value) in ["P", "L", "U"].into_iter().zip(factors) {
+        print!("{} = ", name);
+        print_value(&value);
+        if definitions.0.insert(name.to_string(), value).is_some() {
+            println!("(reemplaza el valor anterior de `{}`)", name);
+        }
+    }
+}
+
+// `qr <expresión>`: factoriza la matriz como `A = Q R` y guarda los factores en `Q` y `R`. Los números exactos se
+// convierten a decimales, porque normalizar las columnas requiere raíces cuadradas
+fn factor_qr(args: &[&str], definitions: &mut Definitions, functions: &UserFunctions, mode: Mode) {
+    let expression = args.join(" ");
+    let matrix = match calculate(&expression, definitions, functions, mode) {
+        Ok(value) if value.is_complex() || value.is_modular() => {
+            return println!("`qr` solo está disponible para números reales")
+        }
+        Ok(value) => match value.numbers().into_float() {
+            Typed::Matrix(matrix) => matrix,
+            Typed::Scalar(_) => return println!("`qr` requiere una matriz"),
+        },
+        Err(error) => return print_error(&expression, &error),
+    };
+    let factors = math::qr(&matrix);
+    for (name, factor) in [("Q", factors.q), ("R", factors.r)] {
+        print!("{} = ", name);
+        let value = Value::Matrix(factor);
+        print_value(&value);
+        if definitions.0.insert(name.to_string(), value).is_some() {
+            println!("(reemplaza el valor anterior de `{}`)", name);
+        }
+    }
+    if factors.rank < matrix.n {
+        println!(
+            "Las columnas no son linealmente independientes: la matriz tiene rango {} y {} columnas, así que R tiene ceros en la diagonal",
+            factors.rank, matrix.n
+        );
+    }
+}
+
+fn lu_values<T: Number>(factors: math::Lu<T>) -> [Value; 3]
+where
+    Value: From<Typed<T>>,
+{
+    [factors.p, factors.l, factors.u].map(|matrix| Value::from(Typed::Matrix(matrix)))
+}
+
+// `escalonar [final] <expresión>`: muestra cada operación de filas hasta la forma escalonada reducida de la matriz,
+// o solo el resultado y las columnas pivote con `final`. El resultado queda guardado en `ans`
+fn row_reduce(args: &[&str], definitions: &mut Definitions, functions: &UserFunctions, mode: Mode) {
+    let (only_final, expression) = match args {
+        ["final", rest @ ..] => (true, rest.join(" ")),
+        _ => (false, args.join(" ")),
+    };
+    let result = match calculate(&expression, definitions, functions, mode) {
+        Ok(result) => result,
+        Err(error) => return print_error(&expression, &error),
+    };
+    let result = match result {
+        Value::Matrix(matrix) => show_rref(math::rref(&matrix), only_final, |x| rounded(x).to_string()),
+        Value::RationalMatrix(matrix) => show_rref(math::rref(&matrix), only_final, |x| x.to_string()),
+        Value::ModularMatrix(matrix) => show_rref(math::rref(&matrix), only_final, |x| x.to_string()),
+        Value::ComplexMatrix(matrix) => show_rref(math::rref(&matrix), only_final, |z| format!("({})", rounded_complex(z))),
+        _ => return println!("`escalonar` requiere una matriz"),
+    };
+    definitions.0.insert(LAST_RESULT_ID.to_string(), result);
+}
+
+// Imprime las operaciones como `F2 <- F2 - 3·F1`, con las filas numeradas desde 1, y devuelve la matriz reducida
+fn show_rref<T: Number>(rref: math::Rref<T>, only_final: bool, show: fn(&T) -> String) -> Value
+where
+    Value: From<Typed<T>>,
+{
+    if !only_final {
+        for step in rref.steps {
+            match step.operation {
+                math::RowOperation::Swap(i, j) => println!("F{} <-> F{}", i + 1, j + 1),
+                math::RowOperation::Scale(i, factor) => println!("F{} <- {}·F{}", i + 1, show(&factor), i + 1),
+                math::RowOperation::AddMultiple { target, source, factor } => {
+                    let factor = show(&factor);
+                    let (sign, factor) = match factor.strip_prefix('-') {
+                        Some(positive) => ("-", positive.to_string()),
+                        None => ("+", factor),
+                    };
+                    let factor = if factor == "1" { String::new() } else { format!("{}·", factor) };
+                    println!("F{} <- F{} {} {}F{}", target + 1, target + 1, sign, factor, source + 1);
+                }
+            }
+            print_value(&Value::from(Typed::Matrix(step.matrix)));
+            println!();
+        }
+    }
+    println!("Forma escalonada reducida:");
+    let result = Value::from(Typed::Matrix(rref.matrix));
+    print_value(&result);
+    let pivots: Vec<String> = rref.pivots.iter().map(|j| (j + 1).to_string()).collect();
+    if pivots.is_empty() {
+        println!("No hay columnas pivote");
+    } else {
+        println!("Columnas pivote: {}", pivots.join(", "));
+    }
+    result
+}
+
+// `ecsis [minimos] [NOMBRE]`: la solución queda guardada en `NOMBRE`, o en `ans` si no se indica. Con `minimos` se
+// busca la solución por mínimos cuadrados, que existe aunque el sistema sea incompatible
+fn system_solve(args: &[&str], definitions: &mut Definitions, mode: Mode) {
+    let (least_squares, name) = match args {
+        ["minimos", rest @ ..] => (true, rest.first().copied()),
+        _ => (false, args.first().copied()),
+    };
+    let name = name.unwrap_or(LAST_RESULT_ID);
+    if name != LAST_RESULT_ID && FORBIDDEN_IDS.contains(&name) {
+        println!("Identificador reservado");
+        return;
+    }
+    if !is_valid_id(name) {
+        println!("Identificador inválido: `{}`", name);
+        return;
+    }
     println!("Cantidad de incógnitas: ");
     let mut incognitas = String::new();
     stdout().flush().unwrap();
@@ -241,19 +598,26 @@ fn system_solve() {
     if let (Ok(cant_incognitas), Ok(cant_ecuaciones)) = (incognitas.trim().parse::<usize>(), ecuaciones.trim().parse::<usize>()) {
         println!("Ingrese los datos separados por espacios, y presione Enter luego de cada fila. Escriba los datos en formato matriz expandida A|b siendo b el vector independiente");    
         if let Ok(mat) = read_matrix(cant_ecuaciones, cant_incognitas+1) {
-            let result = math::solve_system(&mat);
-            if result.is_incompatible() {
-                println!("El sistema de ecuaciones");
-                print_matrix(&mat);
-                println!("Es incompatible");
-            } else if result.is_compatible_indeterminado() {
-                println!("El sistema de ecuaciones");
-                print_matrix(&mat);
-                println!("Es compatible Indeterminado");
-            }else {
-                println!("El sistema de ecuaciones");
-                print_matrix(&mat);
-                println!("Es compatible determinado");
+            println!("El sistema de ecuaciones");
+            print_matrix(&mat);
+            if least_squares {
+                let a = math::submatrix(&mat, 0..mat.m, 0..cant_incognitas);
+                let b = math::submatrix(&mat, 0..mat.m, cant_incognitas..cant_incognitas+1);
+                // En modo exacto las ecuaciones normales dan la solución exacta. Con decimales QR pierde menos precisión
+                match (Value::Matrix(a.clone()).into_mode(mode), Value::Matrix(b.clone()).into_mode(mode)) {
+                    (Value::RationalMatrix(a), Value::RationalMatrix(b)) => {
+                        show_least_squares(math::least_squares_normal(&a, &b), name, definitions, |x| x.to_string())
+                    }
+                    (Value::ModularMatrix(_), _) => println!("Mínimos cuadrados no está disponible en modo modular"),
+                    _ => show_least_squares(math::least_squares_qr(&a, &b), name, definitions, |x| rounded(x).to_string()),
+                }
+                return;
+            }
+            // En modo exacto se escalona con fracciones, sin errores de redondeo, y en modo modular en Z_p
+            match Value::Matrix(mat.clone()).into_mode(mode) {
+                Value::RationalMatrix(exact) => show_solution(math::solve_system(&exact), name, definitions, |x| x.to_string()),
+                Value::ModularMatrix(modular) => show_solution(math::solve_system(&modular), name, definitions, |x| x.to_string()),
+                _ => show_solution(math::solve_system(&mat), name, definitions, |x| rounded(x).to_string()),
             }
         } else {
         println!("Error en la carga de datos");
@@ -262,3 +626,100 @@ fn system_solve() {
         println!("No ha ingresado los datos");
     }
 }
+
+// Muestra la solución por mínimos cuadrados y la guarda en `name`, con el residuo `b - A x` en `name_residuo`
+fn show_least_squares<T: Number>(
+    result: Result<math::LeastSquares<T>, Box<dyn Error>>,
+    name: &str,
+    definitions: &mut Definitions,
+    show: fn(&T) -> String,
+) where
+    Value: From<Typed<T>>,
+{
+    let solution = match result {
+        Ok(solution) => solution,
+        Err(error) => {
+            match error.downcast_ref::<math::Singular>() {
+                Some(singular) => println!(
+                    "Las columnas de A no son linealmente independientes (rango {}): la solución no es única",
+                    singular.rank
+                ),
+                None => println!("Error: {}", error),
+            }
+            return;
+        }
+    };
+    println!("Solución por mínimos cuadrados:");
+    for i in 0..solution.x.m {
+        println!("x{} = {}", i + 1, show(&solution.x[i][0]));
+    }
+    let residual: Vec<String> = (0..solution.residual.m).map(|i| show(&solution.residual[i][0])).collect();
+    println!("Residuo b - A x: [{}]", residual.join("; "));
+    println!("Norma del residuo: {}", rounded(&solution.norm));
+    println!("Solución guardada en `{}` y residuo en `{}_residuo`", name, name);
+    definitions.0.insert(format!("{}_residuo", name), Value::from(Typed::Matrix(solution.residual)));
+    definitions.0.insert(name.to_string(), Value::from(Typed::Matrix(solution.x)));
+}
+
+// Muestra cada incógnita en función de las libres y guarda la solución en `name`. Si el sistema es indeterminado,
+// `name` es una solución particular y `name_xk` el vector que multiplica a la incógnita libre `xk`
+fn show_solution<T: Number>(result: Compatibility<T>, name: &str, definitions: &mut Definitions, show: fn(&T) -> String)
+where
+    Value: From<Typed<T>>,
+{
+    if result.is_incompatible() {
+        println!("Es incompatible");
+    } else if result.is_compatible_determinado() {
+        println!("Es compatible determinado");
+    } else if result.is_compatible_indeterminado() {
+        print!("Es compatible indeterminado. ");
+    }
+    match result {
+        Compatibility::Incompatible => println!("Con `ecsis minimos` se obtiene la solución por mínimos cuadrados"),
+        Compatibility::CompatibleDeterminado(x) => {
+            for i in 0..x.m {
+                println!("x{} = {}", i + 1, show(&x[i][0]));
+            }
+            println!("Solución guardada en `{}`", name);
+            definitions.0.insert(name.to_string(), Value::from(Typed::Matrix(x)));
+        }
+        Compatibility::CompatibleIndeterminado(solution) => {
+            let free: Vec<String> = solution.free.iter().map(|j| format!("x{}", j + 1)).collect();
+            println!("Incógnitas libres: {}", free.join(", "));
+            for i in (0..solution.particular.m).filter(|i| !solution.free.contains(i)) {
+                let mut terms = String::new();
+                if !solution.particular[i][0].is_zero() {
+                    terms.push_str(&show(&solution.particular[i][0]));
+                }
+                for (vector, variable) in solution.basis.iter().zip(&free) {
+                    if vector[i][0].is_zero() {
+                        continue;
+                    }
+                    let coefficient = show(&vector[i][0]);
+                    let (sign, coefficient) = match coefficient.strip_prefix('-') {
+                        Some(positive) => ("-", positive.to_string()),
+                        None => ("+", coefficient),
+                    };
+                    if !terms.is_empty() {
+                        terms.push_str(&format!(" {} ", sign));
+                    } else if sign == "-" {
+                        terms.push('-');
+                    }
+                    if coefficient != "1" {
+                        terms.push_str(&format!("{} ", coefficient));
+                    }
+                    terms.push_str(variable);
+                }
+                println!("x{} = {}", i + 1, if terms.is_empty() { String::from("0") } else { terms });
+            }
+            let mut general = name.to_string();
+            for (vector, variable) in solution.basis.into_iter().zip(&free) {
+                let id = format!("{}_{}", name, variable);
+                general.push_str(&format!(" + {} * {}", variable, id));
+                definitions.0.insert(id, Value::from(Typed::Matrix(vector)));
+            }
+            println!("Solución general guardada como `{}`", general);
+            definitions.0.insert(name.to_string(), Value::from(Typed::Matrix(solution.particular)));
+        }
+    }
+}
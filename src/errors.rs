@@ -0,0 +1,205 @@
+// Errors produced while interpreting and evaluating expressions
+use std::{error::Error, fmt};
+
+use crate::{exp_interpreter::{Operators, MAX_CALL_DEPTH}, lexer::Span, math::{NotPositiveDefinite, Singular}};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalError {
+    InvalidCharacter { ch: char, span: Span },
+    InvalidNumber { span: Span },
+    UnknownIdentifier { name: String, span: Span },
+    UnbalancedParenthesis { span: Span },
+    UnbalancedBracket { span: Span },
+    UnexpectedToken { span: Span },
+    MissingOperand { op: Operators, span: Span },
+    MissingOperator { span: Span },
+    EmptyExpression,
+    // `op` is the operator or function name as written in the expression
+    DimensionMismatch { op: String, left: (usize, usize), right: (usize, usize), span: Span },
+    NotSquare { op: String, shape: (usize, usize), span: Span },
+    // `rank` is the rank found, smaller than `size`
+    SingularMatrix { rank: usize, size: usize, span: Span },
+    // `name` needs linearly independent columns, but only `rank` of the `cols` columns are
+    RankDeficient { name: String, rank: usize, cols: usize, span: Span },
+    NotSymmetric { op: String, span: Span },
+    // The leading principal minor of order `order` is not positive
+    NotPositiveDefinite { op: String, order: usize, span: Span },
+    UnaryOperatorMisuse { op: Operators, span: Span },
+    InvalidOperands { op: Operators, span: Span },
+    InvalidExponent { span: Span },
+    // A power too large to compute: an exact result with too many digits, or a matrix exponent above `u32::MAX`
+    ExponentTooLarge { span: Span },
+    DivisionByZero { span: Span },
+    RaggedMatrix { span: Span },
+    EmptyMatrix { span: Span },
+    NonScalarElement { span: Span },
+    UnknownFunction { name: String, span: Span },
+    WrongArgumentCount { name: String, min: usize, max: usize, found: usize, span: Span },
+    InvalidArgument { name: String, span: Span },
+    InvalidDefinition { span: Span },
+    ReservedFunction { name: String, span: Span },
+    DuplicateParameter { name: String, span: Span },
+    RecursionLimit { name: String, span: Span },
+    ZeroVector { name: String, span: Span },
+    IndexCount { found: usize, span: Span },
+    InvalidIndex { span: Span },
+    IndexOutOfBounds { index: usize, size: usize, span: Span },
+    NotIndexable { span: Span },
+    InvalidTarget { span: Span },
+    // Error raised while evaluating the body of a user function. `span` is the outermost call
+    InFunction { name: String, span: Span, error: Box<EvalError> },
+}
+
+impl EvalError {
+    /// Error for a `size` x `size` matrix with no inverse, with the rank found by `inv`
+    pub fn singular(error: &(dyn Error + 'static), size: usize, span: Span) -> EvalError {
+        let rank = error.downcast_ref::<Singular>().map_or(0, |singular| singular.rank);
+        EvalError::SingularMatrix { rank, size, span }
+    }
+
+    /// Error for `name` applied to a matrix whose `cols` columns are dependent
+    pub fn rank_deficient(error: &(dyn Error + 'static), name: String, cols: usize, span: Span) -> EvalError {
+        let rank = error.downcast_ref::<Singular>().map_or(0, |singular| singular.rank);
+        EvalError::RankDeficient { name, rank, cols, span }
+    }
+
+    /// Error for `op` applied to a square matrix that is not symmetric positive definite
+    pub fn not_positive_definite(error: &(dyn Error + 'static), op: String, span: Span) -> EvalError {
+        match error.downcast_ref::<NotPositiveDefinite>() {
+            Some(NotPositiveDefinite::Minor(order)) => EvalError::NotPositiveDefinite { op, order: *order, span },
+            _ => EvalError::NotSymmetric { op, span },
+        }
+    }
+
+    /// Part of the expression that caused the error, if known
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            EvalError::InvalidCharacter { span, .. }
+            | EvalError::InvalidNumber { span }
+            | EvalError::UnknownIdentifier { span, .. }
+            | EvalError::UnbalancedParenthesis { span }
+            | EvalError::UnbalancedBracket { span }
+            | EvalError::UnexpectedToken { span }
+            | EvalError::MissingOperand { span, .. }
+            | EvalError::MissingOperator { span }
+            | EvalError::DimensionMismatch { span, .. }
+            | EvalError::NotSquare { span, .. }
+            | EvalError::SingularMatrix { span, .. }
+            | EvalError::RankDeficient { span, .. }
+            | EvalError::NotSymmetric { span, .. }
+            | EvalError::NotPositiveDefinite { span, .. }
+            | EvalError::UnaryOperatorMisuse { span, .. }
+            | EvalError::InvalidOperands { span, .. }
+            | EvalError::InvalidExponent { span }
+            | EvalError::ExponentTooLarge { span }
+            | EvalError::DivisionByZero { span }
+            | EvalError::RaggedMatrix { span }
+            | EvalError::EmptyMatrix { span }
+            | EvalError::NonScalarElement { span }
+            | EvalError::UnknownFunction { span, .. }
+            | EvalError::WrongArgumentCount { span, .. }
+            | EvalError::InvalidArgument { span, .. }
+            | EvalError::InvalidDefinition { span }
+            | EvalError::ReservedFunction { span, .. }
+            | EvalError::DuplicateParameter { span, .. }
+            | EvalError::RecursionLimit { span, .. }
+            | EvalError::ZeroVector { span, .. }
+            | EvalError::IndexCount { span, .. }
+            | EvalError::InvalidIndex { span }
+            | EvalError::IndexOutOfBounds { span, .. }
+            | EvalError::NotIndexable { span }
+            | EvalError::InvalidTarget { span }
+            | EvalError::InFunction { span, .. } => Some(*span),
+            EvalError::EmptyExpression => None,
+        }
+    }
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalError::InvalidCharacter { ch, .. } => write!(f, "Caracter inesperado `{}`", ch),
+            EvalError::InvalidNumber { .. } => write!(f, "Número inválido"),
+            EvalError::UnknownIdentifier { name, .. } => write!(f, "La variable `{}` no está definida", name),
+            EvalError::UnbalancedParenthesis { .. } => write!(f, "Paréntesis sin cerrar o sin abrir"),
+            EvalError::UnbalancedBracket { .. } => write!(f, "Corchete sin cerrar o sin abrir"),
+            EvalError::UnexpectedToken { .. } => write!(f, "Símbolo inesperado"),
+            EvalError::MissingOperand { op, .. } => write!(f, "Falta un operando para `{}`", op),
+            EvalError::MissingOperator { .. } => write!(f, "Falta un operador entre dos operandos"),
+            EvalError::EmptyExpression => write!(f, "La expresión está vacía"),
+            EvalError::DimensionMismatch { op, left, right, .. } => write!(
+                f,
+                "Dimensiones incompatibles para `{}`: {}x{} y {}x{}",
+                op, left.0, left.1, right.0, right.1
+            ),
+            EvalError::NotSquare { op, shape, .. } => write!(
+                f,
+                "`{}` requiere una matriz cuadrada, pero la matriz es {}x{}",
+                op, shape.0, shape.1
+            ),
+            EvalError::SingularMatrix { rank, size, .. } => write!(
+                f,
+                "La matriz no es inversible: es {}x{} pero tiene rango {} (determinante nulo)",
+                size, size, rank
+            ),
+            EvalError::RankDeficient { name, rank, cols, .. } => write!(
+                f,
+                "`{}` requiere columnas linealmente independientes, pero la matriz tiene {} columnas y rango {}",
+                name, cols, rank
+            ),
+            EvalError::NotSymmetric { op, .. } => write!(f, "`{}` requiere una matriz simétrica", op),
+            EvalError::NotPositiveDefinite { op, order, .. } => write!(
+                f,
+                "`{}` requiere una matriz definida positiva, pero el menor principal de orden {} no es positivo",
+                op, order
+            ),
+            EvalError::UnaryOperatorMisuse { op, .. } => write!(f, "No se puede aplicar `{}` a un escalar", op),
+            EvalError::InvalidOperands { op, .. } => write!(f, "Operandos inválidos para `{}`", op),
+            EvalError::InvalidExponent { .. } => {
+                write!(f, "El exponente de una matriz debe ser un entero no negativo")
+            }
+            EvalError::ExponentTooLarge { .. } => write!(f, "La potencia es demasiado grande para calcularla"),
+            EvalError::DivisionByZero { .. } => write!(f, "División por cero"),
+            EvalError::RaggedMatrix { .. } => write!(f, "Todas las filas de la matriz deben tener la misma cantidad de elementos"),
+            EvalError::EmptyMatrix { .. } => write!(f, "La matriz no tiene elementos"),
+            EvalError::NonScalarElement { .. } => write!(f, "Los elementos de una matriz deben ser escalares"),
+            EvalError::UnknownFunction { name, .. } => write!(f, "La función `{}` no existe", name),
+            EvalError::WrongArgumentCount { name, min, max, found, .. } => {
+                let expected = if min == max {
+                    format!("{}", min)
+                } else if *max == usize::MAX {
+                    format!("al menos {}", min)
+                } else {
+                    format!("entre {} y {}", min, max)
+                };
+                write!(f, "`{}` recibe {} argumento(s), pero se le pasaron {}", name, expected, found)
+            }
+            EvalError::InvalidArgument { name, .. } => write!(f, "Argumento inválido para `{}`", name),
+            EvalError::InvalidDefinition { .. } => {
+                write!(f, "La definición debe tener la forma `NOMBRE(parámetros) = expresión`")
+            }
+            EvalError::ReservedFunction { name, .. } => write!(f, "`{}` es una función predefinida", name),
+            EvalError::DuplicateParameter { name, .. } => write!(f, "El parámetro `{}` está repetido", name),
+            EvalError::RecursionLimit { name, .. } => write!(
+                f,
+                "Se superó el límite de {} llamadas anidadas al evaluar `{}`",
+                MAX_CALL_DEPTH, name
+            ),
+            EvalError::ZeroVector { name, .. } => write!(f, "`{}` no está definida para el vector nulo", name),
+            EvalError::IndexCount { found, .. } => {
+                write!(f, "Se esperaban 2 índices (`A[fila, columna]`), pero hay {}", found)
+            }
+            EvalError::InvalidIndex { .. } => write!(f, "Los índices deben ser enteros no negativos"),
+            EvalError::IndexOutOfBounds { index, size, .. } => {
+                write!(f, "El índice {} está fuera de rango, la dimensión es {}", index, size)
+            }
+            EvalError::NotIndexable { .. } => write!(f, "Solo se pueden indexar matrices"),
+            EvalError::InvalidTarget { .. } => {
+                write!(f, "Solo se puede asignar a una variable o a una parte de ella, como `A[1, :]`")
+            }
+            EvalError::InFunction { name, error, .. } => write!(f, "En la función `{}`: {}", name, error),
+        }
+    }
+}
+
+impl Error for EvalError {}
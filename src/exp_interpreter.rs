@@ -1,9 +1,17 @@
 use phf::{phf_map, Map};
+use num_traits::Zero;
 
 // Tools for interpreting and calculating expressions
-use std::{collections::{HashMap}, error::Error};
+use std::{cell::Cell, collections::{HashMap}, fmt, ops::Range};
 
-use crate::{structs::Matrix, math::{mul_scalar, mul, sum, sub, pow, transpose, det, inv}};
+use crate::{
+    structs::Matrix,
+    math::{mul_scalar, mul, sum, sub, elem_mul, elem_div, elem_pow, pow, transpose, conj_transpose, det, inv, submatrix, set_submatrix, broadcast, PowerTooLarge},
+    lexer::{tokenize, Span, Token, TokenKind},
+    errors::EvalError,
+    functions::{Functions, FUNCTIONS},
+    number::{rational_from_f64, rational_to_f64, Complex, Modular, Number, Rational},
+};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Operators {
@@ -12,24 +20,290 @@ pub enum Operators {
     Sum,
     Sub,
     Pow,
+    ElemMul,
+    ElemDiv,
+    ElemPow,
     Transp,
+    // Conjugate transpose
+    ConjTransp,
     Det,
     Inv,
+    Neg,
+    Pos,
 }
 
 pub enum Operand<'a> {
     Operation(Operators),
-    Scalar(f32),
-    Matrix(&'a Matrix),
+    // Number written in the expression. It is a fraction in the exact mode
+    Scalar(f64),
+    // Imaginary number written in the expression, like `2i`
+    Imaginary(f64),
+    // Stored variable, keeps the type of number it was saved with
+    Variable(&'a Value),
+    // `[...]` literal with its size. The elements are the node's arguments, row by row
+    MatrixLiteral(usize, usize),
+    // Call to a predefined function. The node's arguments are the function arguments
+    Function(Functions),
+    // Call to a function declared with `func`, with its name
+    UserFunction(&'a str, &'a UserFunction),
+    // `A[rows, cols]`. The node's arguments are the indexed value, the rows and the columns
+    Index,
+    // `from:to` inside an index. Its children are the bounds, a missing child is an omitted bound
+    Range,
+    // Omitted bound while the tree is being built, never part of a finished tree
+    OpenBound,
 }
 
+#[derive(Debug, Clone)]
 pub enum Value {
-    Scalar(f32),
+    Scalar(f64),
     Matrix(Matrix),
+    // Exact fractions, used in the exact mode
+    Rational(Rational),
+    RationalMatrix(Matrix<Rational>),
+    // Integers modulo a prime, used in the modular mode
+    Modular(Modular),
+    ModularMatrix(Matrix<Modular>),
+    Complex(Complex),
+    ComplexMatrix(Matrix<Complex>),
+}
+
+// Value whose numbers all have type `T`, so each operation is written once for every type of number
+#[derive(Debug, Clone)]
+pub enum Typed<T> {
+    Scalar(T),
+    Matrix(Matrix<T>),
+}
+
+// The numbers of a value, split by their type
+pub enum Numbers {
+    Exact(Typed<Rational>),
+    Modular(Typed<Modular>),
+    Float(Typed<f64>),
+    Complex(Typed<Complex>),
+}
+
+// Type of number given to the numbers written in expressions. Chosen for the session with `modo`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Mode {
+    #[default]
+    Decimal,
+    Exact,
+    // Integers modulo the given prime
+    Modular(i64),
+}
+
+impl<T: Clone> Typed<T> {
+    pub fn map<U>(&self, f: impl Fn(&T) -> U) -> Typed<U> {
+        match self {
+            Typed::Scalar(num) => Typed::Scalar(f(num)),
+            Typed::Matrix(matrix) => Typed::Matrix(matrix.map(f)),
+        }
+    }
+
+    // Every element, row by row
+    pub fn elements(&self) -> Vec<T> {
+        match self {
+            Typed::Scalar(num) => vec![num.clone()],
+            Typed::Matrix(matrix) => (0..matrix.m).flat_map(|i| matrix[i].clone()).collect(),
+        }
+    }
+
+    // Like `map`, but gives nothing if `f` fails for any element
+    pub fn try_map<U: Clone + Zero>(&self, f: impl Fn(&T) -> Option<U>) -> Option<Typed<U>> {
+        let elements = self.elements().iter().map(f).collect::<Option<Vec<U>>>()?;
+        match self {
+            Typed::Scalar(_) => elements.into_iter().next().map(Typed::Scalar),
+            Typed::Matrix(matrix) => Some(Typed::Matrix(from_elements(matrix.m, matrix.n, elements.into_iter()))),
+        }
+    }
+}
+
+impl Numbers {
+    // Fractions become the closest f64, and modular integers their representative. Complex numbers with an
+    // imaginary part have no real value, they become NaN
+    pub fn into_float(self) -> Typed<f64> {
+        match self {
+            Numbers::Exact(value) => value.map(rational_to_f64),
+            Numbers::Modular(value) => value.map(|num| num.value() as f64),
+            Numbers::Float(value) => value,
+            Numbers::Complex(value) => value.map(|num| if num.im == 0.0 { num.re } else { f64::NAN }),
+        }
+    }
+
+    pub fn into_complex(self) -> Typed<Complex> {
+        match self {
+            Numbers::Complex(value) => value,
+            numbers => numbers.into_float().map(|num| Complex::from(*num)),
+        }
+    }
+
+    // Decimals become the fraction they show, so `0.1` is `1/10`. Infinity, NaN and complex numbers have no
+    // fraction
+    pub fn into_exact(self) -> Option<Typed<Rational>> {
+        match self {
+            Numbers::Exact(value) => Some(value),
+            Numbers::Modular(value) => Some(value.map(|num| Rational::from_integer(num.value().into()))),
+            Numbers::Float(value) => value.try_map(|num| rational_from_f64(*num)),
+            Numbers::Complex(_) => None,
+        }
+    }
+
+    // A fraction `a/b` is `a` times the inverse of `b`, which doesn't exist if `b` is a multiple of `modulus`
+    pub fn into_modular(self, modulus: i64) -> Option<Typed<Modular>> {
+        match self {
+            Numbers::Modular(value) => Some(value.map(|num| Modular::new(num.value(), modulus))),
+            numbers => numbers.into_exact()?.try_map(|num| Modular::from_rational(num, modulus)),
+        }
+    }
+
+    pub fn is_exact(&self) -> bool {
+        matches!(self, Numbers::Exact(..))
+    }
+}
+
+impl From<Typed<f64>> for Value {
+    fn from(value: Typed<f64>) -> Value {
+        match value {
+            Typed::Scalar(num) => Value::Scalar(num),
+            Typed::Matrix(matrix) => Value::Matrix(matrix),
+        }
+    }
+}
+
+impl From<Typed<Rational>> for Value {
+    fn from(value: Typed<Rational>) -> Value {
+        match value {
+            Typed::Scalar(num) => Value::Rational(num),
+            Typed::Matrix(matrix) => Value::RationalMatrix(matrix),
+        }
+    }
+}
+
+impl From<Typed<Complex>> for Value {
+    fn from(value: Typed<Complex>) -> Value {
+        match value {
+            Typed::Scalar(num) => Value::Complex(num),
+            Typed::Matrix(matrix) => Value::ComplexMatrix(matrix),
+        }
+    }
+}
+
+impl From<Numbers> for Value {
+    fn from(numbers: Numbers) -> Value {
+        match numbers {
+            Numbers::Exact(value) => Value::from(value),
+            Numbers::Modular(value) => Value::from(value),
+            Numbers::Float(value) => Value::from(value),
+            Numbers::Complex(value) => Value::from(value),
+        }
+    }
+}
+
+impl From<Typed<Modular>> for Value {
+    fn from(value: Typed<Modular>) -> Value {
+        match value {
+            Typed::Scalar(num) => Value::Modular(num),
+            Typed::Matrix(matrix) => Value::ModularMatrix(matrix),
+        }
+    }
 }
 
 impl Value {
-    pub fn as_scalar(&self) -> Option<&f32> {
+    pub fn numbers(self) -> Numbers {
+        match self {
+            Value::Scalar(num) => Numbers::Float(Typed::Scalar(num)),
+            Value::Matrix(matrix) => Numbers::Float(Typed::Matrix(matrix)),
+            Value::Rational(num) => Numbers::Exact(Typed::Scalar(num)),
+            Value::RationalMatrix(matrix) => Numbers::Exact(Typed::Matrix(matrix)),
+            Value::Modular(num) => Numbers::Modular(Typed::Scalar(num)),
+            Value::ModularMatrix(matrix) => Numbers::Modular(Typed::Matrix(matrix)),
+            Value::Complex(num) => Numbers::Complex(Typed::Scalar(num)),
+            Value::ComplexMatrix(matrix) => Numbers::Complex(Typed::Matrix(matrix)),
+        }
+    }
+
+    // Same value with the type of number of `mode`. Values that can't be converted, like infinity in the
+    // exact mode or complex numbers, stay as they are
+    pub fn into_mode(self, mode: Mode) -> Value {
+        let numbers = self.clone().numbers();
+        let converted = match mode {
+            Mode::Decimal if self.is_complex() => None,
+            Mode::Decimal => Some(Value::from(numbers.into_float())),
+            Mode::Exact => numbers.into_exact().map(Value::from),
+            Mode::Modular(modulus) => numbers.into_modular(modulus).map(Value::from),
+        };
+        converted.unwrap_or(self)
+    }
+
+    // Scalar with no fractional part, as used for indices and sizes
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            Value::Scalar(num) => num.as_integer(),
+            Value::Rational(num) => num.as_integer(),
+            Value::Modular(num) => num.as_integer(),
+            Value::Complex(num) => num.as_integer(),
+            _ => None,
+        }
+    }
+
+    pub fn is_complex(&self) -> bool {
+        matches!(self, Value::Complex(_) | Value::ComplexMatrix(_))
+    }
+
+    pub fn is_modular(&self) -> bool {
+        matches!(self, Value::Modular(_) | Value::ModularMatrix(_))
+    }
+
+    pub fn as_complex(&self) -> Option<&Complex> {
+        if let Self::Complex(v) = self {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_complex_matrix(&self) -> Option<&Matrix<Complex>> {
+        if let Self::ComplexMatrix(v) = self {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_modular(&self) -> Option<&Modular> {
+        if let Self::Modular(v) = self {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_modular_matrix(&self) -> Option<&Matrix<Modular>> {
+        if let Self::ModularMatrix(v) = self {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_rational(&self) -> Option<&Rational> {
+        if let Self::Rational(v) = self {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_rational_matrix(&self) -> Option<&Matrix<Rational>> {
+        if let Self::RationalMatrix(v) = self {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_scalar(&self) -> Option<&f64> {
         if let Self::Scalar(v) = self {
             Some(v)
         } else {
@@ -79,14 +353,6 @@ impl<'a> Operand<'a> {
         matches!(self, Self::Scalar(..))
     }
 
-    /// Returns `true` if the operand is [`Matrix`].
-    ///
-    /// [`Matrix`]: Operand::Matrix
-    #[must_use]
-    pub fn is_matrix(&self) -> bool {
-        matches!(self, Self::Matrix(..))
-    }
-
     pub fn as_operation(&self) -> Option<&Operators> {
         if let Self::Operation(v) = self {
             Some(v)
@@ -95,32 +361,26 @@ impl<'a> Operand<'a> {
         }
     }
 
-    pub fn as_scalar(&self) -> Option<&f32> {
+    pub fn as_scalar(&self) -> Option<&f64> {
         if let Self::Scalar(v) = self {
             Some(v)
         } else {
             None
         }
     }
-
-    pub fn as_matrix(&self) -> Option<&&'a Matrix> {
-        if let Self::Matrix(v) = self {
-            Some(v)
-        } else {
-            None
-        }
-    }
 }
 
 pub struct ExpTree<'a> {
     op: Operand<'a>,
+    span: Span,
     left_op: Option<Box<ExpTree<'a>>>,
     right_op: Option<Box<ExpTree<'a>>>,
+    args: Vec<ExpTree<'a>>,
 }
 
 impl<'a> ExpTree<'a> {
-    pub fn new(op: Operand<'a>) -> ExpTree<'a> {
-        return ExpTree { op, left_op: None, right_op: None };
+    pub fn new(op: Operand<'a>, span: Span) -> ExpTree<'a> {
+        return ExpTree { op, span, left_op: None, right_op: None, args: Vec::new() };
     }
 
     pub fn left_op(&self) -> &Option<Box<ExpTree<'a>>> {
@@ -139,7 +399,15 @@ impl<'a> ExpTree<'a> {
         self.right_op = Some(Box::new(right_op));
     }
 
-    pub fn op(&self) -> &Operand {
+    pub fn args(&self) -> &Vec<ExpTree<'a>> {
+        &self.args
+    }
+
+    pub fn set_args(&mut self, args: Vec<ExpTree<'a>>) {
+        self.args = args;
+    }
+
+    pub fn op(&self) -> &Operand<'a> {
         &self.op
     }
 
@@ -147,397 +415,2023 @@ impl<'a> ExpTree<'a> {
         self.op = op;
     }
 
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
     pub fn is_leaf(&self) -> bool {
         return self.left_op().is_none() && self.right_op().is_none();
     }
 
     // Will return either a number or matrix as a result
-    fn solve(&self) -> Result<Value, Box<dyn Error>> {
-        if self.is_leaf() {
-            if self.op().is_operation() {
-                return Err("Leaf node has operator as only data")?;
+    fn solve(&self, scope: &Scope) -> Result<Value, EvalError> {
+        let operator = match self.op() {
+            Operand::Scalar(value) => return Ok(Value::Scalar(*value).into_mode(scope.mode)),
+            Operand::Imaginary(value) => return Ok(Value::Complex(Complex::new(0.0, *value))),
+            Operand::Variable(value) => return Ok((*value).clone()),
+            Operand::MatrixLiteral(rows, cols) => return self.solve_matrix_literal(*rows, *cols, scope),
+            Operand::Function(function) => return function.call(self.solve_args(scope)?, self.span(), scope.mode),
+            Operand::UserFunction(name, function) => {
+                return call_user_function(name, function, self.solve_args(scope)?, self.span(), scope);
             }
-            if let Some(value) = self.op().as_scalar() {
-                return Ok(Value::Scalar(*value));
-            } else if let Some(value) = self.op().as_matrix() {
-                return Ok(Value::Matrix(value.clone().to_owned()));
+            Operand::Index => return self.solve_index(scope),
+            // Ranges are only solved as part of an index
+            Operand::Range | Operand::OpenBound => return Err(EvalError::UnexpectedToken { span: self.span() }),
+            Operand::Operation(operator) => *operator,
+        };
+        let span = self.span();
+
+        if operator.is_unary() {
+            if self.right_op().is_some() {
+                return Err(EvalError::UnaryOperatorMisuse { op: operator, span });
             }
+            let operand = match self.left_op() {
+                Some(left) => left.solve(scope)?,
+                None => return Err(EvalError::MissingOperand { op: operator, span }),
+            };
+            return match operand.numbers() {
+                Numbers::Exact(operand) => solve_unary(operator, operand, span).map(Value::from),
+                Numbers::Modular(operand) => solve_unary(operator, operand, span).map(Value::from),
+                Numbers::Float(operand) => solve_unary(operator, operand, span).map(Value::from),
+                Numbers::Complex(operand) => solve_unary(operator, operand, span).map(Value::from),
+            };
         }
-        
-        if let Some(operator) = self.op().as_operation() {
-            match *operator {
-                Operators::Mul => {
-                    if let (Some(left), Some(right)) = (self.left_op(), self.right_op()) {
-                        if let (Ok(left), Ok(right)) = (left.solve(), right.solve()) {
-                            // Left and right are scalars, left is matrix and the other scalar, the other way around, or both are matrices
-                            if let (Some(left), Some(right)) = (left.as_scalar(), right.as_scalar()) {
-                                return Ok(Value::Scalar(left * right));
-                            } else if let (Some(left), Some(right)) = (left.as_scalar(), right.as_matrix()) {
-                                return Ok(Value::Matrix(mul_scalar(right, *left)));
-                            } else if let (Some(left), Some(right)) = (left.as_matrix(), right.as_scalar()) {
-                                return Ok(Value::Matrix(mul_scalar(left, *right)));
-                            } else if let (Some(left), Some(right)) = (left.as_matrix(), right.as_matrix()) {
-                                if let Ok(result) = mul(left, right) {
-                                    return Ok(Value::Matrix(result));
-                                }
-                            }
-                        }
-                    }
-                },
-                Operators::Div => {
-                    if let (Some(left), Some(right)) = (self.left_op(), self.right_op()) {
-                        if let (Ok(left), Ok(right)) = (left.solve(), right.solve()) {
-                            // Left and right are scalars, both are matrices, or matrix divided by num
-                            if let (Some(left), Some(right)) = (left.as_scalar(), right.as_scalar()) {
-                                if *right == 0.0 {
-                                    return Err("division by zero")?;
-                                }
-                                return Ok(Value::Scalar(left / right));
-                            } else if let (Some(left), Some(right)) = (left.as_matrix(), right.as_scalar()) {
-                                if *right == 0.0 {
-                                    return Err("division by zero")?;
-                                }
-                                return Ok(Value::Matrix(mul_scalar(left, 1.0/right)));
-                            } else if let (Some(left), Some(right)) = (left.as_matrix(), right.as_matrix()) {
-                                if let Ok(inverse) = inv(right) {
-                                    if let Ok(result) = mul(left, &inverse) {
-                                        return Ok(Value::Matrix(result));
-                                    }
-                                }
-                            }
-                        }
-                    }
-                },
-                Operators::Sum => {
-                    if let (Some(left), Some(right)) = (self.left_op(), self.right_op()) {
-                        if let (Ok(left), Ok(right)) = (left.solve(), right.solve()) {
-                            // Left and right are scalars, or both are matrices
-                            if let (Some(left), Some(right)) = (left.as_scalar(), right.as_scalar()) {
-                                return Ok(Value::Scalar(left + right));
-                            } else if let (Some(left), Some(right)) = (left.as_matrix(), right.as_matrix()) {
-                                if let Ok(result) = sum(left, right) {
-                                    return Ok(Value::Matrix(result));
-                                }
-                            }
-                        }
-                    }
-                },
-                Operators::Sub => {
-                    if let (Some(left), Some(right)) = (self.left_op(), self.right_op()) {
-                        if let (Ok(left), Ok(right)) = (left.solve(), right.solve()) {
-                            // Left and right are scalars, or both are matrices
-                            if let (Some(left), Some(right)) = (left.as_scalar(), right.as_scalar()) {
-                                return Ok(Value::Scalar(left - right));
-                            } else if let (Some(left), Some(right)) = (left.as_matrix(), right.as_matrix()) {
-                                if let Ok(result) = sub(left, right) {
-                                    return Ok(Value::Matrix(result));
-                                }
-                            }
-                        }
-                    }
-                },
-                Operators::Pow => {
-                    if let (Some(left), Some(right)) = (self.left_op(), self.right_op()) {
-                        if let (Ok(left), Ok(right)) = (left.solve(), right.solve()) {
-                            // Left can be both, right always scalar
-                            if let (Some(left), Some(right)) = (left.as_scalar(), right.as_scalar()) {
-                                return Ok(Value::Scalar(left.powf(*right)));
-                            } else if let (Some(left), Some(right)) = (left.as_matrix(), right.as_scalar()) {
-                                if let Ok(result) = pow(left, *right as i8) {
-                                    return Ok(Value::Matrix(result));
-                                }
-                            }
-                        }
-                    }
-                }
-                Operators::Transp => {
-                    if self.right_op().is_some() {
-                        return Err("Operador unario tiene dos operandos")?;
-                    } else if let Some(left) = self.left_op() {
-                        if let Ok(left) = left.solve() {
-                            if left.is_scalar() {
-                                return Err("No se puede aplicar la operacion Transponer a un escalar")?;
-                            } else if let Ok(result) = transpose(left.as_matrix().unwrap()) {
-                                return Ok(Value::Matrix(result));
-                            }
-                        }
-                    }
-                }
-                Operators::Det => {
-                    if self.right_op().is_some() {
-                        return Err("Operador unario tiene dos operandos")?;
-                    } else if let Some(left) = self.left_op() {
-                        if let Ok(left) = left.solve() {
-                            if left.is_scalar() {
-                                return Err("No se puede aplicar la operacion Determinante a un escalar")?;
-                            } else if let Ok(result) = det(left.as_matrix().unwrap()) {
-                                return Ok(Value::Scalar(result));
-                            }
-                        }
-                    }
-                }
-                Operators::Inv => {
-                    if self.right_op().is_some() {
-                        return Err("Operador unario tiene dos operandos")?;
-                    } else if let Some(left) = self.left_op() {
-                        if let Ok(left) = left.solve() {
-                            if left.is_scalar() {
-                                return Err("No se puede aplicar la operacion Inversa a un escalar")?;
-                            } else if let Ok(result) = inv(left.as_matrix().unwrap()) {
-                                return Ok(Value::Matrix(result));
-                            }
-                        }
-                    }
-                }
+
+        // Modular exponents are plain integers: reducing them mod p would change the power, as in `2 ^ 7`
+        let power = matches!(operator, Operators::Pow | Operators::ElemPow);
+        let right_scope = match scope.mode {
+            Mode::Modular(_) if power => Scope { mode: Mode::Exact, ..*scope },
+            _ => *scope,
+        };
+        let (left, right) = match (self.left_op(), self.right_op()) {
+            (Some(left), Some(right)) => (left.solve(scope)?, right.solve(&right_scope)?),
+            _ => return Err(EvalError::MissingOperand { op: operator, span }),
+        };
+        // The result is exact only if both operands are. An inexact one, like the result of `sqrt`, or a
+        // fractional power make the whole operation inexact. The same goes for modular integers. A complex
+        // operand makes the whole operation complex
+        match (left.numbers(), right.numbers()) {
+            (Numbers::Exact(l), Numbers::Exact(r)) if !fractional_power(operator, &r) => {
+                solve_binary(operator, l, r, span).map(Value::from)
             }
-        } else {
-            return Err("Non leaf node is not an operator")?;
+            (Numbers::Modular(l), r @ (Numbers::Exact(_) | Numbers::Float(_))) if power => {
+                let r = integer_exponent(r).ok_or(EvalError::InvalidOperands { op: operator, span })?;
+                modular_power(operator, l, r, span).map(Value::from)
+            }
+            (Numbers::Modular(l), Numbers::Modular(r)) => solve_binary(operator, l, r, span).map(Value::from),
+            (l @ Numbers::Complex(_), r) | (l, r @ Numbers::Complex(_)) => {
+                solve_binary(operator, l.into_complex(), r.into_complex(), span).map(Value::from)
+            }
+            (l, r) => solve_binary(operator, l.into_float(), r.into_float(), span).map(Value::from),
         }
-        return Err("Something happened")?;
     }
-}
-
-static OPERATIONS: Map<&str, Operators> = phf_map! {
-    "+"   => Operators::Sum,
-    "-"   => Operators::Sub,
-    "/"   => Operators::Div,
-    "*"   => Operators::Mul,
-    "^"   => Operators::Pow,
-    "INV" => Operators::Inv,
-    "T"   => Operators::Transp,
-    "DET" => Operators::Det,
-};
 
-static OP_PRECEDENCE: Map<&str, usize> = phf_map! {
-    "+"   => 1,
-    "-"   => 1,
-    "*"   => 1,
-    "/"   => 2,
-    "DET" => 2,
-    "^"   => 3,
-    "INV" => 3,
-    "T"   => 3,
-};
+    fn solve_index(&self, scope: &Scope) -> Result<Value, EvalError> {
+        let target = &self.args()[0];
+        match target.solve(scope)?.numbers() {
+            Numbers::Exact(Typed::Matrix(matrix)) => self.select(&matrix, scope).map(Value::from),
+            Numbers::Modular(Typed::Matrix(matrix)) => self.select(&matrix, scope).map(Value::from),
+            Numbers::Float(Typed::Matrix(matrix)) => self.select(&matrix, scope).map(Value::from),
+            Numbers::Complex(Typed::Matrix(matrix)) => self.select(&matrix, scope).map(Value::from),
+            _ => Err(EvalError::NotIndexable { span: target.span() }),
+        }
+    }
 
-// All operations are binary unless specified here
-static UNARY_OPS: Map<&str, bool> = phf_map! {
-    "INV" => true,
-    "T"   => true,
-    "DET" => true,
-};
+    fn select<T: Number>(&self, matrix: &Matrix<T>, scope: &Scope) -> Result<Typed<T>, EvalError> {
+        let (rows, single_row) = self.args()[1].solve_selection(matrix.m, scope)?;
+        let (cols, single_col) = self.args()[2].solve_selection(matrix.n, scope)?;
+        if single_row && single_col {
+            return Ok(Typed::Scalar(matrix[rows.start][cols.start].clone()));
+        }
+        Ok(Typed::Matrix(submatrix(matrix, rows, cols)))
+    }
 
-fn is_operator(key: &str) -> bool {
-    OP_PRECEDENCE.contains_key(key)
-}
+    // Positions picked by an index along a dimension of size `len`, and whether it was a single position.
+    // Indices start at 0 and ranges don't include their upper bound
+    fn solve_selection(&self, len: usize, scope: &Scope) -> Result<(Range<usize>, bool), EvalError> {
+        if !matches!(self.op(), Operand::Range) {
+            let index = self.solve_position(scope)?;
+            if index >= len {
+                return Err(EvalError::IndexOutOfBounds { index, size: len, span: self.span() });
+            }
+            return Ok((index..index + 1, true));
+        }
+        let from = match self.left_op() {
+            Some(node) => node.solve_position(scope)?,
+            None => 0,
+        };
+        let to = match self.right_op() {
+            Some(node) => node.solve_position(scope)?,
+            None => len,
+        };
+        if to > len {
+            let span = self.right_op().as_ref().map_or(self.span(), |node| node.span());
+            return Err(EvalError::IndexOutOfBounds { index: to, size: len, span });
+        }
+        if from >= to {
+            return Err(EvalError::EmptyMatrix { span: self.span() });
+        }
+        Ok((from..to, false))
+    }
 
-// Struct that holds the currently declared variables 
-pub struct Definitions(pub HashMap<String, Value>);
+    fn solve_position(&self, scope: &Scope) -> Result<usize, EvalError> {
+        match self.solve(scope)?.as_integer() {
+            Some(index) if index >= 0 => Ok(index as usize),
+            _ => Err(EvalError::InvalidIndex { span: self.span() }),
+        }
+    }
 
-fn in_variable_defintions(key: &str, map: &Definitions) -> bool {
-    map.0.keys().find(|&x| *x == *key).is_some()
-}
+    fn solve_args(&self, scope: &Scope) -> Result<Vec<Value>, EvalError> {
+        self.args().iter().map(|arg| arg.solve(scope)).collect()
+    }
 
-fn infix_to_postfix<'a>(
-    infix_exp: &'a Vec<&'a str>,
-    definitions: &'a Definitions,
-) -> Vec<&'a str> {
-    let mut stack: Vec<&str> = Vec::new();
-
-    let mut postfix: Vec<&str> = Vec::new();
-    for elem in infix_exp {
-        if *elem == "(" {
-            stack.push(elem);
-        } else if *elem == ")" {
-            while let Some(_elem) = stack.pop() {
-                if _elem == "(" {
-                    break;
+    // The matrix is exact, or modular, only if all of its elements are. A single complex element makes it complex
+    fn solve_matrix_literal(&self, rows: usize, cols: usize, scope: &Scope) -> Result<Value, EvalError> {
+        let mut elements = Vec::new();
+        for element in self.args() {
+            match element.solve(scope)? {
+                value @ (Value::Scalar(_) | Value::Rational(_) | Value::Modular(_) | Value::Complex(_)) => {
+                    elements.push(value)
                 }
-                postfix.push(_elem);
+                _ => return Err(EvalError::NonScalarElement { span: element.span() }),
             }
         }
-        // If number or declared variable
-        else if elem.trim().parse::<f32>().is_ok() || in_variable_defintions(elem, definitions) {
-            postfix.push(elem);
+        if let Some(exact) = elements.iter().map(Value::as_rational).collect::<Option<Vec<_>>>() {
+            return Ok(Value::RationalMatrix(from_elements(rows, cols, exact.into_iter().cloned())));
         }
-        // If number is operator
-        if is_operator(elem) {
-            // Pop all other operators and push to output (except parenthesis)
-            while let Some(_elem) = stack.pop() {
-                if _elem == "(" || OP_PRECEDENCE.get(_elem) <= OP_PRECEDENCE.get(elem) {
-                    stack.push(_elem);
-                    break;
-                } else {
-                    postfix.push(_elem);
-                }
-            }
-            stack.push(elem);
+        if let Some(modular) = elements.iter().map(Value::as_modular).collect::<Option<Vec<_>>>() {
+            return Ok(Value::ModularMatrix(from_elements(rows, cols, modular.into_iter().copied())));
         }
+        if elements.iter().any(Value::is_complex) {
+            let complex = elements.into_iter().flat_map(|value| value.numbers().into_complex().elements());
+            return Ok(Value::ComplexMatrix(from_elements(rows, cols, complex)));
+        }
+        let float = elements.into_iter().flat_map(|value| value.numbers().into_float().elements());
+        Ok(Value::Matrix(from_elements(rows, cols, float)))
     }
-    // Finish sending stack to output
-    while let Some(_elem) = stack.pop() {
-        postfix.push(_elem);
+}
+
+// Parameters only exist while the body is evaluated, they never reach the global definitions
+fn call_user_function(
+    name: &str,
+    function: &UserFunction,
+    args: Vec<Value>,
+    span: Span,
+    scope: &Scope,
+) -> Result<Value, EvalError> {
+    if scope.depth >= MAX_CALL_DEPTH {
+        return Err(EvalError::RecursionLimit { name: name.to_string(), span });
     }
-    return postfix;
+    let locals = Definitions(function.params.iter().cloned().zip(args).collect());
+    let inner = Scope { locals: Some(&locals), depth: scope.depth + 1, ..*scope };
+    let result = postfix_to_tree(&function.postfix, &inner).and_then(|tree| tree.solve(&inner));
+    // Spans inside the body mean nothing in the caller's expression, so point at the outermost call
+    // and keep only the original cause
+    result.map_err(|error| match error {
+        EvalError::InFunction { error, .. } => EvalError::InFunction { name: name.to_string(), span, error },
+        error => EvalError::InFunction { name: name.to_string(), span, error: Box::new(error) },
+    })
 }
 
-fn postfix_to_tree<'a>(
-    postfix_exp: &'a Vec<&'a str>,
-    definitions: &'a Definitions
-) -> Option<ExpTree<'a>> {
-    let mut stack: Vec<ExpTree> = Vec::new();
+fn solve_unary<T: Number>(operator: Operators, operand: Typed<T>, span: Span) -> Result<Typed<T>, EvalError> {
+    // Prefix signs work on both scalars and matrices
+    match (operator, &operand) {
+        (Operators::Pos, _) => return Ok(operand),
+        (Operators::Neg, Typed::Scalar(num)) => return Ok(Typed::Scalar(-num.clone())),
+        (Operators::Neg, Typed::Matrix(matrix)) => return Ok(Typed::Matrix(mul_scalar(matrix, -T::one()))),
+        _ => {}
+    }
+    let matrix = match operand {
+        Typed::Matrix(matrix) => matrix,
+        Typed::Scalar(_) => return Err(EvalError::UnaryOperatorMisuse { op: operator, span }),
+    };
+    let not_square = EvalError::NotSquare { op: operator.to_string(), shape: (matrix.m, matrix.n), span };
+    match operator {
+        Operators::Transp => transpose(&matrix).map(Typed::Matrix).map_err(|_| not_square),
+        Operators::ConjTransp => conj_transpose(&matrix).map(Typed::Matrix).map_err(|_| not_square),
+        Operators::Det => det(&matrix).map(Typed::Scalar).map_err(|_| not_square),
+        Operators::Inv => {
+            if !matrix.is_squared() {
+                return Err(not_square);
+            }
+            inv(&matrix).map(Typed::Matrix).map_err(|e| EvalError::singular(e.as_ref(), matrix.n, span))
+        }
+        _ => Err(EvalError::InvalidOperands { op: operator, span }),
+    }
+}
 
-    for elem in postfix_exp {
-        // If operand
-        if let Some(num) = elem.trim().parse::<f32>().ok() {
-            stack.push(ExpTree::new(Operand::Scalar(num)))
-        } else if let Some(val) = definitions.0.get(*elem) {
-            if let Some(num) = val.as_scalar() {
-                stack.push(ExpTree::new(Operand::Scalar(*num)))
-            } else if let Some(mat) = val.as_matrix() {
-                stack.push(ExpTree::new(Operand::Matrix(mat)))
-            }
-        }
-        // If operator
-        else if let Some(operand) = OPERATIONS.get(elem) {
-            let mut node = ExpTree::new(Operand::Operation(*operand));
-            // If unary add one child to the left, otherwise add both
-            if let Some(is_unary) = UNARY_OPS.get(elem) {
-                if *is_unary {
-                    if let Some(left_child) = stack.pop() {
-                        node.set_left_op(left_child);
+fn solve_binary<T: Number>(operator: Operators, left: Typed<T>, right: Typed<T>, span: Span) -> Result<Typed<T>, EvalError> {
+    let mismatch = |l: &Matrix<T>, r: &Matrix<T>| EvalError::DimensionMismatch {
+        op: operator.to_string(),
+        left: (l.m, l.n),
+        right: (r.m, r.n),
+        span,
+    };
+
+    match (operator, left, right) {
+        // Left and right are scalars, left is matrix and the other scalar, the other way around, or both are matrices
+        (Operators::Mul, Typed::Scalar(l), Typed::Scalar(r)) => Ok(Typed::Scalar(l * r)),
+        (Operators::Mul, Typed::Scalar(l), Typed::Matrix(r)) => Ok(Typed::Matrix(mul_scalar(&r, l))),
+        (Operators::Mul, Typed::Matrix(l), Typed::Scalar(r)) => Ok(Typed::Matrix(mul_scalar(&l, r))),
+        (Operators::Mul, Typed::Matrix(l), Typed::Matrix(r)) => {
+            mul(&l, &r).map(Typed::Matrix).map_err(|_| mismatch(&l, &r))
+        }
+        // Left and right are scalars, both are matrices, or matrix divided by num
+        (Operators::Div, _, Typed::Scalar(r)) if r.is_zero() => Err(EvalError::DivisionByZero { span }),
+        (Operators::Div, Typed::Scalar(l), Typed::Scalar(r)) => Ok(Typed::Scalar(l / r)),
+        (Operators::Div, Typed::Matrix(l), Typed::Scalar(r)) => Ok(Typed::Matrix(mul_scalar(&l, T::one() / r))),
+        (Operators::Div, Typed::Matrix(l), Typed::Matrix(r)) => {
+            if !r.is_squared() {
+                return Err(EvalError::NotSquare { op: operator.to_string(), shape: (r.m, r.n), span });
+            }
+            let inverse = inv(&r).map_err(|e| EvalError::singular(e.as_ref(), r.n, span))?;
+            mul(&l, &inverse).map(Typed::Matrix).map_err(|_| mismatch(&l, &r))
+        }
+        // Element by element. A scalar, or a row or column vector, is repeated to match the other operand
+        (Operators::Sum, Typed::Scalar(l), Typed::Scalar(r)) => Ok(Typed::Scalar(l + r)),
+        (Operators::Sub, Typed::Scalar(l), Typed::Scalar(r)) => Ok(Typed::Scalar(l - r)),
+        (Operators::ElemMul, Typed::Scalar(l), Typed::Scalar(r)) => Ok(Typed::Scalar(l * r)),
+        (Operators::ElemDiv, _, ref r) if contains_zero(r) => Err(EvalError::DivisionByZero { span }),
+        (Operators::ElemDiv, Typed::Scalar(l), Typed::Scalar(r)) => Ok(Typed::Scalar(l / r)),
+        (Operators::ElemPow | Operators::Pow, Typed::Scalar(l), Typed::Scalar(r)) => {
+            if zero_to_negative(&l, &r) {
+                return Err(EvalError::DivisionByZero { span });
+            }
+            l.power(&r).map(Typed::Scalar).ok_or(EvalError::ExponentTooLarge { span })
+        }
+        (Operators::Sum | Operators::Sub | Operators::ElemMul | Operators::ElemDiv | Operators::ElemPow, l, r) => {
+            let (l, r) = (into_matrix(l), into_matrix(r));
+            let result = match operator {
+                Operators::Sum => sum(&l, &r),
+                Operators::Sub => sub(&l, &r),
+                Operators::ElemMul => elem_mul(&l, &r),
+                Operators::ElemDiv => elem_div(&l, &r),
+                _ => {
+                    let undefined = broadcast(&l, &r, |a, b| if zero_to_negative(a, b) { T::one() } else { T::zero() });
+                    if undefined.is_ok_and(|undefined| !contains_only_zeros(&undefined)) {
+                        return Err(EvalError::DivisionByZero { span });
                     }
+                    elem_pow(&l, &r)
                 }
-            } else {
-                if let Some(right_child) = stack.pop() {
-                    node.set_right_op(right_child);
-                } else {
-                    // panic!("Run out of stack")
-                    return None
-                }
-                if let Some(left_child) = stack.pop() {
-                    node.set_left_op(left_child);
+            };
+            result.map(Typed::Matrix).map_err(|error| {
+                if error.is::<PowerTooLarge>() {
+                    EvalError::ExponentTooLarge { span }
                 } else {
-                    // panic!("Run out of stack")
-                    return None
+                    mismatch(&l, &r)
                 }
-            }
-            stack.push(node);
-        } else {
-            return None;
-        }
-    }
-    if stack.len() > 0 {
-        if let Some(tree) = stack.pop() {
-            return Some(tree);
-        } else {
-            return None;
+            })
         }
+        // Left is a matrix and right an integer exponent
+        (Operators::Pow, Typed::Matrix(l), Typed::Scalar(r)) => matrix_power(operator, l, r.as_integer(), span),
+        (operator, _, _) => Err(EvalError::InvalidOperands { op: operator, span }),
     }
-    return None;
 }
 
-pub fn calculate(infix_exp: &str, definitions: &Definitions) -> Result<Value, Box<(dyn std::error::Error)>> {
-    if let Some(tree) = postfix_to_tree(&infix_to_postfix(&Vec::from_iter(infix_exp.split(' ').into_iter()), definitions), definitions) {
-        return tree.solve()
-    } else {
-        return Err("Parsing error")?;
+// `^` with a square matrix base and a non negative integer exponent
+fn matrix_power<T: Number>(operator: Operators, matrix: Matrix<T>, exp: Option<i64>, span: Span) -> Result<Typed<T>, EvalError> {
+    if !matrix.is_squared() {
+        return Err(EvalError::NotSquare { op: operator.to_string(), shape: (matrix.m, matrix.n), span });
+    }
+    match exp {
+        Some(exp) if exp >= 0 => match u32::try_from(exp) {
+            Ok(exp) => pow(&matrix, exp).map(Typed::Matrix).map_err(|_| EvalError::ExponentTooLarge { span }),
+            Err(_) => Err(EvalError::ExponentTooLarge { span }),
+        },
+        _ => Err(EvalError::InvalidExponent { span }),
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use std::collections::HashMap;
+// Powers of modular integers, whose exponents are plain integers. A negative exponent raises the inverse, which
+// zero does not have
+fn modular_power(operator: Operators, base: Typed<Modular>, exponent: Typed<i64>, span: Span) -> Result<Typed<Modular>, EvalError> {
+    match (operator, base, exponent) {
+        (_, Typed::Scalar(base), Typed::Scalar(exp)) => {
+            base.pow_int(exp).map(Typed::Scalar).ok_or(EvalError::DivisionByZero { span })
+        }
+        (Operators::Pow, Typed::Matrix(base), Typed::Scalar(exp)) => matrix_power(operator, base, Some(exp), span),
+        (Operators::ElemPow, base, exponent) => {
+            let (base, exponent) = (into_matrix(base), into_matrix(exponent));
+            let undefined = Cell::new(false);
+            let result = broadcast(&base, &exponent, |a, b| {
+                a.pow_int(*b).unwrap_or_else(|| {
+                    undefined.set(true);
+                    Modular::zero()
+                })
+            });
+            match result {
+                _ if undefined.get() => Err(EvalError::DivisionByZero { span }),
+                Ok(result) => Ok(Typed::Matrix(result)),
+                Err(_) => Err(EvalError::DimensionMismatch {
+                    op: operator.to_string(),
+                    left: (base.m, base.n),
+                    right: (exponent.m, exponent.n),
+                    span,
+                }),
+            }
+        }
+        (operator, _, _) => Err(EvalError::InvalidOperands { op: operator, span }),
+    }
+}
 
-    use super::*;
-    use crate::structs::Matrix;
+// Fractions raised to a fractional power are usually irrational, so those powers are solved with floats
+fn fractional_power(operator: Operators, exponent: &Typed<Rational>) -> bool {
+    matches!(operator, Operators::Pow | Operators::ElemPow) && exponent.elements().iter().any(|exp| !exp.is_integer())
+}
 
-    fn do_vecs_match<T: PartialEq>(a: &Vec<T>, b: &Vec<T>) -> bool {
-        let matching = a.iter().zip(b.iter()).filter(|&(a, b)| a == b).count();
-        matching == a.len() && matching == b.len()
+// Exponent of a modular power, without reducing it
+fn integer_exponent(exponent: Numbers) -> Option<Typed<i64>> {
+    match exponent {
+        Numbers::Exact(exponent) => exponent.try_map(Number::as_integer),
+        Numbers::Float(exponent) => exponent.try_map(Number::as_integer),
+        _ => None,
     }
+}
 
-    #[test]
-    fn test_infix_to_postfix() {
-        let infix: Vec<&str> = "2 + ( ( A + B ) * ( C ^ D ) T )".split(' ').collect();
-        let postfix: Vec<&str> = "2 A B + C D ^ T * +".split(' ').collect();
-
-        let definitions = Definitions(HashMap::from([
-            (String::from("A"), Value::Matrix(Matrix::new_empty(1, 1))),
-            (String::from("B"), Value::Matrix(Matrix::new_empty(1, 1))),
-            (String::from("C"), Value::Matrix(Matrix::new_empty(1, 1))),
-            (String::from("D"), Value::Scalar(2.0)),
-        ]));
+// `0 ^ -1` divides by zero
+fn zero_to_negative<T: Number>(base: &T, exp: &T) -> bool {
+    base.is_zero() && exp.as_integer().is_some_and(|exp| exp < 0)
+}
 
-        assert!(do_vecs_match(
-            &infix_to_postfix(&infix, &definitions),
-            &postfix
-        ));
+// Scalars take part in element-wise operations as 1x1 matrices
+fn into_matrix<T: Clone + Zero>(value: Typed<T>) -> Matrix<T> {
+    match value {
+        Typed::Scalar(num) => {
+            let mut matrix = Matrix::new_empty(1, 1);
+            matrix.set(0, 0, num);
+            matrix
+        }
+        Typed::Matrix(matrix) => matrix,
     }
+}
 
-    #[test]
-    fn test_postfix_to_tree() {
-        let postfix: Vec<&str> = "2 A B + C D ^ T * +".split(' ').collect();
+fn contains_zero<T: Number>(value: &Typed<T>) -> bool {
+    value.elements().iter().any(T::is_zero)
+}
 
-        let definitions = Definitions(HashMap::from([
-            (String::from("A"), Value::Matrix(Matrix::new_empty(1, 1))),
-            (String::from("B"), Value::Matrix(Matrix::new_empty(1, 1))),
-            (String::from("C"), Value::Matrix(Matrix::new_empty(1, 1))),
-            (String::from("D"), Value::Scalar(2.0)),
-        ]));
+fn contains_only_zeros<T: Number>(matrix: &Matrix<T>) -> bool {
+    (0..matrix.m).all(|i| matrix[i].iter().all(T::is_zero))
+}
 
-        let tree = postfix_to_tree(&postfix, &definitions).unwrap();
-        assert_eq!(tree.op().as_operation(), Some(&Operators::Sum));
-        assert_eq!(tree.left_op().as_ref().unwrap().op().as_scalar(), Some(&2.0));
-        assert_eq!(tree.right_op().as_ref().unwrap().op().as_operation(), Some(&Operators::Mul));
-        assert_eq!(tree.right_op().as_ref().unwrap().left_op().as_ref().unwrap().op().as_operation(), Some(&Operators::Sum));
-        // Me cansé, pero creo que anda bien
+fn from_elements<T: Clone + Zero>(rows: usize, cols: usize, elements: impl Iterator<Item = T>) -> Matrix<T> {
+    let mut matrix = Matrix::new_empty(rows, cols);
+    for (k, element) in elements.enumerate() {
+        matrix.set(k / cols, k % cols, element);
     }
+    matrix
+}
 
-    #[test]
-    fn test_solve() {
-        let expected = Matrix::new_from(2, 2, &[&[12.5, 6.5], &[23.25, 12.0]]).unwrap();
+pub static OPERATIONS: Map<&str, Operators> = phf_map! {
+    "+"   => Operators::Sum,
+    "-"   => Operators::Sub,
+    "/"   => Operators::Div,
+    "*"   => Operators::Mul,
+    "^"   => Operators::Pow,
+    ".*"  => Operators::ElemMul,
+    "./"  => Operators::ElemDiv,
+    ".^"  => Operators::ElemPow,
+    "INV" => Operators::Inv,
+    "T"   => Operators::Transp,
+    "H"   => Operators::ConjTransp,
+    "DET" => Operators::Det,
+};
 
-        let definitions = Definitions(HashMap::from([
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Associativity {
+    Left,
+    Right,
+}
+
+impl Operators {
+    /// Binding strength of the operator, from loosest to tightest:
+    ///
+    /// 1. Additive: `+`, `-`
+    /// 2. Multiplicative: `*`, `/`, `.*`, `./`
+    /// 3. Unary prefix: `-A`, `+A`. `-2 ^ 2` is `-(2 ^ 2)` but `-A * B` is `(-A) * B`
+    /// 4. Power: `^`, `.^`
+    /// 5. Postfix: `T`, `H`, `INV`, `DET`. `A ^ 2 T` is `A ^ (2 T)`, use parenthesis to transpose the power
+    fn precedence(&self) -> usize {
+        match self {
+            Operators::Sum | Operators::Sub => 1,
+            Operators::Mul | Operators::Div | Operators::ElemMul | Operators::ElemDiv => 2,
+            Operators::Neg | Operators::Pos => 3,
+            Operators::Pow | Operators::ElemPow => 4,
+            Operators::Transp | Operators::ConjTransp | Operators::Inv | Operators::Det => 5,
+        }
+    }
+
+    /// Order in which a chain of operators of the same precedence is grouped.
+    /// `A - B - C` is `(A - B) - C`, while `2 ^ 3 ^ 2` is `2 ^ (3 ^ 2)`
+    fn associativity(&self) -> Associativity {
+        match self {
+            Operators::Pow | Operators::ElemPow | Operators::Neg | Operators::Pos => Associativity::Right,
+            _ => Associativity::Left,
+        }
+    }
+
+    // Whether `self`, waiting in the operator stack, must be applied before `incoming`
+    fn binds_before(&self, incoming: &Operators) -> bool {
+        self.precedence() > incoming.precedence()
+            || (self.precedence() == incoming.precedence() && incoming.associativity() == Associativity::Left)
+    }
+
+    // All operations are binary unless specified here
+    fn is_unary(&self) -> bool {
+        matches!(
+            self,
+            Operators::Inv | Operators::Transp | Operators::ConjTransp | Operators::Det | Operators::Neg | Operators::Pos
+        )
+    }
+
+    // Unary operators written before their operand. The rest of unary operators go after it
+    fn is_prefix(&self) -> bool {
+        matches!(self, Operators::Neg | Operators::Pos)
+    }
+
+    // Sign version of `+` and `-`, used when they appear where an operand is expected
+    fn as_prefix(&self) -> Option<Operators> {
+        match self {
+            Operators::Sub => Some(Operators::Neg),
+            Operators::Sum => Some(Operators::Pos),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Operators {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Show the operator as it is written in expressions
+        match self {
+            Operators::Neg => return write!(f, "-"),
+            Operators::Pos => return write!(f, "+"),
+            _ => {}
+        }
+        match OPERATIONS.entries().find(|(_, op)| *op == self) {
+            Some((symbol, _)) => write!(f, "{}", symbol),
+            None => write!(f, "{:?}", self),
+        }
+    }
+}
+
+// Struct that holds the currently declared variables 
+pub struct Definitions(pub HashMap<String, Value>);
+
+// Function declared with `func NAME(params) = expr`
+#[derive(Debug, Clone)]
+pub struct UserFunction {
+    pub params: Vec<String>,
+    // Body as written by the user, to show it back
+    pub body: String,
+    // Body already converted to postfix. Its spans point into the whole definition
+    postfix: Vec<Token>,
+}
+
+// Struct that holds the functions declared by the user
+#[derive(Default)]
+pub struct UserFunctions(pub HashMap<String, UserFunction>);
+
+// Nested user function calls allowed before giving up, so `f(X) = f(X)` fails instead of overflowing the stack
+pub const MAX_CALL_DEPTH: usize = 64;
+
+// Names visible while building and solving a tree. Inside a user function its parameters hide the
+// global variables with the same name, and the caller's parameters are not visible at all
+#[derive(Clone, Copy)]
+struct Scope<'a> {
+    definitions: &'a Definitions,
+    functions: &'a UserFunctions,
+    locals: Option<&'a Definitions>,
+    depth: usize,
+    mode: Mode,
+}
+
+impl<'a> Scope<'a> {
+    fn new(definitions: &'a Definitions, functions: &'a UserFunctions, mode: Mode) -> Scope<'a> {
+        Scope { definitions, functions, locals: None, depth: 0, mode }
+    }
+
+    fn lookup(&self, name: &str) -> Option<&'a Value> {
+        self.locals.and_then(|locals| locals.0.get(name)).or_else(|| self.definitions.0.get(name))
+    }
+}
+
+fn ends_operand(token: &Token) -> bool {
+    match token.kind {
+        TokenKind::Number(_) | TokenKind::Imaginary(_) | TokenKind::Ident(_) | TokenKind::RParen | TokenKind::RBracket => true,
+        TokenKind::Operator(op) => op.is_unary() && !op.is_prefix(),
+        _ => false,
+    }
+}
+
+fn starts_operand(token: &Token, next: Option<&Token>) -> bool {
+    match token.kind {
+        TokenKind::Number(_) | TokenKind::Imaginary(_) | TokenKind::Ident(_) | TokenKind::LParen | TokenKind::LBracket => true,
+        // A sign glued to what follows, as in `[1 -2]`. `[1 - 2]` is a subtraction
+        TokenKind::Operator(op) => {
+            op.as_prefix().is_some() && next.is_some_and(|next| next.span.start == token.span.end)
+        }
+        _ => false,
+    }
+}
+
+// Inside `[...]` elements may be separated only by spaces: `[1 2; 3 4]`. Make those separators explicit commas
+fn insert_element_separators(tokens: &[Token]) -> Vec<Token> {
+    let mut result: Vec<Token> = Vec::new();
+    // Innermost group is a matrix literal (`true`) or a parenthesis or index (`false`)
+    let mut groups: Vec<bool> = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        if let (Some(true), Some(prev)) = (groups.last(), result.last()) {
+            let spaced = prev.span.end < token.span.start;
+            if spaced && ends_operand(prev) && starts_operand(token, tokens.get(i + 1)) {
+                result.push(Token::new(TokenKind::Comma, prev.span.end, token.span.start));
+            }
+        }
+        match token.kind {
+            // After an operand `[` is an index, not a matrix
+            TokenKind::LBracket => groups.push(!result.last().is_some_and(ends_operand)),
+            TokenKind::LParen => groups.push(false),
+            TokenKind::RBracket | TokenKind::RParen => {
+                groups.pop();
+            }
+            _ => {}
+        }
+        result.push(token.clone());
+    }
+    result
+}
+
+// State of a matrix literal while its elements are being read
+struct MatrixFrame {
+    start: usize,
+    row_lengths: Vec<usize>,
+    current_row: usize,
+    element_started: bool,
+}
+
+impl MatrixFrame {
+    fn new(start: usize) -> MatrixFrame {
+        MatrixFrame { start, row_lengths: Vec::new(), current_row: 0, element_started: false }
+    }
+
+    fn end_element(&mut self, separator: Span) -> Result<(), EvalError> {
+        if !self.element_started {
+            return Err(EvalError::UnexpectedToken { span: separator });
+        }
+        self.current_row += 1;
+        self.element_started = false;
+        Ok(())
+    }
+
+    fn end_row(&mut self, separator: Span) -> Result<(), EvalError> {
+        if self.current_row == 0 {
+            return Err(EvalError::UnexpectedToken { span: separator });
+        }
+        self.row_lengths.push(self.current_row);
+        self.current_row = 0;
+        Ok(())
+    }
+
+    // Checks that every row has the same length and returns the token that builds the matrix
+    fn finish(mut self, end: Span) -> Result<Token, EvalError> {
+        let span = Span::new(self.start, end.end);
+        if self.element_started {
+            self.end_element(end)?;
+        }
+        // A trailing `;` before `]` is allowed
+        if self.current_row > 0 {
+            self.end_row(end)?;
+        }
+        let cols = match self.row_lengths.first() {
+            Some(cols) => *cols,
+            None => return Err(EvalError::EmptyMatrix { span }),
+        };
+        if self.row_lengths.iter().any(|len| *len != cols) {
+            return Err(EvalError::RaggedMatrix { span });
+        }
+        Ok(Token { kind: TokenKind::MatrixLiteral { rows: self.row_lengths.len(), cols }, span })
+    }
+}
+
+// State of a function call while its arguments are being read
+struct CallFrame {
+    name: Token,
+    argc: usize,
+    arg_started: bool,
+}
+
+impl CallFrame {
+    fn end_arg(&mut self, separator: Span) -> Result<(), EvalError> {
+        if !self.arg_started {
+            return Err(EvalError::UnexpectedToken { span: separator });
+        }
+        self.argc += 1;
+        self.arg_started = false;
+        Ok(())
+    }
+
+    fn finish(mut self, end: Span) -> Result<Token, EvalError> {
+        // `f()` has no arguments, but `f(1,)` is missing one
+        if self.arg_started || self.argc > 0 {
+            self.end_arg(end)?;
+        }
+        let name = match self.name.kind {
+            TokenKind::Ident(name) => name,
+            _ => return Err(EvalError::UnexpectedToken { span: self.name.span }),
+        };
+        Ok(Token {
+            kind: TokenKind::Call { name, argc: self.argc },
+            span: Span::new(self.name.span.start, end.end),
+        })
+    }
+}
+
+// State of an index `A[...]` while its positions are being read
+struct IndexFrame {
+    start: usize,
+    argc: usize,
+    arg_started: bool,
+    // The current position already has its `:`
+    range: bool,
+}
+
+impl IndexFrame {
+    fn new(start: usize) -> IndexFrame {
+        IndexFrame { start, argc: 0, arg_started: false, range: false }
+    }
+
+    fn end_arg(&mut self, separator: Span) -> Result<(), EvalError> {
+        if !self.arg_started {
+            return Err(EvalError::UnexpectedToken { span: separator });
+        }
+        self.argc += 1;
+        self.arg_started = false;
+        self.range = false;
+        Ok(())
+    }
+
+    fn finish(mut self, end: Span) -> Result<Token, EvalError> {
+        let span = Span::new(self.start, end.end);
+        if self.arg_started || self.argc > 0 {
+            self.end_arg(end)?;
+        }
+        if self.argc != 2 {
+            return Err(EvalError::IndexCount { found: self.argc, span });
+        }
+        Ok(Token { kind: TokenKind::Index, span })
+    }
+}
+
+// Parenthesis or bracket that is still open while reading the expression
+enum Group {
+    Paren,
+    Call(CallFrame),
+    Matrix(MatrixFrame),
+    Index(IndexFrame),
+}
+
+impl Group {
+    // A token that is not a separator was read inside this group
+    fn mark_started(&mut self) {
+        match self {
+            Group::Paren => {}
+            Group::Call(call) => call.arg_started = true,
+            Group::Matrix(matrix) => matrix.element_started = true,
+            Group::Index(index) => index.arg_started = true,
+        }
+    }
+}
+
+// Moves operators to the output until the `(` or `[` that opened the current group, which stays in the stack
+fn pop_until_group(stack: &mut Vec<Token>, postfix: &mut Vec<Token>) {
+    while let Some(top) = stack.last() {
+        if matches!(top.kind, TokenKind::LParen | TokenKind::LBracket) {
+            break;
+        }
+        postfix.push(stack.pop().unwrap());
+    }
+}
+
+fn infix_to_postfix(infix_exp: &[Token]) -> Result<Vec<Token>, EvalError> {
+    let mut stack: Vec<Token> = Vec::new();
+    let mut groups: Vec<Group> = Vec::new();
+    // Name of the function whose `(` comes next
+    let mut pending_call: Option<Token> = None;
+
+    let mut postfix: Vec<Token> = Vec::new();
+    // Whether the next token should be an operand. `+` and `-` found there are signs
+    let mut expect_operand = true;
+    let tokens = insert_element_separators(infix_exp);
+    for (i, elem) in tokens.iter().enumerate() {
+        let mut elem = elem.clone();
+        if let TokenKind::Operator(op) = elem.kind {
+            if let (true, Some(sign)) = (expect_operand, op.as_prefix()) {
+                elem.kind = TokenKind::Operator(sign);
+            }
+        }
+        let was_expecting_operand = expect_operand;
+        expect_operand = match elem.kind {
+            TokenKind::LParen | TokenKind::LBracket | TokenKind::Comma | TokenKind::Semicolon | TokenKind::Colon => true,
+            TokenKind::Operator(op) => !op.is_unary() || op.is_prefix(),
+            _ => false,
+        };
+        // Everything but the separators is part of the current element or argument of the innermost group
+        if !matches!(elem.kind, TokenKind::Comma | TokenKind::Semicolon | TokenKind::RBracket | TokenKind::RParen) {
+            if let Some(group) = groups.last_mut() {
+                group.mark_started();
+            }
+        }
+        // A range that ends at a separator has no upper bound, as in `A[1:, :]`
+        if matches!(elem.kind, TokenKind::Comma | TokenKind::RBracket)
+            && i > 0
+            && tokens[i - 1].kind == TokenKind::Colon
+        {
+            postfix.push(Token::new(TokenKind::OpenBound, elem.span.start, elem.span.start));
+        }
+
+        match &elem.kind {
+            TokenKind::LParen => {
+                match pending_call.take() {
+                    Some(name) => groups.push(Group::Call(CallFrame { name, argc: 0, arg_started: false })),
+                    None => groups.push(Group::Paren),
+                }
+                stack.push(elem);
+            }
+            TokenKind::RParen => {
+                pop_until_group(&mut stack, &mut postfix);
+                match stack.pop() {
+                    Some(open) if open.kind == TokenKind::LParen => {}
+                    Some(open) => return Err(EvalError::UnbalancedBracket { span: open.span }),
+                    None => return Err(EvalError::UnbalancedParenthesis { span: elem.span }),
+                }
+                match groups.pop() {
+                    Some(Group::Call(call)) => postfix.push(call.finish(elem.span)?),
+                    // `()` groups nothing, so it can't be an operand or an argument
+                    Some(Group::Paren) if tokens[i - 1].kind == TokenKind::LParen => {
+                        return Err(EvalError::UnexpectedToken { span: tokens[i - 1].span })
+                    }
+                    _ => {}
+                }
+            }
+            TokenKind::LBracket => {
+                if was_expecting_operand {
+                    groups.push(Group::Matrix(MatrixFrame::new(elem.span.start)));
+                } else {
+                    // After an operand `[` indexes it. Postfix operators already read apply first, as in `A T[0, 1]`
+                    while stack.last().and_then(Token::as_operator).is_some_and(|op| op.is_unary() && !op.is_prefix()) {
+                        postfix.push(stack.pop().unwrap());
+                    }
+                    groups.push(Group::Index(IndexFrame::new(elem.span.start)));
+                }
+                stack.push(elem);
+            }
+            TokenKind::RBracket => {
+                pop_until_group(&mut stack, &mut postfix);
+                match stack.pop() {
+                    Some(open) if open.kind == TokenKind::LBracket => {}
+                    Some(open) => return Err(EvalError::UnbalancedParenthesis { span: open.span }),
+                    None => return Err(EvalError::UnbalancedBracket { span: elem.span }),
+                }
+                match groups.pop() {
+                    Some(Group::Matrix(matrix)) => postfix.push(matrix.finish(elem.span)?),
+                    Some(Group::Index(index)) => postfix.push(index.finish(elem.span)?),
+                    _ => {}
+                }
+            }
+            TokenKind::Comma | TokenKind::Semicolon => {
+                pop_until_group(&mut stack, &mut postfix);
+                match (groups.last_mut(), &elem.kind) {
+                    (Some(Group::Call(call)), TokenKind::Comma) => call.end_arg(elem.span)?,
+                    (Some(Group::Matrix(matrix)), TokenKind::Comma) => matrix.end_element(elem.span)?,
+                    (Some(Group::Index(index)), TokenKind::Comma) => index.end_arg(elem.span)?,
+                    (Some(Group::Matrix(matrix)), _) => {
+                        matrix.end_element(elem.span)?;
+                        matrix.end_row(elem.span)?;
+                    }
+                    _ => return Err(EvalError::UnexpectedToken { span: elem.span }),
+                }
+            }
+            // `:` has the lowest precedence and is only valid once in each position of an index
+            TokenKind::Colon => {
+                match groups.last_mut() {
+                    Some(Group::Index(index)) if !index.range => index.range = true,
+                    _ => return Err(EvalError::UnexpectedToken { span: elem.span }),
+                }
+                pop_until_group(&mut stack, &mut postfix);
+                // A range that starts at the separator has no lower bound, as in `A[:2, 0]`
+                if matches!(tokens[i - 1].kind, TokenKind::LBracket | TokenKind::Comma) {
+                    postfix.push(Token::new(TokenKind::OpenBound, elem.span.start, elem.span.start));
+                }
+                stack.push(elem);
+            }
+            // An identifier followed by `(` is a function name
+            TokenKind::Ident(_) if tokens.get(i + 1).is_some_and(|next| next.kind == TokenKind::LParen) => {
+                pending_call = Some(elem);
+            }
+            // Numbers and identifiers go straight to the output
+            TokenKind::Number(_) | TokenKind::Imaginary(_) | TokenKind::Ident(_) => postfix.push(elem),
+            // Prefix operators don't pop anything, their operand hasn't been read yet
+            TokenKind::Operator(op) if op.is_prefix() => stack.push(elem),
+            TokenKind::Operator(op) => {
+                // Pop all other operators and push to output (except parenthesis)
+                while let Some(_elem) = stack.pop() {
+                    match _elem.as_operator() {
+                        Some(top) if top.binds_before(op) => postfix.push(_elem),
+                        _ => {
+                            stack.push(_elem);
+                            break;
+                        }
+                    }
+                }
+                stack.push(elem);
+            }
+            TokenKind::MatrixLiteral { .. } | TokenKind::Call { .. } | TokenKind::Index | TokenKind::OpenBound => {
+                return Err(EvalError::UnexpectedToken { span: elem.span })
+            }
+        }
+    }
+    // Finish sending stack to output
+    while let Some(_elem) = stack.pop() {
+        match _elem.kind {
+            TokenKind::LParen => return Err(EvalError::UnbalancedParenthesis { span: _elem.span }),
+            TokenKind::LBracket => return Err(EvalError::UnbalancedBracket { span: _elem.span }),
+            _ => postfix.push(_elem),
+        }
+    }
+    Ok(postfix)
+}
+
+fn postfix_to_tree<'a>(
+    postfix_exp: &[Token],
+    scope: &Scope<'a>
+) -> Result<ExpTree<'a>, EvalError> {
+    let mut stack: Vec<ExpTree> = Vec::new();
+
+    for elem in postfix_exp {
+        match &elem.kind {
+            // If operand
+            TokenKind::Number(num) => stack.push(ExpTree::new(Operand::Scalar(*num), elem.span)),
+            TokenKind::Imaginary(num) => stack.push(ExpTree::new(Operand::Imaginary(*num), elem.span)),
+            TokenKind::Ident(name) => match scope.lookup(name) {
+                Some(value) => stack.push(ExpTree::new(Operand::Variable(value), elem.span)),
+                None => return Err(EvalError::UnknownIdentifier { name: name.clone(), span: elem.span }),
+            },
+            // If operator
+            TokenKind::Operator(operator) => {
+                let missing = EvalError::MissingOperand { op: *operator, span: elem.span };
+                let mut node = ExpTree::new(Operand::Operation(*operator), elem.span);
+                // If unary add one child to the left, otherwise add both
+                if operator.is_unary() {
+                    node.set_left_op(stack.pop().ok_or(missing)?);
+                } else {
+                    node.set_right_op(stack.pop().ok_or(missing.clone())?);
+                    node.set_left_op(stack.pop().ok_or(missing)?);
+                }
+                stack.push(node);
+            }
+            TokenKind::MatrixLiteral { rows, cols } => {
+                let count = rows * cols;
+                if stack.len() < count {
+                    return Err(EvalError::EmptyMatrix { span: elem.span });
+                }
+                let mut node = ExpTree::new(Operand::MatrixLiteral(*rows, *cols), elem.span);
+                node.set_args(stack.split_off(stack.len() - count));
+                stack.push(node);
+            }
+            TokenKind::Call { name, argc } => {
+                let name_span = Span::new(elem.span.start, elem.span.start + name.chars().count());
+                // Predefined functions first, user functions can't take their names
+                let (operand, (min, max)) = match FUNCTIONS.get(name.as_str()) {
+                    Some(function) => (Operand::Function(*function), function.arity()),
+                    None => match scope.functions.0.get_key_value(name) {
+                        Some((name, function)) => {
+                            (Operand::UserFunction(name, function), (function.params.len(), function.params.len()))
+                        }
+                        None => return Err(EvalError::UnknownFunction { name: name.clone(), span: name_span }),
+                    },
+                };
+                if *argc < min || *argc > max {
+                    return Err(EvalError::WrongArgumentCount { name: name.clone(), min, max, found: *argc, span: elem.span });
+                }
+                if stack.len() < *argc {
+                    return Err(EvalError::UnexpectedToken { span: elem.span });
+                }
+                let mut node = ExpTree::new(operand, elem.span);
+                node.set_args(stack.split_off(stack.len() - argc));
+                stack.push(node);
+            }
+            TokenKind::OpenBound => stack.push(ExpTree::new(Operand::OpenBound, elem.span)),
+            TokenKind::Colon => {
+                let missing = EvalError::UnexpectedToken { span: elem.span };
+                let to = stack.pop().ok_or(missing.clone())?;
+                let from = stack.pop().ok_or(missing)?;
+                let mut node = ExpTree::new(Operand::Range, elem.span);
+                if !matches!(from.op(), Operand::OpenBound) {
+                    node.set_left_op(from);
+                }
+                if !matches!(to.op(), Operand::OpenBound) {
+                    node.set_right_op(to);
+                }
+                stack.push(node);
+            }
+            TokenKind::Index => {
+                if stack.len() < 3 {
+                    return Err(EvalError::UnexpectedToken { span: elem.span });
+                }
+                let mut node = ExpTree::new(Operand::Index, elem.span);
+                node.set_args(stack.split_off(stack.len() - 3));
+                stack.push(node);
+            }
+            _ => return Err(EvalError::UnexpectedToken { span: elem.span }),
+        }
+    }
+    // Anything left below the root is an operand without an operator
+    if stack.len() > 1 {
+        return Err(EvalError::MissingOperator { span: stack[1].span() });
+    }
+    stack.pop().ok_or(EvalError::EmptyExpression)
+}
+
+pub fn calculate(
+    infix_exp: &str,
+    definitions: &Definitions,
+    functions: &UserFunctions,
+    mode: Mode,
+) -> Result<Value, EvalError> {
+    evaluate(&tokenize(infix_exp)?, &Scope::new(definitions, functions, mode))
+}
+
+fn evaluate(tokens: &[Token], scope: &Scope) -> Result<Value, EvalError> {
+    let tree = postfix_to_tree(&infix_to_postfix(tokens)?, scope)?;
+    tree.solve(scope)
+}
+
+// Tokens read from the text after `=` keep spans that point into the whole input
+fn tokenize_after(input: &str, offset: usize) -> Result<Vec<Token>, EvalError> {
+    let mut tokens = tokenize(input)?;
+    for token in &mut tokens {
+        token.span = Span::new(token.span.start + offset, token.span.end + offset);
+    }
+    Ok(tokens)
+}
+
+// `A[1, :] = [5 6]`: replaces part of the stored matrix `A`. A scalar is copied to every selected element.
+// Returns the name and the whole new value
+pub fn assign_slice(
+    input: &str,
+    definitions: &mut Definitions,
+    functions: &UserFunctions,
+    mode: Mode,
+) -> Result<(String, Value), EvalError> {
+    let (target, expression) = match input.split_once('=') {
+        Some(parts) => parts,
+        None => return Err(EvalError::InvalidTarget { span: Span::new(0, input.chars().count()) }),
+    };
+    let equals = Span::new(target.chars().count(), target.chars().count() + 1);
+    let target_tokens = tokenize(target)?;
+    let postfix = infix_to_postfix(&target_tokens)?;
+    // Only `NAME[rows, cols]` is accepted, not `A T[0, 0]` nor `A[0, :][0, 0]`
+    let name = match (target_tokens.first(), target_tokens.get(1), postfix.last()) {
+        (Some(Token { kind: TokenKind::Ident(name), .. }), Some(open), Some(index))
+            if open.kind == TokenKind::LBracket
+                && index.kind == TokenKind::Index
+                && index.span == Span::new(open.span.start, target_tokens[target_tokens.len() - 1].span.end) =>
+        {
+            name.clone()
+        }
+        _ => return Err(EvalError::InvalidTarget { span: Span::new(0, equals.start) }),
+    };
+
+    let scope = Scope::new(definitions, functions, mode);
+    let target = match scope.lookup(&name) {
+        Some(value) => value.clone().numbers(),
+        None => return Err(EvalError::UnknownIdentifier { name, span: target_tokens[0].span }),
+    };
+    let (m, n) = match &target {
+        Numbers::Exact(Typed::Matrix(matrix)) => (matrix.m, matrix.n),
+        Numbers::Modular(Typed::Matrix(matrix)) => (matrix.m, matrix.n),
+        Numbers::Float(Typed::Matrix(matrix)) => (matrix.m, matrix.n),
+        Numbers::Complex(Typed::Matrix(matrix)) => (matrix.m, matrix.n),
+        _ => return Err(EvalError::NotIndexable { span: target_tokens[0].span }),
+    };
+    let tree = postfix_to_tree(&postfix, &scope)?;
+    let (rows, _) = tree.args()[1].solve_selection(m, &scope)?;
+    let (cols, _) = tree.args()[2].solve_selection(n, &scope)?;
+    let value = evaluate(&tokenize_after(expression, equals.end)?, &scope)?;
+
+    // Like in the operations, an inexact value makes the whole matrix inexact, and a complex one complex
+    let result = match (target, value.numbers()) {
+        (Numbers::Exact(target), Numbers::Exact(value)) => replace(target, value, rows, cols, equals).map(Value::from),
+        (Numbers::Modular(target), Numbers::Modular(value)) => replace(target, value, rows, cols, equals).map(Value::from),
+        (target @ Numbers::Complex(_), value) | (target, value @ Numbers::Complex(_)) => {
+            replace(target.into_complex(), value.into_complex(), rows, cols, equals).map(Value::from)
+        }
+        (target, value) => replace(target.into_float(), value.into_float(), rows, cols, equals).map(Value::from),
+    }?;
+    definitions.0.insert(name.clone(), result.clone());
+    Ok((name, result))
+}
+
+fn replace<T: Number>(
+    target: Typed<T>,
+    value: Typed<T>,
+    rows: Range<usize>,
+    cols: Range<usize>,
+    equals: Span,
+) -> Result<Typed<T>, EvalError> {
+    let mut matrix = into_matrix(target);
+    let block = match value {
+        Typed::Matrix(block) => block,
+        Typed::Scalar(num) => {
+            let mut block = Matrix::new_empty(rows.len(), cols.len());
+            block.fill(num);
+            block
+        }
+    };
+    if (block.m, block.n) != (rows.len(), cols.len()) {
+        return Err(EvalError::DimensionMismatch {
+            op: String::from("="),
+            left: (rows.len(), cols.len()),
+            right: (block.m, block.n),
+            span: equals,
+        });
+    }
+    set_submatrix(&mut matrix, rows.start, cols.start, &block).map_err(|_| EvalError::InvalidTarget { span: equals })?;
+    Ok(Typed::Matrix(matrix))
+}
+
+// Reads `NAME(P1, P2) = expr`. The body is only checked for syntax errors, the names it uses are
+// looked up on each call, so it may refer to variables and functions declared later
+pub fn define_function(definition: &str) -> Result<(String, UserFunction), EvalError> {
+    let (header, body) = match definition.split_once('=') {
+        Some(parts) => parts,
+        None => return Err(EvalError::InvalidDefinition { span: Span::new(0, definition.chars().count()) }),
+    };
+    let header_tokens = tokenize(header)?;
+    let mut params = parse_header(&header_tokens, header.chars().count())?;
+    let name = params.remove(0);
+    if FUNCTIONS.get(name.0.as_str()).is_some() {
+        return Err(EvalError::ReservedFunction { name: name.0, span: name.1 });
+    }
+    for (i, (param, span)) in params.iter().enumerate() {
+        if params[..i].iter().any(|(other, _)| other == param) {
+            return Err(EvalError::DuplicateParameter { name: param.clone(), span: *span });
+        }
+    }
+
+    let postfix = infix_to_postfix(&tokenize_after(body, header.chars().count() + 1)?)?;
+    if postfix.is_empty() {
+        return Err(EvalError::EmptyExpression);
+    }
+
+    let function = UserFunction {
+        params: params.into_iter().map(|(param, _)| param).collect(),
+        body: body.trim().to_string(),
+        postfix,
+    };
+    Ok((name.0, function))
+}
+
+// `NAME(P1, P2)` or `NAME()`. Returns the name followed by the parameters, with their spans
+fn parse_header(tokens: &[Token], end: usize) -> Result<Vec<(String, Span)>, EvalError> {
+    let invalid = |i: usize| EvalError::InvalidDefinition {
+        span: tokens.get(i).map_or(Span::new(end, end + 1), |token| token.span),
+    };
+    let kind = |i: usize| tokens.get(i).map(|token| &token.kind);
+    let ident = |i: usize| match kind(i) {
+        Some(TokenKind::Ident(name)) => Ok((name.clone(), tokens[i].span)),
+        _ => Err(invalid(i)),
+    };
+
+    let mut names = vec![ident(0)?];
+    if kind(1) != Some(&TokenKind::LParen) {
+        return Err(invalid(1));
+    }
+    let mut i = 2;
+    if kind(i) != Some(&TokenKind::RParen) {
+        loop {
+            names.push(ident(i)?);
+            match kind(i + 1) {
+                Some(TokenKind::Comma) => i += 2,
+                Some(TokenKind::RParen) => {
+                    i += 1;
+                    break;
+                }
+                _ => return Err(invalid(i + 1)),
+            }
+        }
+    }
+    if i + 1 < tokens.len() {
+        return Err(invalid(i + 1));
+    }
+    Ok(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::structs::Matrix;
+
+    fn do_vecs_match<T: PartialEq>(a: &Vec<T>, b: &Vec<T>) -> bool {
+        let matching = a.iter().zip(b.iter()).filter(|&(a, b)| a == b).count();
+        matching == a.len() && matching == b.len()
+    }
+
+    fn kinds(tokens: &[Token]) -> Vec<TokenKind> {
+        tokens.iter().map(|t| t.kind.clone()).collect()
+    }
+
+    // Variables shared by the tests. `extra` adds variables or replaces the shared ones
+    fn fixture(extra: &[(&str, Value)]) -> Definitions {
+        let mut definitions = Definitions(HashMap::from([
             (String::from("A"), Value::Matrix(Matrix::new_from(2, 2, &[&[1.0, 2.0], &[3.0, 4.0]]).unwrap())),
             (String::from("B"), Value::Matrix(Matrix::new_from(2, 2, &[&[3.0, 4.0], &[5.0, 6.0]]).unwrap())),
             (String::from("C"), Value::Matrix(Matrix::new_from(2, 2, &[&[1.25, 0.5], &[0.5, 0.5]]).unwrap())),
+            (String::from("S"), Value::Matrix(Matrix::new_from(2, 2, &[&[1.0, 2.0], &[2.0, 4.0]]).unwrap())),
+            (String::from("V"), Value::Matrix(Matrix::new_from(3, 1, &[&[1.0], &[2.0], &[3.0]]).unwrap())),
             (String::from("D"), Value::Scalar(2.0)),
         ]));
+        for (name, value) in extra {
+            definitions.0.insert(name.to_string(), value.clone());
+        }
+        definitions
+    }
+
+    // Parses each infix expression with the shared variables and compares the fully parenthesized tree
+    fn check_cases(functions: &UserFunctions, cases: &[(&str, &str)]) {
+        let definitions = fixture(&[]);
+        let scope = Scope::new(&definitions, functions, Mode::Decimal);
+        for (infix, expected) in cases {
+            let postfix = infix_to_postfix(&tokenize(infix).unwrap()).unwrap();
+            let tree = postfix_to_tree(&postfix, &scope).unwrap();
+            assert_eq!(render(&tree), *expected, "parsing `{}`", infix);
+        }
+    }
+
+    #[test]
+    fn test_infix_to_postfix() {
+        let infix = tokenize("2 + ( ( A + B ) * ( C ^ D ) T )").unwrap();
+        let postfix = tokenize("2 A B + C D ^ T * +").unwrap();
+
+        assert!(do_vecs_match(
+            &kinds(&infix_to_postfix(&infix).unwrap()),
+            &kinds(&postfix)
+        ));
+
+        // Spacing doesn't change the result
+        let infix = tokenize("2+((A+B)*(C^D)T)").unwrap();
+        assert!(do_vecs_match(
+            &kinds(&infix_to_postfix(&infix).unwrap()),
+            &kinds(&postfix)
+        ));
+    }
+
+    #[test]
+    fn test_postfix_to_tree() {
+        let postfix = tokenize("2 A B + C D ^ T * +").unwrap();
+
+        let definitions = fixture(&[]);
+        let functions = UserFunctions::default();
+
+        let tree = postfix_to_tree(&postfix, &Scope::new(&definitions, &functions, Mode::Decimal)).unwrap();
+        assert_eq!(tree.op().as_operation(), Some(&Operators::Sum));
+        assert_eq!(tree.left_op().as_ref().unwrap().op().as_scalar(), Some(&2.0));
+        assert_eq!(tree.right_op().as_ref().unwrap().op().as_operation(), Some(&Operators::Mul));
+        assert_eq!(tree.right_op().as_ref().unwrap().left_op().as_ref().unwrap().op().as_operation(), Some(&Operators::Sum));
+        // Me cansé, pero creo que anda bien
+    }
+
+    #[test]
+    fn test_solve() {
+        let expected = Matrix::new_from(2, 2, &[&[12.5, 6.5], &[23.25, 12.0]]).unwrap();
+
+        let definitions = fixture(&[]);
+        let functions = UserFunctions::default();
         
         // Power
-        assert!(*calculate("4 ^ 3", &definitions).unwrap().as_scalar().unwrap() == 64.0);
+        assert!(*calculate("4 ^ 3", &definitions, &functions, Mode::Decimal).unwrap().as_scalar().unwrap() == 64.0);
         
         // A complex expression
         let infix_exp = "( A + B ) * ( C ^ D ) T";
-        let result = calculate(infix_exp, &definitions).unwrap();
+        let result = calculate(infix_exp, &definitions, &functions, Mode::Decimal).unwrap();
         let matrix = result.as_matrix().unwrap();
         assert!(matrix.equals(&expected));
 
         // With determinant
         let infix_exp = "( C ^ D ) T DET";
         let expected = 9.0/64.0;
-        let result = *calculate(infix_exp, &definitions).unwrap().as_scalar().unwrap();
-        assert!(result == expected);
+        let result = *calculate(infix_exp, &definitions, &functions, Mode::Decimal).unwrap().as_scalar().unwrap();
+        assert!(result.approx_eq(&expected));
 
         // Even more complex
         let infix_exp = "( ( A + B ) * ( C ^ D ) T ) DET";
         let expected = -9.0/8.0;
-        let result = *calculate(infix_exp, &definitions).unwrap().as_scalar().unwrap();
-        assert!(result == expected);
+        let result = *calculate(infix_exp, &definitions, &functions, Mode::Decimal).unwrap().as_scalar().unwrap();
+        assert!(result.approx_eq(&expected));
+
+        // Without spaces
+        let expected = Matrix::new_from(2, 2, &[&[12.5, 6.5], &[23.25, 12.0]]).unwrap();
+        let result = calculate("(A+B)*(C^D)T", &definitions, &functions, Mode::Decimal).unwrap();
+        assert!(result.as_matrix().unwrap().equals(&expected));
+        assert!(*calculate("2*D", &definitions, &functions, Mode::Decimal).unwrap().as_scalar().unwrap() == 4.0);
+        assert!(*calculate("1e1  +  D", &definitions, &functions, Mode::Decimal).unwrap().as_scalar().unwrap() == 12.0);
 
         // With inverse
         let infix_exp = "( A ^ D ) INV";
         let expected = Matrix::new_from(2, 2, &[&[5.5, -2.5], &[-3.75, 1.75]]).unwrap();
-        let result = calculate(infix_exp, &definitions).unwrap();
+        let result = calculate(infix_exp, &definitions, &functions, Mode::Decimal).unwrap();
         let result = result.as_matrix().unwrap();
         assert!(result.equals(&expected));
 
         // Division
-        assert!(*calculate("4 / 2", &definitions).unwrap().as_scalar().unwrap() == 2.0);
+        assert!(*calculate("4 / 2", &definitions, &functions, Mode::Decimal).unwrap().as_scalar().unwrap() == 2.0);
         let infix_exp = "A / B";
         let expected = Matrix::new_from(2, 2, &[&[2.0, -1.0], &[1.0, 0.0]]).unwrap();
-        let result = calculate(infix_exp, &definitions).unwrap();
+        let result = calculate(infix_exp, &definitions, &functions, Mode::Decimal).unwrap();
         let result = result.as_matrix().unwrap();
         assert!(result.equals(&expected));
     }
+
+    #[test]
+    fn test_errors() {
+        let definitions = fixture(&[]);
+        let functions = UserFunctions::default();
+
+        assert_eq!(
+            calculate("A + X", &definitions, &functions, Mode::Decimal).unwrap_err(),
+            EvalError::UnknownIdentifier { name: String::from("X"), span: Span::new(4, 5) }
+        );
+        assert_eq!(
+            calculate("A * V T", &definitions, &functions, Mode::Decimal).unwrap_err(),
+            EvalError::DimensionMismatch { op: String::from("*"), left: (2, 2), right: (1, 3), span: Span::new(2, 3) }
+        );
+        assert_eq!(
+            calculate("A + V", &definitions, &functions, Mode::Decimal).unwrap_err(),
+            EvalError::DimensionMismatch { op: String::from("+"), left: (2, 2), right: (3, 1), span: Span::new(2, 3) }
+        );
+        assert_eq!(
+            calculate("V DET", &definitions, &functions, Mode::Decimal).unwrap_err(),
+            EvalError::NotSquare { op: String::from("DET"), shape: (3, 1), span: Span::new(2, 5) }
+        );
+        assert_eq!(calculate("S INV", &definitions, &functions, Mode::Decimal).unwrap_err(), EvalError::SingularMatrix { rank: 1, size: 2, span: Span::new(2, 5) });
+        assert_eq!(
+            calculate("D T", &definitions, &functions, Mode::Decimal).unwrap_err(),
+            EvalError::UnaryOperatorMisuse { op: Operators::Transp, span: Span::new(2, 3) }
+        );
+        assert_eq!(calculate("(A + A", &definitions, &functions, Mode::Decimal).unwrap_err(), EvalError::UnbalancedParenthesis { span: Span::new(0, 1) });
+        assert_eq!(calculate("A + A)", &definitions, &functions, Mode::Decimal).unwrap_err(), EvalError::UnbalancedParenthesis { span: Span::new(5, 6) });
+        assert_eq!(calculate("A / (D - 2)", &definitions, &functions, Mode::Decimal).unwrap_err(), EvalError::DivisionByZero { span: Span::new(2, 3) });
+        assert_eq!(calculate("A D", &definitions, &functions, Mode::Decimal).unwrap_err(), EvalError::MissingOperator { span: Span::new(2, 3) });
+        assert_eq!(
+            calculate("A +", &definitions, &functions, Mode::Decimal).unwrap_err(),
+            EvalError::MissingOperand { op: Operators::Sum, span: Span::new(2, 3) }
+        );
+        assert_eq!(calculate("A ^ 0.5", &definitions, &functions, Mode::Decimal).unwrap_err(), EvalError::InvalidExponent { span: Span::new(2, 3) });
+        assert_eq!(calculate("A ^ -1", &definitions, &functions, Mode::Decimal).unwrap_err(), EvalError::InvalidExponent { span: Span::new(2, 3) });
+        assert!(calculate("A ^ 200", &definitions, &functions, Mode::Decimal).is_ok());
+        assert_eq!(
+            calculate("A ^ 5000000000", &definitions, &functions, Mode::Decimal).unwrap_err(),
+            EvalError::ExponentTooLarge { span: Span::new(2, 3) }
+        );
+        assert_eq!(calculate("  ", &definitions, &functions, Mode::Decimal).unwrap_err(), EvalError::EmptyExpression);
+    }
+
+    #[test]
+    fn test_signs() {
+        let definitions = fixture(&[("B", Value::Matrix(Matrix::new_from(2, 1, &[&[1.0], &[-2.0]]).unwrap()))]);
+        let functions = UserFunctions::default();
+        let scalar = |exp: &str| *calculate(exp, &definitions, &functions, Mode::Decimal).unwrap().as_scalar().unwrap();
+
+        assert_eq!(scalar("-2 ^ 2"), -4.0);
+        assert_eq!(scalar("(-2) ^ 2"), 4.0);
+        assert_eq!(scalar("2 * - 3"), -6.0);
+        assert_eq!(scalar("2 ^ -1"), 0.5);
+        assert_eq!(scalar("- - D"), 2.0);
+        assert_eq!(scalar("+D - -1"), 3.0);
+        assert_eq!(scalar("-A DET"), 2.0);
+
+        let result = calculate("- A", &definitions, &functions, Mode::Decimal).unwrap();
+        let expected = Matrix::new_from(2, 2, &[&[-1.0, -2.0], &[-3.0, -4.0]]).unwrap();
+        assert!(result.as_matrix().unwrap().equals(&expected));
+
+        let result = calculate("( - B ) T", &definitions, &functions, Mode::Decimal).unwrap();
+        let expected = Matrix::new_from(1, 2, &[&[-1.0, 2.0]]).unwrap();
+        assert!(result.as_matrix().unwrap().equals(&expected));
+
+        // The sign applies after the transpose
+        let result = calculate("-B T", &definitions, &functions, Mode::Decimal).unwrap();
+        assert!(result.as_matrix().unwrap().equals(&expected));
+
+        // After a postfix operator `-` is still a subtraction
+        let result = calculate("A T - A", &definitions, &functions, Mode::Decimal).unwrap();
+        let expected = Matrix::new_from(2, 2, &[&[0.0, 1.0], &[-1.0, 0.0]]).unwrap();
+        assert!(result.as_matrix().unwrap().equals(&expected));
+    }
+
+    // Writes the tree fully parenthesized, so the grouping chosen by the parser is visible
+    fn render(tree: &ExpTree) -> String {
+        let left = tree.left_op().as_ref().map(|t| render(t));
+        let right = tree.right_op().as_ref().map(|t| render(t));
+        match (tree.op(), left, right) {
+            (Operand::Scalar(num), _, _) => format!("{}", num),
+            (Operand::Imaginary(num), _, _) => format!("{}i", num),
+            (Operand::Index, _, _) => {
+                let args: Vec<String> = tree.args().iter().map(render).collect();
+                format!("{}[{}, {}]", args[0], args[1], args[2])
+            }
+            (Operand::Range, left, right) => format!("{}:{}", left.unwrap_or_default(), right.unwrap_or_default()),
+            (Operand::OpenBound, _, _) => String::new(),
+            (Operand::Variable(Value::Matrix(mat)), _, _) => format!("M{}x{}", mat.m, mat.n),
+            (Operand::Variable(Value::Scalar(num)), _, _) => format!("{}", num),
+            (Operand::Variable(value), _, _) => format!("{:?}", value),
+            (Operand::Function(function), _, _) => {
+                let args: Vec<String> = tree.args().iter().map(render).collect();
+                format!("{}({})", function, args.join(", "))
+            }
+            (Operand::UserFunction(name, _), _, _) => {
+                let args: Vec<String> = tree.args().iter().map(render).collect();
+                format!("{}({})", name, args.join(", "))
+            }
+            (Operand::MatrixLiteral(_, cols), _, _) => {
+                let elements: Vec<String> = tree.args().iter().map(render).collect();
+                let rows: Vec<String> = elements.chunks(*cols).map(|row| row.join(", ")).collect();
+                format!("[{}]", rows.join("; "))
+            }
+            (Operand::Operation(op), Some(left), Some(right)) => format!("({} {} {})", left, op, right),
+            (Operand::Operation(op), Some(operand), None) if op.is_prefix() => format!("({}{})", op, operand),
+            (Operand::Operation(op), Some(operand), None) => format!("({} {})", operand, op),
+            (Operand::Operation(op), _, _) => format!("{}", op),
+        }
+    }
+
+    #[test]
+    fn test_precedence() {
+        let definitions = fixture(&[]);
+        let functions = UserFunctions::default();
+        let cases = [
+            // Additive < multiplicative
+            ("2 + 3 * 4", "(2 + (3 * 4))"),
+            ("2 * 3 + 4", "((2 * 3) + 4)"),
+            ("A * B / 2", "((M2x2 * M2x2) / 2)"),
+            ("8 / 4 * 2", "((8 / 4) * 2)"),
+            // Left associativity
+            ("1 - 2 - 3", "((1 - 2) - 3)"),
+            ("1 - 2 + 3", "((1 - 2) + 3)"),
+            ("8 / 4 / 2", "((8 / 4) / 2)"),
+            // Power is right associative and binds tighter than signs
+            ("2 ^ 3 ^ 2", "(2 ^ (3 ^ 2))"),
+            ("-2 ^ 2", "(-(2 ^ 2))"),
+            ("2 ^ -1", "(2 ^ (-1))"),
+            ("-2 * 3", "((-2) * 3)"),
+            ("2 * 3 ^ 2", "(2 * (3 ^ 2))"),
+            // Postfix operators bind tightest
+            ("A T INV", "((M2x2 T) INV)"),
+            ("2 * A DET", "(2 * (M2x2 DET))"),
+            ("-A T", "(-(M2x2 T))"),
+            ("A ^ 2 T", "(M2x2 ^ (2 T))"),
+            ("(A ^ 2) T", "((M2x2 ^ 2) T)"),
+            ("A + B T * 2", "(M2x2 + ((M2x2 T) * 2))"),
+            // Parenthesis override everything
+            ("(2 + 3) * 4", "((2 + 3) * 4)"),
+            ("2 ^ (3 - 1) ^ 2", "(2 ^ ((3 - 1) ^ 2))"),
+        ];
+        check_cases(&functions, &cases);
+
+        // And the values follow the grouping
+        assert_eq!(*calculate("2 + 3 * 4", &definitions, &functions, Mode::Decimal).unwrap().as_scalar().unwrap(), 14.0);
+        assert_eq!(*calculate("1 - 2 - 3", &definitions, &functions, Mode::Decimal).unwrap().as_scalar().unwrap(), -4.0);
+        assert_eq!(*calculate("2 ^ 3 ^ 2", &definitions, &functions, Mode::Decimal).unwrap().as_scalar().unwrap(), 512.0);
+    }
+
+    #[test]
+    fn test_matrix_literals() {
+        let definitions = fixture(&[]);
+        let functions = UserFunctions::default();
+        let cases = [
+            ("[1 2; 3 4]", "[1, 2; 3, 4]"),
+            ("[1, 2; 3, 4]", "[1, 2; 3, 4]"),
+            ("[1 -2]", "[1, (-2)]"),
+            ("[1 - 2]", "[(1 - 2)]"),
+            ("[1 -D*2, (D+1) 3]", "[1, ((-2) * 2), (2 + 1), 3]"),
+            ("[1; 2;]", "[1; 2]"),
+            ("[[1 2] DET]", "[([1, 2] DET)]"),
+            ("[1 2] T * 2", "(([1, 2] T) * 2)"),
+        ];
+        check_cases(&functions, &cases);
+
+        let result = calculate("[1 2; 3 4] INV * B", &definitions, &functions, Mode::Decimal).unwrap();
+        let expected = Matrix::new_from(2, 2, &[&[-1.0, -2.0], &[2.0, 3.0]]).unwrap();
+        assert!(result.as_matrix().unwrap().equals(&expected));
+
+        let result = calculate("[D^2, -1; 1/2 D]", &definitions, &functions, Mode::Decimal).unwrap();
+        let expected = Matrix::new_from(2, 2, &[&[4.0, -1.0], &[0.5, 2.0]]).unwrap();
+        assert!(result.as_matrix().unwrap().equals(&expected));
+
+        assert_eq!(calculate("[1 2; 3]", &definitions, &functions, Mode::Decimal).unwrap_err(), EvalError::RaggedMatrix { span: Span::new(0, 8) });
+        assert_eq!(calculate("[]", &definitions, &functions, Mode::Decimal).unwrap_err(), EvalError::EmptyMatrix { span: Span::new(0, 2) });
+        assert_eq!(calculate("[1, , 2]", &definitions, &functions, Mode::Decimal).unwrap_err(), EvalError::UnexpectedToken { span: Span::new(4, 5) });
+        assert_eq!(calculate("[1 2", &definitions, &functions, Mode::Decimal).unwrap_err(), EvalError::UnbalancedBracket { span: Span::new(0, 1) });
+        assert_eq!(calculate("1 2]", &definitions, &functions, Mode::Decimal).unwrap_err(), EvalError::UnbalancedBracket { span: Span::new(3, 4) });
+        assert_eq!(calculate("[1 (2]", &definitions, &functions, Mode::Decimal).unwrap_err(), EvalError::UnbalancedParenthesis { span: Span::new(3, 4) });
+        assert_eq!(calculate("[B 1]", &definitions, &functions, Mode::Decimal).unwrap_err(), EvalError::NonScalarElement { span: Span::new(1, 2) });
+        assert_eq!(calculate("1, 2", &definitions, &functions, Mode::Decimal).unwrap_err(), EvalError::UnexpectedToken { span: Span::new(1, 2) });
+    }
+
+    #[test]
+    fn test_functions() {
+        let definitions = fixture(&[]);
+        let functions = UserFunctions::default();
+        let cases = [
+            ("det(A)", "det(M2x2)"),
+            ("max(1, 2 + 3, -D)", "max(1, (2 + 3), (-2))"),
+            ("2 * sqrt(D) ^ 2", "(2 * (sqrt(2) ^ 2))"),
+            ("det (A T) + 1", "(det((M2x2 T)) + 1)"),
+            ("inv(det(A) * A)", "inv((det(M2x2) * M2x2))"),
+            ("[max(1, 2) 3]", "[max(1, 2), 3]"),
+            ("zeros(D, 3)", "zeros(2, 3)"),
+        ];
+        check_cases(&functions, &cases);
+
+        let scalar = |exp: &str| *calculate(exp, &definitions, &functions, Mode::Decimal).unwrap().as_scalar().unwrap();
+        assert_eq!(scalar("det(A)"), -2.0);
+        assert_eq!(scalar("det_cofactores(A)"), -2.0);
+        assert_eq!(scalar("trace(A)"), 5.0);
+        assert_eq!(scalar("rank(A)"), 2.0);
+        assert_eq!(scalar("sqrt(16) + abs(-D)"), 6.0);
+        assert_eq!(scalar("sin(0)"), 0.0);
+        assert_eq!(scalar("max(1, D, A)"), 4.0);
+        assert_eq!(scalar("min(A, 3)"), 1.0);
+        assert_eq!(scalar("det(eye(3))"), 1.0);
+
+        let result = calculate("inv_adjunta(A)", &definitions, &functions, Mode::Decimal).unwrap();
+        let expected = calculate("inv(A)", &definitions, &functions, Mode::Decimal).unwrap();
+        assert!(result.as_matrix().unwrap().equals(expected.as_matrix().unwrap()));
+        assert_eq!(
+            calculate("inv([1 2 3; 2 4 6; 1 1 1])", &definitions, &functions, Mode::Decimal).unwrap_err(),
+            EvalError::SingularMatrix { rank: 2, size: 3, span: Span::new(0, 26) }
+        );
+        let result = calculate("inv(A)", &definitions, &functions, Mode::Decimal).unwrap();
+        let expected = calculate("A INV", &definitions, &functions, Mode::Decimal).unwrap();
+        assert!(result.as_matrix().unwrap().equals(expected.as_matrix().unwrap()));
+        let result = calculate("zeros(2, 3) + ones(2, 3) * D", &definitions, &functions, Mode::Decimal).unwrap();
+        let expected = Matrix::new_from(2, 3, &[&[2.0, 2.0, 2.0], &[2.0, 2.0, 2.0]]).unwrap();
+        assert!(result.as_matrix().unwrap().equals(&expected));
+        let result = calculate("abs(-A)", &definitions, &functions, Mode::Decimal).unwrap();
+        assert!(result.as_matrix().unwrap().equals(definitions.0["A"].as_matrix().unwrap()));
+
+        assert_eq!(
+            calculate("foo(1)", &definitions, &functions, Mode::Decimal).unwrap_err(),
+            EvalError::UnknownFunction { name: String::from("foo"), span: Span::new(0, 3) }
+        );
+        assert_eq!(
+            calculate("det(A, A)", &definitions, &functions, Mode::Decimal).unwrap_err(),
+            EvalError::WrongArgumentCount { name: String::from("det"), min: 1, max: 1, found: 2, span: Span::new(0, 9) }
+        );
+        assert_eq!(
+            calculate("1 + eye()", &definitions, &functions, Mode::Decimal).unwrap_err(),
+            EvalError::WrongArgumentCount { name: String::from("eye"), min: 1, max: 1, found: 0, span: Span::new(4, 9) }
+        );
+        assert_eq!(
+            calculate("det(V)", &definitions, &functions, Mode::Decimal).unwrap_err(),
+            EvalError::NotSquare { op: String::from("det"), shape: (3, 1), span: Span::new(0, 6) }
+        );
+        assert_eq!(
+            calculate("eye(1.5)", &definitions, &functions, Mode::Decimal).unwrap_err(),
+            EvalError::InvalidArgument { name: String::from("eye"), span: Span::new(0, 8) }
+        );
+        assert_eq!(calculate("max(1,)", &definitions, &functions, Mode::Decimal).unwrap_err(), EvalError::UnexpectedToken { span: Span::new(6, 7) });
+        assert_eq!(calculate("(1, 2)", &definitions, &functions, Mode::Decimal).unwrap_err(), EvalError::UnexpectedToken { span: Span::new(2, 3) });
+        assert_eq!(calculate("det(())", &definitions, &functions, Mode::Decimal).unwrap_err(), EvalError::UnexpectedToken { span: Span::new(4, 5) });
+        assert_eq!(calculate("1+det(())", &definitions, &functions, Mode::Decimal).unwrap_err(), EvalError::UnexpectedToken { span: Span::new(6, 7) });
+    }
+
+    #[test]
+    fn test_user_functions() {
+        let definitions = fixture(&[
+            ("V", Value::Matrix(Matrix::new_from(2, 1, &[&[1.0], &[1.0]]).unwrap())),
+            ("X", Value::Scalar(100.0)),
+        ]);
+        let mut functions = UserFunctions::default();
+        for definition in [
+            "sq(X) = X * X",
+            "gram(M) = M T * M",
+            "scale(M, k) = M * k * D",
+            "twice(X) = sq(X) + sq(X)",
+            "two() = 2",
+            "loop(X) = loop(X) + 1",
+            "bad(X) = X + Y",
+        ] {
+            let (name, function) = define_function(definition).unwrap();
+            functions.0.insert(name, function);
+        }
+
+        check_cases(&functions, &[("sq(1 + D) * two()", "(sq((1 + 2)) * two())")]);
+
+        let scalar = |exp: &str| *calculate(exp, &definitions, &functions, Mode::Decimal).unwrap().as_scalar().unwrap();
+        assert_eq!(scalar("sq(3)"), 9.0);
+        assert_eq!(scalar("twice(D) + two()"), 10.0);
+        // Parameters hide the global `X` only inside the function
+        assert_eq!(scalar("sq(2) + X"), 104.0);
+        assert_eq!(scalar("det(scale(A, 2))"), -32.0);
+
+        let result = calculate("sq(A)", &definitions, &functions, Mode::Decimal).unwrap();
+        let expected = Matrix::new_from(2, 2, &[&[7.0, 10.0], &[15.0, 22.0]]).unwrap();
+        assert!(result.as_matrix().unwrap().equals(&expected));
+        let result = calculate("gram(V T)", &definitions, &functions, Mode::Decimal).unwrap();
+        let expected = Matrix::new_from(2, 2, &[&[1.0, 1.0], &[1.0, 1.0]]).unwrap();
+        assert!(result.as_matrix().unwrap().equals(&expected));
+
+        assert_eq!(
+            calculate("sq(1, 2)", &definitions, &functions, Mode::Decimal).unwrap_err(),
+            EvalError::WrongArgumentCount { name: String::from("sq"), min: 1, max: 1, found: 2, span: Span::new(0, 8) }
+        );
+        // Errors inside the body point at the call and keep the original cause
+        assert_eq!(
+            calculate("1 + bad(2)", &definitions, &functions, Mode::Decimal).unwrap_err(),
+            EvalError::InFunction {
+                name: String::from("bad"),
+                span: Span::new(4, 10),
+                error: Box::new(EvalError::UnknownIdentifier { name: String::from("Y"), span: Span::new(13, 14) }),
+            }
+        );
+        assert_eq!(
+            calculate("loop(1)", &definitions, &functions, Mode::Decimal).unwrap_err(),
+            EvalError::InFunction {
+                name: String::from("loop"),
+                span: Span::new(0, 7),
+                error: Box::new(EvalError::RecursionLimit { name: String::from("loop"), span: Span::new(10, 17) }),
+            }
+        );
+
+        // Invalid definitions
+        let error = |definition: &str| define_function(definition).unwrap_err();
+        assert_eq!(error("det(X) = X"), EvalError::ReservedFunction { name: String::from("det"), span: Span::new(0, 3) });
+        assert_eq!(error("f(X, X) = X"), EvalError::DuplicateParameter { name: String::from("X"), span: Span::new(5, 6) });
+        assert_eq!(error("f(X Y) = X"), EvalError::InvalidDefinition { span: Span::new(4, 5) });
+        assert_eq!(error("f(X,) = X"), EvalError::InvalidDefinition { span: Span::new(4, 5) });
+        assert_eq!(error("f X = X"), EvalError::InvalidDefinition { span: Span::new(2, 3) });
+        assert_eq!(error("f(X)"), EvalError::InvalidDefinition { span: Span::new(0, 4) });
+        assert_eq!(error("f(X) = (X"), EvalError::UnbalancedParenthesis { span: Span::new(7, 8) });
+        assert_eq!(error("f(X) =  "), EvalError::EmptyExpression);
+    }
+
+    #[test]
+    fn test_indexing() {
+        let mut definitions = fixture(&[
+            ("A", Value::Matrix(Matrix::new_from(3, 3, &[&[1.0, 2.0, 3.0], &[4.0, 5.0, 6.0], &[7.0, 8.0, 9.0]]).unwrap())),
+        ]);
+        let functions = UserFunctions::default();
+        let cases = [
+            ("A[0, 1]", "M2x2[0, 1]"),
+            ("A[D - 1, :]", "M2x2[(2 - 1), :]"),
+            ("A[0:2, 1:]", "M2x2[0:2, 1:]"),
+            ("A[:D, 0]", "M2x2[:2, 0]"),
+            ("-A[0, 0] ^ 2", "(-(M2x2[0, 0] ^ 2))"),
+            ("A T[0, 1]", "(M2x2 T)[0, 1]"),
+            ("[A[0, 0] 2]", "[M2x2[0, 0], 2]"),
+            ("[1 2][0, 1]", "[1, 2][0, 1]"),
+            ("inv(A)[0, :]", "inv(M2x2)[0, :]"),
+        ];
+        check_cases(&functions, &cases);
+
+        let scalar = |exp: &str| *calculate(exp, &definitions, &functions, Mode::Decimal).unwrap().as_scalar().unwrap();
+        assert_eq!(scalar("A[0, 1]"), 2.0);
+        assert_eq!(scalar("A[D, D] + A T[0, 1]"), 13.0);
+        let matrix = |exp: &str| calculate(exp, &definitions, &functions, Mode::Decimal).unwrap().as_matrix().unwrap().clone();
+        assert!(matrix("A[1, :]").equals(&Matrix::new_from(1, 3, &[&[4.0, 5.0, 6.0]]).unwrap()));
+        assert!(matrix("A[:, 2]").equals(&Matrix::new_from(3, 1, &[&[3.0], &[6.0], &[9.0]]).unwrap()));
+        assert!(matrix("A[0:2, 1:3]").equals(&Matrix::new_from(2, 2, &[&[2.0, 3.0], &[5.0, 6.0]]).unwrap()));
+        assert!(matrix("A[1:, :1]").equals(&Matrix::new_from(2, 1, &[&[4.0], &[7.0]]).unwrap()));
+        assert!(matrix("A[:, :]").equals(definitions.0["A"].as_matrix().unwrap()));
+
+        let error = |exp: &str| calculate(exp, &definitions, &functions, Mode::Decimal).unwrap_err();
+        assert_eq!(error("A[3, 0]"), EvalError::IndexOutOfBounds { index: 3, size: 3, span: Span::new(2, 3) });
+        assert_eq!(error("A[0, 1:4]"), EvalError::IndexOutOfBounds { index: 4, size: 3, span: Span::new(7, 8) });
+        assert_eq!(error("A[2:1, 0]"), EvalError::EmptyMatrix { span: Span::new(3, 4) });
+        assert_eq!(error("A[0.5, 0]"), EvalError::InvalidIndex { span: Span::new(2, 5) });
+        assert_eq!(error("A[-1, 0]"), EvalError::InvalidIndex { span: Span::new(2, 3) });
+        assert_eq!(error("A[0]"), EvalError::IndexCount { found: 1, span: Span::new(1, 4) });
+        assert_eq!(error("A[]"), EvalError::IndexCount { found: 0, span: Span::new(1, 3) });
+        assert_eq!(error("D[0, 0]"), EvalError::NotIndexable { span: Span::new(0, 1) });
+        assert_eq!(error("A[0:1:2, 0]"), EvalError::UnexpectedToken { span: Span::new(5, 6) });
+        assert_eq!(error("1:2"), EvalError::UnexpectedToken { span: Span::new(1, 2) });
+        assert_eq!(error("[1:2]"), EvalError::UnexpectedToken { span: Span::new(2, 3) });
+
+        // Slice assignment
+        let (name, value) = assign_slice("A[1, :] = [5 6 7]", &mut definitions, &functions, Mode::Decimal).unwrap();
+        assert_eq!(name, "A");
+        let expected = Matrix::new_from(3, 3, &[&[1.0, 2.0, 3.0], &[5.0, 6.0, 7.0], &[7.0, 8.0, 9.0]]).unwrap();
+        assert!(value.as_matrix().unwrap().equals(&expected));
+        assert!(definitions.0["A"].as_matrix().unwrap().equals(&expected));
+        assign_slice("A[:2, 1:] = 0", &mut definitions, &functions, Mode::Decimal).unwrap();
+        let expected = Matrix::new_from(3, 3, &[&[1.0, 0.0, 0.0], &[5.0, 0.0, 0.0], &[7.0, 8.0, 9.0]]).unwrap();
+        assert!(definitions.0["A"].as_matrix().unwrap().equals(&expected));
+        assign_slice("A[:, 0] = -1", &mut definitions, &functions, Mode::Decimal).unwrap();
+        let expected = Matrix::new_from(3, 3, &[&[-1.0, 0.0, 0.0], &[-1.0, 0.0, 0.0], &[-1.0, 8.0, 9.0]]).unwrap();
+        assert!(definitions.0["A"].as_matrix().unwrap().equals(&expected));
+
+        let error = |input: &str| assign_slice(input, &mut Definitions(definitions.0.clone()), &functions, Mode::Decimal).unwrap_err();
+        assert_eq!(
+            error("A[0, :] = [1 2]"),
+            EvalError::DimensionMismatch { op: String::from("="), left: (1, 3), right: (1, 2), span: Span::new(8, 9) }
+        );
+        assert_eq!(error("A[0, 0] = X"), EvalError::UnknownIdentifier { name: String::from("X"), span: Span::new(10, 11) });
+        assert_eq!(error("A T[0, 0] = 1"), EvalError::InvalidTarget { span: Span::new(0, 10) });
+        assert_eq!(error("A[0, :][0, 0] = 1"), EvalError::InvalidTarget { span: Span::new(0, 14) });
+        assert_eq!(error("D[0, 0] = 1"), EvalError::NotIndexable { span: Span::new(0, 1) });
+        assert_eq!(error("Y[0, 0] = 1"), EvalError::UnknownIdentifier { name: String::from("Y"), span: Span::new(0, 1) });
+    }
+
+    #[test]
+    fn test_element_wise() {
+        let definitions = fixture(&[
+            ("M", Value::Matrix(Matrix::new_from(2, 3, &[&[1.0, 2.0, 3.0], &[4.0, 5.0, 6.0]]).unwrap())),
+            ("R", Value::Matrix(Matrix::new_from(1, 3, &[&[1.0, 0.0, -1.0]]).unwrap())),
+            ("C", Value::Matrix(Matrix::new_from(2, 1, &[&[2.0], &[4.0]]).unwrap())),
+        ]);
+        let functions = UserFunctions::default();
+        let cases = [
+            ("A .* A * 2", "((M2x2 .* M2x2) * 2)"),
+            ("A + A ./ 2", "(M2x2 + (M2x2 ./ 2))"),
+            ("-A .^ 2 .^ 3", "(-(M2x2 .^ (2 .^ 3)))"),
+            ("2.*A", "(2 .* M2x2)"),
+        ];
+        check_cases(&functions, &cases);
+
+        let matrix = |exp: &str| calculate(exp, &definitions, &functions, Mode::Decimal).unwrap().as_matrix().unwrap().clone();
+        assert!(matrix("A .* A").equals(&Matrix::new_from(2, 2, &[&[1.0, 4.0], &[9.0, 16.0]]).unwrap()));
+        assert!(matrix("A .^ 2").equals(&matrix("A .* A")));
+        assert!(matrix("2 .^ A").equals(&Matrix::new_from(2, 2, &[&[2.0, 4.0], &[8.0, 16.0]]).unwrap()));
+        assert!(matrix("12 ./ A").equals(&Matrix::new_from(2, 2, &[&[12.0, 6.0], &[4.0, 3.0]]).unwrap()));
+        assert!(matrix("A + 1").equals(&Matrix::new_from(2, 2, &[&[2.0, 3.0], &[4.0, 5.0]]).unwrap()));
+        assert!(matrix("1 - A").equals(&Matrix::new_from(2, 2, &[&[0.0, -1.0], &[-2.0, -3.0]]).unwrap()));
+        assert!(matrix("M + R").equals(&Matrix::new_from(2, 3, &[&[2.0, 2.0, 2.0], &[5.0, 5.0, 5.0]]).unwrap()));
+        assert!(matrix("M .* C").equals(&Matrix::new_from(2, 3, &[&[2.0, 4.0, 6.0], &[16.0, 20.0, 24.0]]).unwrap()));
+        assert!(matrix("M ./ C - R").equals(&Matrix::new_from(2, 3, &[&[-0.5, 1.0, 2.5], &[0.0, 1.25, 2.5]]).unwrap()));
+        assert_eq!(*calculate("6 ./ 4 .^ 0.5", &definitions, &functions, Mode::Decimal).unwrap().as_scalar().unwrap(), 3.0);
+
+        let error = |exp: &str| calculate(exp, &definitions, &functions, Mode::Decimal).unwrap_err();
+        assert_eq!(
+            error("M .* A"),
+            EvalError::DimensionMismatch { op: String::from(".*"), left: (2, 3), right: (2, 2), span: Span::new(2, 4) }
+        );
+        assert_eq!(
+            error("M - R T"),
+            EvalError::DimensionMismatch { op: String::from("-"), left: (2, 3), right: (3, 1), span: Span::new(2, 3) }
+        );
+        assert_eq!(error("A ./ R[0, 0:2]"), EvalError::DivisionByZero { span: Span::new(2, 4) });
+        assert_eq!(error("1 ./ 0"), EvalError::DivisionByZero { span: Span::new(2, 4) });
+    }
+
+    #[test]
+    fn test_vector_functions() {
+        let definitions = fixture(&[
+            ("U", Value::Matrix(Matrix::new_from(1, 3, &[&[3.0, 0.0, 4.0]]).unwrap())),
+            ("V", Value::Matrix(Matrix::new_from(3, 1, &[&[0.0], &[2.0], &[0.0]]).unwrap())),
+            ("Z", Value::Matrix(Matrix::new_empty(3, 1))),
+        ]);
+        let functions = UserFunctions::default();
+        let scalar = |exp: &str| *calculate(exp, &definitions, &functions, Mode::Decimal).unwrap().as_scalar().unwrap();
+        let matrix = |exp: &str| calculate(exp, &definitions, &functions, Mode::Decimal).unwrap().as_matrix().unwrap().clone();
+
+        // Rows and columns can be mixed
+        assert_eq!(scalar("dot(U, V)"), 0.0);
+        assert_eq!(scalar("dot(U, U T)"), 25.0);
+        assert_eq!(scalar("norm(U)"), 5.0);
+        assert_eq!(scalar("norm(U, 1)"), 7.0);
+        assert_eq!(scalar("norm(normalize(V))"), 1.0);
+        assert_eq!(scalar("angle(U, V)"), std::f64::consts::FRAC_PI_2);
+        assert!(matrix("normalize(U)").equals(&Matrix::new_from(1, 3, &[&[0.6, 0.0, 0.8]]).unwrap()));
+        assert!(matrix("cross(U, V)").equals(&Matrix::new_from(1, 3, &[&[-8.0, 0.0, 6.0]]).unwrap()));
+        assert!(matrix("cross(V, U)").equals(&Matrix::new_from(3, 1, &[&[8.0], &[0.0], &[-6.0]]).unwrap()));
+        assert!(matrix("proj(U + V T, V)").equals(&Matrix::new_from(3, 1, &[&[0.0], &[2.0], &[0.0]]).unwrap()));
+        assert!(matrix("proj([1 1 0], [2 0 0])").equals(&Matrix::new_from(1, 3, &[&[1.0, 0.0, 0.0]]).unwrap()));
+
+        let error = |exp: &str| calculate(exp, &definitions, &functions, Mode::Decimal).unwrap_err();
+        assert_eq!(
+            error("dot(U, [1 2])"),
+            EvalError::DimensionMismatch { op: String::from("dot"), left: (1, 3), right: (1, 2), span: Span::new(0, 13) }
+        );
+        assert_eq!(error("dot(A, A)"), EvalError::InvalidArgument { name: String::from("dot"), span: Span::new(0, 9) });
+        assert_eq!(error("norm(2)"), EvalError::InvalidArgument { name: String::from("norm"), span: Span::new(0, 7) });
+        assert_eq!(error("norm(U, 0.5)"), EvalError::InvalidArgument { name: String::from("norm"), span: Span::new(0, 12) });
+        assert_eq!(error("cross([1 2], [3 4])"), EvalError::InvalidArgument { name: String::from("cross"), span: Span::new(0, 19) });
+        assert_eq!(error("normalize(Z)"), EvalError::ZeroVector { name: String::from("normalize"), span: Span::new(0, 12) });
+        assert_eq!(error("angle(Z, U)"), EvalError::ZeroVector { name: String::from("angle"), span: Span::new(0, 11) });
+        assert_eq!(error("proj(U, Z)"), EvalError::ZeroVector { name: String::from("proj"), span: Span::new(0, 10) });
+    }
+
+    #[test]
+    fn test_exact_mode() {
+        let frac = |numer: i64, denom: i64| Rational::new(numer.into(), denom.into());
+        let mut definitions = fixture(&[("D", Value::Scalar(0.5))]);
+        let functions = UserFunctions::default();
+        let exact = |exp: &str, definitions: &Definitions| calculate(exp, definitions, &functions, Mode::Exact).unwrap();
+        let rational = |exp: &str, definitions: &Definitions| exact(exp, definitions).as_rational().unwrap().clone();
+
+        assert_eq!(rational("1/3 + 1/6", &definitions), frac(1, 2));
+        assert_eq!(rational("0.1 + 0.2", &definitions), frac(3, 10));
+        assert_eq!(rational("2 ^ -2", &definitions), frac(1, 4));
+        assert_eq!(rational("det([1 2; 3 4] INV)", &definitions), frac(-1, 2));
+        assert_eq!(rational("[1 2; 3 4][1, 0] / 9", &definitions), frac(1, 3));
+        assert_eq!(rational("max(1/3, 0.3)", &definitions), frac(1, 3));
+        assert_eq!(rational("rank([1 2; 2 4])", &definitions), frac(1, 1));
+        assert_eq!(rational("abs(-1/3)", &definitions), frac(1, 3));
+
+        let result = exact("inv([1 2; 3 4])", &definitions);
+        let expected = [[frac(-2, 1), frac(1, 1)], [frac(3, 2), frac(-1, 2)]];
+        let matrix = result.as_rational_matrix().unwrap();
+        assert!((0..2).all(|i| (0..2).all(|j| matrix[i][j] == expected[i][j])));
+        let result = exact("eye(2) / 3 - adj([1 0; 0 1]) .* [1 2]", &definitions);
+        assert_eq!(result.as_rational_matrix().unwrap()[1][1], frac(-5, 3));
+
+        // Irrational functions, fractional powers and the stored floats make the result inexact
+        assert_eq!(*exact("sqrt(4)", &definitions).as_scalar().unwrap(), 2.0);
+        assert_eq!(*exact("1/4 + sqrt(4)", &definitions).as_scalar().unwrap(), 2.25);
+        assert_eq!(*exact("4 ^ 0.5", &definitions).as_scalar().unwrap(), 2.0);
+        assert_eq!(*exact("D + 1/2", &definitions).as_scalar().unwrap(), 1.0);
+        assert!(exact("A INV", &definitions).as_matrix().is_some());
+        assert_eq!(
+            calculate("0 ^ -1", &definitions, &functions, Mode::Exact).unwrap_err(),
+            EvalError::DivisionByZero { span: Span::new(2, 3) }
+        );
+        assert_eq!(
+            calculate("[0 1] .^ -1", &definitions, &functions, Mode::Exact).unwrap_err(),
+            EvalError::DivisionByZero { span: Span::new(6, 8) }
+        );
+        assert_eq!(
+            calculate("2 ^ 100000000", &definitions, &functions, Mode::Exact).unwrap_err(),
+            EvalError::ExponentTooLarge { span: Span::new(2, 3) }
+        );
+        assert_eq!(
+            calculate("[1 2] .^ 100000000", &definitions, &functions, Mode::Exact).unwrap_err(),
+            EvalError::ExponentTooLarge { span: Span::new(6, 8) }
+        );
+        assert_eq!(rational("(-1) ^ 100000001", &definitions), frac(-1, 1));
+        assert_eq!(rational("([1 1; 1 0] ^ 200)[0, 1]", &definitions), "280571172992510140037611932413038677189525".parse().unwrap());
+        assert_eq!(
+            calculate("[1 1; 1 0] ^ 4000000000", &definitions, &functions, Mode::Exact).unwrap_err(),
+            EvalError::ExponentTooLarge { span: Span::new(11, 12) }
+        );
+
+        // Changing the mode converts the stored values
+        for value in definitions.0.values_mut() {
+            *value = value.clone().into_mode(Mode::Exact);
+        }
+        assert_eq!(rational("D", &definitions), frac(1, 2));
+        assert_eq!(rational("A INV[1, 0]", &definitions), frac(3, 2));
+        let (_, value) = assign_slice("A[0, :] = 1/3", &mut definitions, &functions, Mode::Exact).unwrap();
+        assert_eq!(value.as_rational_matrix().unwrap()[0][1], frac(1, 3));
+        let back = definitions.0["A"].clone().into_mode(Mode::Decimal);
+        assert_eq!(back.as_matrix().unwrap()[1][1], 4.0);
+    }
+
+    #[test]
+    fn test_complex_numbers() {
+        let c = |re: f64, im: f64| Complex::new(re, im);
+        let definitions = fixture(&[]);
+        let functions = UserFunctions::default();
+        let solve = |exp: &str| calculate(exp, &definitions, &functions, Mode::Decimal).unwrap();
+        let complex = |exp: &str| *solve(exp).as_complex().unwrap();
+        let scalar = |exp: &str| *solve(exp).as_scalar().unwrap();
+
+        assert_eq!(complex("3+2i"), c(3.0, 2.0));
+        assert_eq!(complex("(3+2i) * (1-i)"), c(5.0, -1.0));
+        assert_eq!(complex("i ^ 2"), c(-1.0, 0.0));
+        assert_eq!(complex("1 / i"), c(0.0, -1.0));
+        assert!(complex("sqrt(-4 + 0i)").approx_eq(&c(0.0, 2.0)));
+        assert!(complex("exp(i * 3.141592653589793)").approx_eq(&c(-1.0, 0.0)));
+        assert_eq!(complex("conj(3+2i)"), c(3.0, -2.0));
+        assert_eq!(scalar("re(3+2i) + im(3+2i)"), 5.0);
+        assert_eq!(scalar("abs(3-4i)"), 5.0);
+        assert_eq!(scalar("arg(2i)"), std::f64::consts::FRAC_PI_2);
+        assert_eq!(scalar("re(2) + im(2)"), 2.0);
+
+        let matrix = solve("[1 i; 2 3]");
+        let expected = Matrix::new_from(2, 2, &[&[c(1.0, 0.0), c(0.0, 1.0)], &[c(2.0, 0.0), c(3.0, 0.0)]]).unwrap();
+        assert!(matrix.as_complex_matrix().unwrap().equals(&expected));
+        let transpose = solve("[1 i; 2 3] T");
+        assert_eq!(transpose.as_complex_matrix().unwrap()[1][0], c(0.0, 1.0));
+        let adjoint = solve("[1 i; 2 3] H");
+        assert_eq!(adjoint.as_complex_matrix().unwrap()[1][0], c(0.0, -1.0));
+        assert!(solve("A H").as_matrix().unwrap().equals(solve("A T").as_matrix().unwrap()));
+        assert_eq!(complex("det([1 i; -i 2])"), c(1.0, 0.0));
+        assert_eq!(complex("[1 i; 2 3][0, 1] * 2"), c(0.0, 2.0));
+        let product = solve("[1 i; -i 2] * inv([1 i; -i 2])");
+        assert!(product.as_complex_matrix().unwrap().equals(&crate::math::id_matrix(2)));
+        let product = solve("A * i");
+        assert_eq!(product.as_complex_matrix().unwrap()[1][1], c(0.0, 4.0));
+
+        // Functions that compare or measure real numbers reject complex ones
+        assert!(matches!(
+            calculate("max(1, i)", &definitions, &functions, Mode::Decimal).unwrap_err(),
+            EvalError::InvalidArgument { .. }
+        ));
+        assert_eq!(
+            calculate("2 H", &definitions, &functions, Mode::Decimal).unwrap_err(),
+            EvalError::UnaryOperatorMisuse { op: Operators::ConjTransp, span: Span::new(2, 3) }
+        );
+        // Complex values are kept by every mode
+        assert_eq!(complex("1/2 + i"), c(0.5, 1.0));
+        let exact = calculate("1/2 + i", &definitions, &functions, Mode::Exact).unwrap();
+        assert_eq!(*exact.as_complex().unwrap(), c(0.5, 1.0));
+        assert_eq!(*exact.into_mode(Mode::Modular(7)).as_complex().unwrap(), c(0.5, 1.0));
+    }
+
+    #[test]
+    fn test_modular_mode() {
+        let z7 = |num: i64| Modular::new(num, 7);
+        let definitions = fixture(&[]);
+        let functions = UserFunctions::default();
+        let modular = |exp: &str| calculate(exp, &definitions, &functions, Mode::Modular(7)).unwrap();
+
+        assert_eq!(*modular("5 + 4").as_modular().unwrap(), z7(2));
+        assert_eq!(*modular("1 / 3").as_modular().unwrap(), z7(5));
+        assert_eq!(*modular("1/2 + 1/2").as_modular().unwrap(), z7(1));
+        assert_eq!(*modular("2 ^ 3").as_modular().unwrap(), z7(1));
+        assert_eq!(*modular("2 ^ 7").as_modular().unwrap(), z7(2));
+        assert_eq!(*modular("3 ^ 8").as_modular().unwrap(), z7(2));
+        assert_eq!(*modular("2 ^ -1").as_modular().unwrap(), z7(4));
+        assert_eq!(*modular("3 ^ -2").as_modular().unwrap(), z7(4));
+        assert_eq!(*modular("3 ^ (5 + 3)").as_modular().unwrap(), z7(2));
+        assert_eq!(*modular("2 ^ 100000000000").as_modular().unwrap(), z7(2));
+        let result = modular("[2 3] .^ [7 -2]");
+        assert!(result.as_modular_matrix().unwrap().equals(&Matrix::new_from(1, 2, &[&[z7(2), z7(4)]]).unwrap()));
+        let result = modular("[1 1; 0 1] ^ 8");
+        assert!(result.as_modular_matrix().unwrap().equals(&Matrix::new_from(2, 2, &[&[z7(1), z7(1)], &[z7(0), z7(1)]]).unwrap()));
+        for exp in ["0 ^ -1", "7 ^ -1", "[1 0] .^ -1"] {
+            assert!(matches!(
+                calculate(exp, &definitions, &functions, Mode::Modular(7)).unwrap_err(),
+                EvalError::DivisionByZero { .. }
+            ));
+        }
+        assert!(matches!(
+            calculate("[1 1; 0 1] ^ -1", &definitions, &functions, Mode::Modular(7)).unwrap_err(),
+            EvalError::InvalidExponent { .. }
+        ));
+        assert!(matches!(
+            calculate("[1 2] .^ [1 2 3]", &definitions, &functions, Mode::Modular(7)).unwrap_err(),
+            EvalError::DimensionMismatch { .. }
+        ));
+        // Functions that need square roots are only for real numbers
+        for exp in ["norm([3 4])", "gram_schmidt([1 0; 0 1])", "cholesky([4 2; 2 5])", "lstsq([1; 1], [1; 2])"] {
+            assert!(matches!(
+                calculate(exp, &definitions, &functions, Mode::Modular(7)).unwrap_err(),
+                EvalError::InvalidArgument { .. }
+            ));
+        }
+        assert_eq!(*modular("det([1 2; 3 4])").as_modular().unwrap(), z7(5));
+        let result = modular("[1 2; 3 4] INV");
+        let expected = Matrix::new_from(2, 2, &[&[z7(5), z7(1)], &[z7(5), z7(3)]]).unwrap();
+        assert!(result.as_modular_matrix().unwrap().equals(&expected));
+        assert_eq!(
+            calculate("[1 2; 2 4] INV", &definitions, &functions, Mode::Modular(7)).unwrap_err(),
+            EvalError::SingularMatrix { rank: 1, size: 2, span: Span::new(11, 14) }
+        );
+
+        // Stored values keep their type until the mode is changed
+        assert!(modular("A INV").as_matrix().is_some());
+        let a = definitions.0["A"].clone().into_mode(Mode::Modular(7));
+        assert_eq!(a.as_modular_matrix().unwrap()[1][1], z7(4));
+        assert_eq!(*Value::Scalar(0.5).into_mode(Mode::Modular(7)).as_modular().unwrap(), z7(4));
+        assert_eq!(*Value::Modular(z7(6)).into_mode(Mode::Decimal).as_scalar().unwrap(), 6.0);
+    }
+
+    #[test]
+    fn test_least_squares() {
+        let definitions = fixture(&[]);
+        let functions = UserFunctions::default();
+
+        let points = "[1 0; 1 1; 1 2; 1 3], [1; 2; 2; 4]";
+        let result = calculate(&format!("lstsq({})", points), &definitions, &functions, Mode::Decimal).unwrap();
+        assert!(result.as_matrix().unwrap().equals(&Matrix::new_from(2, 1, &[&[0.9], &[0.9]]).unwrap()));
+        let result = calculate(&format!("lstsq({})", points), &definitions, &functions, Mode::Exact).unwrap();
+        assert_eq!(result.as_rational_matrix().unwrap()[1][0], Rational::new(9.into(), 10.into()));
+
+        // The system is inconsistent: the residual is not zero, but it is orthogonal to the columns of `A`
+        let result = calculate(&format!("lstsq_residuo({})", points), &definitions, &functions, Mode::Decimal).unwrap();
+        let expected = Matrix::new_from(4, 1, &[&[0.1], &[0.2], &[-0.7], &[0.4]]).unwrap();
+        assert!(result.as_matrix().unwrap().equals(&expected));
+        let norm = calculate(&format!("norm(lstsq_residuo({}))", points), &definitions, &functions, Mode::Decimal).unwrap();
+        assert!(norm.as_scalar().unwrap().approx_eq(&0.7f64.sqrt()));
+        let result = calculate(&format!("lstsq_residuo({})", points), &definitions, &functions, Mode::Exact).unwrap();
+        assert_eq!(result.as_rational_matrix().unwrap()[2][0], Rational::new((-7).into(), 10.into()));
+        let orthogonal = format!("[1 0; 1 1; 1 2; 1 3] T * lstsq_residuo({})", points);
+        assert!(contains_only_zeros(calculate(&orthogonal, &definitions, &functions, Mode::Exact).unwrap().as_rational_matrix().unwrap()));
+        assert_eq!(
+            calculate("lstsq([1 2; 2 4; 3 6], [1; 0; 1])", &definitions, &functions, Mode::Decimal).unwrap_err(),
+            EvalError::RankDeficient { name: String::from("lstsq"), rank: 1, cols: 2, span: Span::new(0, 33) }
+        );
+        assert_eq!(
+            calculate("lstsq(A, V)", &definitions, &functions, Mode::Decimal).unwrap_err(),
+            EvalError::DimensionMismatch { op: String::from("lstsq"), left: (2, 2), right: (3, 1), span: Span::new(0, 11) }
+        );
+    }
+
+    #[test]
+    fn test_row_reduction() {
+        let definitions = fixture(&[]);
+        let functions = UserFunctions::default();
+
+        let result = calculate("rref([2 4 2; 1 3 2])", &definitions, &functions, Mode::Decimal).unwrap();
+        assert!(result.as_matrix().unwrap().equals(&Matrix::new_from(2, 3, &[&[1.0, 0.0, -1.0], &[0.0, 1.0, 1.0]]).unwrap()));
+    }
+
+    #[test]
+    fn test_subspaces() {
+        let definitions = fixture(&[]);
+        let functions = UserFunctions::default();
+        let scalar = |exp: &str| *calculate(exp, &definitions, &functions, Mode::Decimal).unwrap().as_scalar().unwrap();
+
+        assert_eq!(scalar("nullity([1 2 3; 2 4 6])"), 2.0);
+        assert_eq!(scalar("rank([1 2 3; 2 4 6]) + nullity([1 2 3; 2 4 6])"), 3.0);
+        let result = calculate("[1 2 3; 2 4 6] * nullspace([1 2 3; 2 4 6])", &definitions, &functions, Mode::Decimal).unwrap();
+        assert!(result.as_matrix().unwrap().equals(&Matrix::new_empty(2, 2)));
+        let result = calculate("colspace(A)", &definitions, &functions, Mode::Decimal).unwrap();
+        assert!(result.as_matrix().unwrap().equals(&Matrix::new_from(2, 2, &[&[1.0, 2.0], &[3.0, 4.0]]).unwrap()));
+        assert_eq!(calculate("leftnull(A)", &definitions, &functions, Mode::Decimal).unwrap().as_matrix().unwrap().n, 0);
+        assert_eq!(calculate("rowspace(V)", &definitions, &functions, Mode::Decimal).unwrap().as_matrix().unwrap().n, 1);
+    }
+
+    #[test]
+    fn test_lu_solve() {
+        // The factors stored by the `lu` command for `A`
+        let factors = crate::math::lu(fixture(&[]).0["A"].as_matrix().unwrap());
+        let definitions = fixture(&[("P", Value::Matrix(factors.p)), ("L", Value::Matrix(factors.l)), ("U", Value::Matrix(factors.u))]);
+        let functions = UserFunctions::default();
+
+        let result = calculate("lusolve(P, L, U, [5 1; 6 0])", &definitions, &functions, Mode::Decimal).unwrap();
+        assert!(result.as_matrix().unwrap().equals(&Matrix::new_from(2, 2, &[&[-4.0, -2.0], &[4.5, 1.5]]).unwrap()));
+        assert_eq!(
+            calculate("lusolve(P, L, U, [1; 2; 3])", &definitions, &functions, Mode::Decimal).unwrap_err(),
+            EvalError::DimensionMismatch { op: String::from("lusolve"), left: (2, 2), right: (3, 1), span: Span::new(0, 27) }
+        );
+        assert_eq!(
+            calculate("lusolve(P, L, U, 1)", &definitions, &functions, Mode::Decimal).unwrap_err(),
+            EvalError::InvalidArgument { name: String::from("lusolve"), span: Span::new(0, 19) }
+        );
+    }
+
+    #[test]
+    fn test_orthogonalization() {
+        let definitions = fixture(&[]);
+        let functions = UserFunctions::default();
+
+        let result = calculate("gram_schmidt([3 1; 4 2])", &definitions, &functions, Mode::Exact).unwrap();
+        assert!(result.as_matrix().unwrap().equals(&Matrix::new_from(2, 2, &[&[0.6, -0.8], &[0.8, 0.6]]).unwrap()));
+        // `[Q R]`, with the same factors as the `qr` command
+        let result = calculate("qr([3 1; 4 2; 0 2])", &definitions, &functions, Mode::Exact).unwrap();
+        let result = result.as_matrix().unwrap();
+        assert_eq!((result.m, result.n), (3, 5));
+        let (q, r) = (submatrix(result, 0..3, 0..3), submatrix(result, 0..3, 3..5));
+        assert!(q.is_orthogonal() && r.is_upper_triangular() && r[0][0] == 5.0);
+        assert!(crate::math::mul(&q, &r).unwrap().equals(&Matrix::new_from(3, 2, &[&[3.0, 1.0], &[4.0, 2.0], &[0.0, 2.0]]).unwrap()));
+        assert!(matches!(
+            calculate("qr([1 i])", &definitions, &functions, Mode::Decimal).unwrap_err(),
+            EvalError::InvalidArgument { .. }
+        ));
+        let result = calculate("gram_schmidt_clasico([1 0; 1 1])", &definitions, &functions, Mode::Decimal).unwrap();
+        let half = 0.5f64.sqrt();
+        assert!(result.as_matrix().unwrap().equals(&Matrix::new_from(2, 2, &[&[half, -half], &[half, half]]).unwrap()));
+        // Dependent columns are an error, not a smaller basis
+        assert_eq!(
+            calculate("gram_schmidt([1 2; 2 4])", &definitions, &functions, Mode::Decimal).unwrap_err(),
+            EvalError::RankDeficient { name: String::from("gram_schmidt"), rank: 1, cols: 2, span: Span::new(0, 24) }
+        );
+        let result = calculate("gram_schmidt(colspace([1 2; 2 4]))", &definitions, &functions, Mode::Decimal).unwrap();
+        assert_eq!(result.as_matrix().unwrap().n, 1);
+        assert_eq!(
+            calculate("gram_schmidt(i)", &definitions, &functions, Mode::Decimal).unwrap_err(),
+            EvalError::InvalidArgument { name: String::from("gram_schmidt"), span: Span::new(0, 15) }
+        );
+    }
+
+    #[test]
+    fn test_cholesky_and_predicates() {
+        let definitions = fixture(&[]);
+        let functions = UserFunctions::default();
+        let scalar = |exp: &str| *calculate(exp, &definitions, &functions, Mode::Decimal).unwrap().as_scalar().unwrap();
+
+        let result = calculate("cholesky([4 2; 2 5])", &definitions, &functions, Mode::Exact).unwrap();
+        assert!(result.as_matrix().unwrap().equals(&Matrix::new_from(2, 2, &[&[2.0, 0.0], &[1.0, 2.0]]).unwrap()));
+        assert_eq!(
+            calculate("cholesky(A)", &definitions, &functions, Mode::Decimal).unwrap_err(),
+            EvalError::NotSymmetric { op: String::from("cholesky"), span: Span::new(0, 11) }
+        );
+        assert_eq!(
+            calculate("cholesky([1 2; 2 1])", &definitions, &functions, Mode::Decimal).unwrap_err(),
+            EvalError::NotPositiveDefinite { op: String::from("cholesky"), order: 2, span: Span::new(0, 20) }
+        );
+        assert_eq!(
+            calculate("cholesky(V)", &definitions, &functions, Mode::Decimal).unwrap_err(),
+            EvalError::NotSquare { op: String::from("cholesky"), shape: (3, 1), span: Span::new(0, 11) }
+        );
+        assert_eq!(scalar("is_symmetric([1 2; 2 1]) + is_posdef([1 2; 2 1])"), 1.0);
+        assert_eq!(scalar("is_posdef([4 2; 2 5]) + is_orthogonal([0 1; 1 0]) + is_orthogonal(A)"), 2.0);
+        assert_eq!(scalar("is_diagonal([1 0 0; 0 2 0]) + is_triangular([1 2; 0 3]) + is_triangular(A)"), 2.0);
+        let result = calculate("is_orthogonal([1 i; i 1] / sqrt(2))", &definitions, &functions, Mode::Decimal).unwrap();
+        assert_eq!(result.as_complex(), Some(&Complex::new(1.0, 0.0)));
+        let result = calculate("is_symmetric([1 2; 2 1])", &definitions, &functions, Mode::Exact).unwrap();
+        assert_eq!(result.as_rational(), Some(&Rational::from_integer(1.into())));
+    }
 }
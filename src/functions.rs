@@ -0,0 +1,485 @@
+// Funciones predefinidas que pueden llamarse desde las expresiones, como `det(A)` o `max(1, 2)`
+use phf::{phf_map, Map};
+use std::fmt;
+
+use num_traits::Signed;
+
+use crate::{
+    errors::EvalError,
+    exp_interpreter::{Mode, Numbers, Typed, Value},
+    lexer::Span,
+    math::{
+        adj, angle, cholesky, colspace, cross, det, det_cofactores, dot, gram_schmidt, id_matrix, inv, inv_adjunta, is_posdef,
+        least_squares_normal, least_squares_qr, leftnull, lu_solve, norm, normalize, nullity, nullspace, proj, qr, rank, rowspace,
+        rref, set_submatrix, trace, LeastSquares, Lu,
+    },
+    number::{Complex, Number},
+    structs::Matrix,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Functions {
+    Det,
+    DetCofactores,
+    Inv,
+    InvAdjunta,
+    Adj,
+    Trace,
+    Rank,
+    Rref,
+    Nullity,
+    Nullspace,
+    Colspace,
+    Rowspace,
+    Leftnull,
+    Sqrt,
+    Abs,
+    Conj,
+    Re,
+    Im,
+    Arg,
+    Sin,
+    Cos,
+    Tan,
+    Exp,
+    Ln,
+    Max,
+    Min,
+    Eye,
+    Zeros,
+    Ones,
+    Dot,
+    Cross,
+    Norm,
+    Normalize,
+    Angle,
+    Proj,
+    Lstsq,
+    LstsqResiduo,
+    LuSolve,
+    Qr,
+    GramSchmidt,
+    GramSchmidtClasico,
+    Cholesky,
+    IsSymmetric,
+    IsPosdef,
+    IsOrthogonal,
+    IsDiagonal,
+    IsTriangular,
+}
+
+pub static FUNCTIONS: Map<&str, Functions> = phf_map! {
+    "det"   => Functions::Det,
+    "det_cofactores" => Functions::DetCofactores,
+    "inv"   => Functions::Inv,
+    "inv_adjunta" => Functions::InvAdjunta,
+    "adj"   => Functions::Adj,
+    "trace" => Functions::Trace,
+    "rank"  => Functions::Rank,
+    "rref"  => Functions::Rref,
+    "nullity" => Functions::Nullity,
+    "nullspace" => Functions::Nullspace,
+    "colspace" => Functions::Colspace,
+    "rowspace" => Functions::Rowspace,
+    "leftnull" => Functions::Leftnull,
+    "sqrt"  => Functions::Sqrt,
+    "abs"   => Functions::Abs,
+    "conj"  => Functions::Conj,
+    "re"    => Functions::Re,
+    "im"    => Functions::Im,
+    "arg"   => Functions::Arg,
+    "sin"   => Functions::Sin,
+    "cos"   => Functions::Cos,
+    "tan"   => Functions::Tan,
+    "exp"   => Functions::Exp,
+    "ln"    => Functions::Ln,
+    "max"   => Functions::Max,
+    "min"   => Functions::Min,
+    "eye"   => Functions::Eye,
+    "zeros" => Functions::Zeros,
+    "ones"  => Functions::Ones,
+    "dot"   => Functions::Dot,
+    "cross" => Functions::Cross,
+    "norm"  => Functions::Norm,
+    "normalize" => Functions::Normalize,
+    "angle" => Functions::Angle,
+    "proj"  => Functions::Proj,
+    "lstsq" => Functions::Lstsq,
+    "lstsq_residuo" => Functions::LstsqResiduo,
+    "lusolve" => Functions::LuSolve,
+    "qr"    => Functions::Qr,
+    "gram_schmidt" => Functions::GramSchmidt,
+    "gram_schmidt_clasico" => Functions::GramSchmidtClasico,
+    "cholesky" => Functions::Cholesky,
+    "is_symmetric" => Functions::IsSymmetric,
+    "is_posdef" => Functions::IsPosdef,
+    "is_orthogonal" => Functions::IsOrthogonal,
+    "is_diagonal" => Functions::IsDiagonal,
+    "is_triangular" => Functions::IsTriangular,
+};
+
+impl Functions {
+    /// Minimum and maximum amount of arguments accepted
+    pub fn arity(&self) -> (usize, usize) {
+        match self {
+            Functions::Max | Functions::Min => (1, usize::MAX),
+            Functions::Zeros | Functions::Ones | Functions::Norm => (1, 2),
+            Functions::LuSolve => (4, 4),
+            Functions::Dot | Functions::Cross | Functions::Angle | Functions::Proj => (2, 2),
+            Functions::Lstsq | Functions::LstsqResiduo => (2, 2),
+            _ => (1, 1),
+        }
+    }
+
+    // Functions that also work with complex numbers. The rest compare or measure real numbers
+    fn accepts_complex(&self) -> bool {
+        !self.real_only()
+            && !matches!(
+                self,
+                Functions::Max
+                    | Functions::Min
+                    | Functions::Eye
+                    | Functions::Zeros
+                    | Functions::Ones
+                    | Functions::Dot
+                    | Functions::Cross
+                    | Functions::Proj
+            )
+    }
+
+    // Lengths, angles and orthogonality need the square roots of real numbers, so these take neither complex nor
+    // modular arguments
+    fn real_only(&self) -> bool {
+        matches!(
+            self,
+            Functions::Norm
+                | Functions::Normalize
+                | Functions::Angle
+                | Functions::Lstsq
+                | Functions::LstsqResiduo
+                | Functions::Qr
+                | Functions::GramSchmidt
+                | Functions::GramSchmidtClasico
+                | Functions::Cholesky
+                | Functions::IsPosdef
+        )
+    }
+
+    // The amount of arguments was already checked against `arity` when building the tree. Exact arguments give
+    // exact results, except for the functions that are usually irrational, like `sqrt`
+    pub fn call(&self, args: Vec<Value>, span: Span, mode: Mode) -> Result<Value, EvalError> {
+        let name = self.to_string();
+        if (!self.accepts_complex() && args.iter().any(Value::is_complex)) || (self.real_only() && args.iter().any(Value::is_modular)) {
+            return Err(EvalError::InvalidArgument { name, span });
+        }
+        let mut numbers = args.into_iter().map(Value::numbers);
+        let first = numbers.next().expect("Functions take at least one argument");
+        match self {
+            Functions::Det
+            | Functions::DetCofactores
+            | Functions::Inv
+            | Functions::InvAdjunta
+            | Functions::Adj
+            | Functions::Trace
+            | Functions::Rank
+            | Functions::Rref
+            | Functions::Nullity
+            | Functions::Nullspace
+            | Functions::Colspace
+            | Functions::Rowspace
+            | Functions::Leftnull
+            | Functions::IsSymmetric
+            | Functions::IsOrthogonal
+            | Functions::IsDiagonal
+            | Functions::IsTriangular => match first {
+                Numbers::Exact(Typed::Matrix(matrix)) => self.call_matrix(&matrix, name, span).map(Value::from),
+                Numbers::Modular(Typed::Matrix(matrix)) => self.call_matrix(&matrix, name, span).map(Value::from),
+                Numbers::Float(Typed::Matrix(matrix)) => self.call_matrix(&matrix, name, span).map(Value::from),
+                Numbers::Complex(Typed::Matrix(matrix)) => self.call_matrix(&matrix, name, span).map(Value::from),
+                _ => Err(EvalError::InvalidArgument { name, span }),
+            },
+            Functions::Sqrt => Ok(map_elements(first, f64::sqrt, Complex::sqrt)),
+            Functions::Abs => match first {
+                Numbers::Exact(value) => Ok(Value::from(value.map(|num| num.abs()))),
+                Numbers::Complex(value) => Ok(Value::from(value.map(|num| num.norm()))),
+                numbers => Ok(Value::from(numbers.into_float().map(|num| num.abs()))),
+            },
+            // Real numbers are complex numbers with no imaginary part
+            Functions::Conj => match first {
+                Numbers::Complex(value) => Ok(Value::from(value.map(Number::conj))),
+                numbers => Ok(Value::from(numbers)),
+            },
+            Functions::Re => match first {
+                Numbers::Complex(value) => Ok(Value::from(value.map(|num| num.re))),
+                numbers => Ok(Value::from(numbers)),
+            },
+            Functions::Im => match first {
+                Numbers::Complex(value) => Ok(Value::from(value.map(|num| num.im))),
+                numbers => Ok(Value::from(numbers.into_float().map(|_| 0.0)).into_mode(mode)),
+            },
+            Functions::Arg => Ok(Value::from(first.into_complex().map(|num| num.arg()))),
+            Functions::Sin => Ok(map_elements(first, f64::sin, Complex::sin)),
+            Functions::Cos => Ok(map_elements(first, f64::cos, Complex::cos)),
+            Functions::Tan => Ok(map_elements(first, f64::tan, Complex::tan)),
+            Functions::Exp => Ok(map_elements(first, f64::exp, Complex::exp)),
+            Functions::Ln => Ok(map_elements(first, f64::ln, Complex::ln)),
+            // Over every element of every argument. Exact only if all of them are
+            Functions::Max | Functions::Min => {
+                let numbers: Vec<Numbers> = std::iter::once(first).chain(numbers).collect();
+                if numbers.iter().all(Numbers::is_exact) {
+                    let elements = numbers.into_iter().flat_map(|numbers| match numbers {
+                        Numbers::Exact(value) => value.elements(),
+                        _ => Vec::new(),
+                    });
+                    let result = if *self == Functions::Max { elements.max() } else { elements.min() };
+                    return result.map(Value::Rational).ok_or(EvalError::InvalidArgument { name, span });
+                }
+                let elements = numbers.into_iter().flat_map(|numbers| numbers.into_float().elements());
+                let result = if *self == Functions::Max {
+                    elements.fold(f64::NEG_INFINITY, f64::max)
+                } else {
+                    elements.fold(f64::INFINITY, f64::min)
+                };
+                Ok(Value::Scalar(result))
+            }
+            // New matrices take the type of number of the mode
+            Functions::Eye => Ok(Value::Matrix(id_matrix(dimension(first, &name, span)?)).into_mode(mode)),
+            Functions::Zeros | Functions::Ones => {
+                let m = dimension(first, &name, span)?;
+                let n = match numbers.next() {
+                    Some(arg) => dimension(arg, &name, span)?,
+                    None => m,
+                };
+                let mut matrix = Matrix::new_empty(m, n);
+                if *self == Functions::Ones {
+                    matrix.fill(1.0);
+                }
+                Ok(Value::Matrix(matrix).into_mode(mode))
+            }
+            Functions::Norm | Functions::Normalize => {
+                let u = first.into_float();
+                let u = vector(&u, &name, span)?;
+                // Euclidean norm unless `p` is given
+                let p = match numbers.next().map(Numbers::into_float) {
+                    Some(Typed::Scalar(p)) if p >= 1.0 => p,
+                    Some(_) => return Err(EvalError::InvalidArgument { name, span }),
+                    None => 2.0,
+                };
+                let length = norm(u, p).map_err(|_| EvalError::InvalidArgument { name: name.clone(), span })?;
+                match self {
+                    Functions::Norm => Ok(Value::Scalar(length)),
+                    _ if length == 0.0 => Err(EvalError::ZeroVector { name, span }),
+                    _ => normalize(u).map(Value::Matrix).map_err(|_| EvalError::ZeroVector { name, span }),
+                }
+            }
+            Functions::Angle => {
+                let second = numbers.next().expect("angle takes two arguments");
+                let (u, v) = (first.into_float(), second.into_float());
+                let (u, v) = vectors(&u, &v, &name, span)?;
+                angle(u, v).map(Value::Scalar).map_err(|_| EvalError::ZeroVector { name, span })
+            }
+            // Exact by normal equations, since fractions have no rounding errors, and by QR otherwise
+            Functions::Lstsq | Functions::LstsqResiduo => {
+                let second = numbers.next().expect("lstsq takes two arguments");
+                let residual = *self == Functions::LstsqResiduo;
+                match (first, second) {
+                    (Numbers::Exact(Typed::Matrix(a)), Numbers::Exact(Typed::Matrix(b))) => {
+                        least_squares(&a, &b, least_squares_normal, residual, name, span).map(Value::from)
+                    }
+                    (a, b) => match (a.into_float(), b.into_float()) {
+                        (Typed::Matrix(a), Typed::Matrix(b)) => {
+                            least_squares(&a, &b, least_squares_qr, residual, name, span).map(Value::from)
+                        }
+                        _ => Err(EvalError::InvalidArgument { name, span }),
+                    },
+                }
+            }
+            // `[Q R]`, since a function gives a single value. `Q` is m x m, so `R` starts at column m
+            Functions::Qr => match first.into_float() {
+                Typed::Matrix(matrix) => {
+                    let factors = qr(&matrix);
+                    let mut result = Matrix::new_empty(matrix.m, matrix.m + matrix.n);
+                    set_submatrix(&mut result, 0, 0, &factors.q).expect("Q fits in the first m columns");
+                    set_submatrix(&mut result, 0, matrix.m, &factors.r).expect("R fits in the last n columns");
+                    Ok(Value::Matrix(result))
+                }
+                Typed::Scalar(_) => Err(EvalError::InvalidArgument { name, span }),
+            },
+            // Orthonormal basis of the column space, modified unless the classic version is asked for. Dependent
+            // columns would be skipped, so they are reported instead of giving fewer vectors than columns
+            Functions::GramSchmidt | Functions::GramSchmidtClasico => match first.into_float() {
+                Typed::Matrix(matrix) => match gram_schmidt(&matrix, *self == Functions::GramSchmidt) {
+                    basis if basis.n < matrix.n => Err(EvalError::RankDeficient { name, rank: basis.n, cols: matrix.n, span }),
+                    basis => Ok(Value::Matrix(basis)),
+                },
+                Typed::Scalar(_) => Err(EvalError::InvalidArgument { name, span }),
+            },
+            // Fractions are converted to floats, since the factor has square roots
+            Functions::Cholesky | Functions::IsPosdef => match first.into_float() {
+                Typed::Matrix(matrix) if !matrix.is_squared() => Err(EvalError::NotSquare { op: name, shape: (matrix.m, matrix.n), span }),
+                Typed::Matrix(matrix) if *self == Functions::IsPosdef => Ok(Value::Scalar(truth(is_posdef(&matrix))).into_mode(mode)),
+                Typed::Matrix(matrix) => {
+                    cholesky(&matrix).map(Value::Matrix).map_err(|e| EvalError::not_positive_definite(e.as_ref(), name, span))
+                }
+                Typed::Scalar(_) => Err(EvalError::InvalidArgument { name, span }),
+            },
+            // Like the operands of an operator: exact or modular only if all of them are, and complex if any is
+            Functions::LuSolve => {
+                let args: Vec<Numbers> = std::iter::once(first).chain(numbers).collect();
+                if args.iter().all(Numbers::is_exact) {
+                    let args = args.into_iter().filter_map(Numbers::into_exact).collect();
+                    factored_solve(args, name, span).map(Value::from)
+                } else if let (Mode::Modular(modulus), true) = (mode, args.iter().all(|arg| matches!(arg, Numbers::Modular(_)))) {
+                    let args = args.into_iter().filter_map(|arg| arg.into_modular(modulus)).collect();
+                    factored_solve(args, name, span).map(Value::from)
+                } else if args.iter().any(|arg| matches!(arg, Numbers::Complex(_))) {
+                    factored_solve(args.into_iter().map(Numbers::into_complex).collect(), name, span).map(Value::from)
+                } else {
+                    factored_solve(args.into_iter().map(Numbers::into_float).collect(), name, span).map(Value::from)
+                }
+            }
+            Functions::Dot | Functions::Cross | Functions::Proj => {
+                let second = numbers.next().expect("Vector functions take two arguments");
+                match (first, second) {
+                    (Numbers::Exact(u), Numbers::Exact(v)) => self.call_vectors(&u, &v, name, span).map(Value::from),
+                    (Numbers::Modular(u), Numbers::Modular(v)) => self.call_vectors(&u, &v, name, span).map(Value::from),
+                    (u, v) => self.call_vectors(&u.into_float(), &v.into_float(), name, span).map(Value::from),
+                }
+            }
+        }
+    }
+
+    // Functions of a single matrix: `det`, `det_cofactores`, `inv`, `inv_adjunta`, `adj`, `trace`, `rank`, `rref`, the
+    // fundamental subspaces and the predicates, which give 1 or 0
+    fn call_matrix<T: Number>(&self, matrix: &Matrix<T>, name: String, span: Span) -> Result<Typed<T>, EvalError> {
+        let not_square = EvalError::NotSquare { op: name, shape: (matrix.m, matrix.n), span };
+        match self {
+            Functions::Det => det(matrix).map(Typed::Scalar).map_err(|_| not_square),
+            Functions::DetCofactores => det_cofactores(matrix).map(Typed::Scalar).map_err(|_| not_square),
+            Functions::Trace => trace(matrix).map(Typed::Scalar).map_err(|_| not_square),
+            Functions::Adj => adj(matrix).map(Typed::Matrix).map_err(|_| not_square),
+            Functions::Rank => Ok(Typed::Scalar(T::from_usize(rank(matrix)).unwrap_or_else(T::zero))),
+            Functions::Rref => Ok(Typed::Matrix(rref(matrix).matrix)),
+            Functions::Nullity => Ok(Typed::Scalar(T::from_usize(nullity(matrix)).unwrap_or_else(T::zero))),
+            Functions::Nullspace => Ok(Typed::Matrix(nullspace(matrix))),
+            Functions::Colspace => Ok(Typed::Matrix(colspace(matrix))),
+            Functions::Rowspace => Ok(Typed::Matrix(rowspace(matrix))),
+            Functions::Leftnull => Ok(Typed::Matrix(leftnull(matrix))),
+            Functions::IsSymmetric => Ok(Typed::Scalar(truth(matrix.is_symmetric()))),
+            Functions::IsOrthogonal => Ok(Typed::Scalar(truth(matrix.is_orthogonal()))),
+            Functions::IsDiagonal => Ok(Typed::Scalar(truth(matrix.is_diagonal()))),
+            Functions::IsTriangular => Ok(Typed::Scalar(truth(matrix.is_triangular()))),
+            _ => {
+                if !matrix.is_squared() {
+                    return Err(not_square);
+                }
+                let inverse = if *self == Functions::InvAdjunta { inv_adjunta(matrix) } else { inv(matrix) };
+                inverse.map(Typed::Matrix).map_err(|e| EvalError::singular(e.as_ref(), matrix.n, span))
+            }
+        }
+    }
+
+    // `dot`, `cross` and `proj`
+    fn call_vectors<T: Number>(&self, u: &Typed<T>, v: &Typed<T>, name: String, span: Span) -> Result<Typed<T>, EvalError> {
+        let (u, v) = vectors(u, v, &name, span)?;
+        match self {
+            Functions::Dot => dot(u, v).map(Typed::Scalar).map_err(|_| EvalError::InvalidArgument { name, span }),
+            Functions::Cross => cross(u, v).map(Typed::Matrix).map_err(|_| EvalError::InvalidArgument { name, span }),
+            _ => proj(u, v).map(Typed::Matrix).map_err(|_| EvalError::ZeroVector { name, span }),
+        }
+    }
+}
+
+impl fmt::Display for Functions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match FUNCTIONS.entries().find(|(_, function)| *function == self) {
+            Some((name, _)) => write!(f, "{}", name),
+            None => write!(f, "{:?}", self),
+        }
+    }
+}
+
+// `lusolve(P, L, U, B)` solves `A X = B` with the factors given by the `lu` command for `A`
+fn factored_solve<T: Number>(args: Vec<Typed<T>>, name: String, span: Span) -> Result<Typed<T>, EvalError> {
+    let mut matrices = Vec::new();
+    for arg in args {
+        match arg {
+            Typed::Matrix(matrix) => matrices.push(matrix),
+            Typed::Scalar(_) => return Err(EvalError::InvalidArgument { name, span }),
+        }
+    }
+    let [p, l, u, b]: [Matrix<T>; 4] = matrices.try_into().map_err(|_| EvalError::InvalidArgument { name: name.clone(), span })?;
+    if !l.is_squared() {
+        return Err(EvalError::NotSquare { op: name, shape: (l.m, l.n), span });
+    }
+    let mismatch = [&p, &u].into_iter().find(|other| (other.m, other.n) != (l.m, l.n)).or((b.m != l.m).then_some(&b));
+    if let Some(other) = mismatch {
+        return Err(EvalError::DimensionMismatch { op: name, left: (l.m, l.n), right: (other.m, other.n), span });
+    }
+    let n = l.n;
+    lu_solve(&Lu { p, l, u }, &b).map(Typed::Matrix).map_err(|e| EvalError::singular(e.as_ref(), n, span))
+}
+
+// `least_squares_normal` or `least_squares_qr`
+type LeastSquaresMethod<T> = fn(&Matrix<T>, &Matrix<T>) -> Result<LeastSquares<T>, Box<dyn std::error::Error>>;
+
+// Best fit `x` of `lstsq(A, b)`, or the residual `b - A x` of `lstsq_residuo` if `residual` is set. `b` must be a
+// column with a row for each row of `A`
+fn least_squares<T: Number>(
+    a: &Matrix<T>,
+    b: &Matrix<T>,
+    method: LeastSquaresMethod<T>,
+    residual: bool,
+    name: String,
+    span: Span,
+) -> Result<Typed<T>, EvalError> {
+    if a.m != b.m || b.n != 1 {
+        return Err(EvalError::DimensionMismatch { op: name, left: (a.m, a.n), right: (b.m, b.n), span });
+    }
+    let solution = method(a, b).map_err(|e| EvalError::rank_deficient(e.as_ref(), name, a.n, span))?;
+    Ok(Typed::Matrix(if residual { solution.residual } else { solution.x }))
+}
+
+// Predicates answer 1 for true and 0 for false
+fn truth<T: Number>(value: bool) -> T {
+    if value { T::one() } else { T::zero() }
+}
+
+// Scalar functions applied to a matrix work element by element. Fractions are converted to floats first, and
+// complex numbers use the complex version `g` of the function
+fn map_elements(numbers: Numbers, f: fn(f64) -> f64, g: fn(Complex) -> Complex) -> Value {
+    match numbers {
+        Numbers::Complex(value) => Value::from(value.map(|num| g(*num))),
+        numbers => Value::from(numbers.into_float().map(|num| f(*num))),
+    }
+}
+
+// Row or column vector, of any length
+fn vector<'a, T>(value: &'a Typed<T>, name: &str, span: Span) -> Result<&'a Matrix<T>, EvalError> {
+    match value {
+        Typed::Matrix(matrix) if matrix.m == 1 || matrix.n == 1 => Ok(matrix),
+        _ => Err(EvalError::InvalidArgument { name: name.to_string(), span }),
+    }
+}
+
+// Two vectors of the same length, each one either a row or a column
+fn vectors<'a, T>(
+    u: &'a Typed<T>,
+    v: &'a Typed<T>,
+    name: &str,
+    span: Span,
+) -> Result<(&'a Matrix<T>, &'a Matrix<T>), EvalError> {
+    let (u, v) = (vector(u, name, span)?, vector(v, name, span)?);
+    if u.m * u.n != v.m * v.n {
+        return Err(EvalError::DimensionMismatch { op: name.to_string(), left: (u.m, u.n), right: (v.m, v.n), span });
+    }
+    Ok((u, v))
+}
+
+// Sizes given as arguments must be positive integers
+fn dimension(numbers: Numbers, name: &str, span: Span) -> Result<usize, EvalError> {
+    match Value::from(numbers.into_float()).as_integer() {
+        Some(size) if size >= 1 => Ok(size as usize),
+        _ => Err(EvalError::InvalidArgument { name: name.to_string(), span }),
+    }
+}
@@ -0,0 +1,292 @@
+// Analizador léxico: convierte el texto de una expresión en tokens tipados
+use crate::{exp_interpreter::{Operators, OPERATIONS}, errors::EvalError};
+
+/// Posición de un token dentro de la expresión original, medida en caracteres.
+/// `end` no está incluido.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TokenKind {
+    Number(f64),
+    // Imaginary number, written as a number followed by `i` (`2i`, `0.5i`) or just `i`
+    Imaginary(f64),
+    Ident(String),
+    Operator(Operators),
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Comma,
+    Semicolon,
+    Colon,
+    // Produced by the parser once a `[...]` literal, a call or an index is read, never by the lexer
+    MatrixLiteral { rows: usize, cols: usize },
+    Call { name: String, argc: usize },
+    Index,
+    // Omitted end of a range, as in `A[:, 0]` or `A[1:, 0]`
+    OpenBound,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+impl Token {
+    pub fn new(kind: TokenKind, start: usize, end: usize) -> Token {
+        Token { kind, span: Span::new(start, end) }
+    }
+
+    pub fn as_operator(&self) -> Option<Operators> {
+        if let TokenKind::Operator(op) = self.kind {
+            Some(op)
+        } else {
+            None
+        }
+    }
+}
+
+// Los espacios son opcionales: `(A+B)*2`, `A  + B` y `2*PI` son válidos
+pub fn tokenize(input: &str) -> Result<Vec<Token>, EvalError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || (c == '.' && chars.get(i + 1).is_some_and(|x| x.is_ascii_digit())) {
+            i = scan_number(&chars, i);
+            let text: String = chars[start..i].iter().collect();
+            let value = text.parse::<f64>().map_err(|_| EvalError::InvalidNumber { span: Span::new(start, i) })?;
+            // `2i` es imaginario, pero `2in` es `2` seguido del identificador `in`
+            if chars.get(i) == Some(&'i') && !chars.get(i + 1).is_some_and(|c| c.is_alphanumeric() || *c == '_') {
+                i += 1;
+                tokens.push(Token::new(TokenKind::Imaginary(value), start, i));
+            } else {
+                tokens.push(Token::new(TokenKind::Number(value), start, i));
+            }
+        } else if c.is_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect();
+            // Las palabras reservadas (`T`, `H`, `DET`, `INV`) son operadores, e `i` es la unidad imaginaria
+            if let Some(op) = OPERATIONS.get(word.as_str()) {
+                tokens.push(Token::new(TokenKind::Operator(*op), start, i));
+            } else if word == "i" {
+                tokens.push(Token::new(TokenKind::Imaginary(1.0), start, i));
+            } else {
+                tokens.push(Token::new(TokenKind::Ident(word), start, i));
+            }
+        } else if c == '(' {
+            i += 1;
+            tokens.push(Token::new(TokenKind::LParen, start, i));
+        } else if c == ')' {
+            i += 1;
+            tokens.push(Token::new(TokenKind::RParen, start, i));
+        } else if c == '[' {
+            i += 1;
+            tokens.push(Token::new(TokenKind::LBracket, start, i));
+        } else if c == ']' {
+            i += 1;
+            tokens.push(Token::new(TokenKind::RBracket, start, i));
+        } else if c == ',' {
+            i += 1;
+            tokens.push(Token::new(TokenKind::Comma, start, i));
+        } else if c == ';' {
+            i += 1;
+            tokens.push(Token::new(TokenKind::Semicolon, start, i));
+        } else if c == ':' {
+            i += 1;
+            tokens.push(Token::new(TokenKind::Colon, start, i));
+        } else if let Some(op) = chars.get(i + 1).and_then(|next| OPERATIONS.get(format!("{}{}", c, next).as_str())) {
+            // Element-wise operators: `.*`, `./`, `.^`
+            i += 2;
+            tokens.push(Token::new(TokenKind::Operator(*op), start, i));
+        } else if let Some(op) = OPERATIONS.get(c.to_string().as_str()) {
+            i += 1;
+            tokens.push(Token::new(TokenKind::Operator(*op), start, i));
+        } else {
+            return Err(EvalError::InvalidCharacter { ch: c, span: Span::new(start, start + 1) });
+        }
+    }
+    Ok(tokens)
+}
+
+// Avanza sobre un número con parte decimal y exponente opcionales (`12`, `1.5`, `.5`, `2e-3`)
+fn scan_number(chars: &[char], mut i: usize) -> usize {
+    while i < chars.len() && chars[i].is_ascii_digit() {
+        i += 1;
+    }
+    // `2.*A` is `2 .* A`
+    if i < chars.len() && chars[i] == '.' && !chars.get(i + 1).is_some_and(|c| matches!(c, '*' | '/' | '^')) {
+        i += 1;
+        while i < chars.len() && chars[i].is_ascii_digit() {
+            i += 1;
+        }
+    }
+    // El exponente solo se consume si hay dígitos, para que `2E` sea `2` seguido del identificador `E`
+    if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+        let mut j = i + 1;
+        if j < chars.len() && (chars[j] == '+' || chars[j] == '-') {
+            j += 1;
+        }
+        if j < chars.len() && chars[j].is_ascii_digit() {
+            i = j;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+        }
+    }
+    i
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(input: &str) -> Vec<TokenKind> {
+        tokenize(input).unwrap().into_iter().map(|t| t.kind).collect()
+    }
+
+    #[test]
+    fn spacing_does_not_matter() {
+        let expected = vec![
+            TokenKind::LParen,
+            TokenKind::Ident(String::from("A")),
+            TokenKind::Operator(Operators::Sum),
+            TokenKind::Ident(String::from("B")),
+            TokenKind::RParen,
+            TokenKind::Operator(Operators::Mul),
+            TokenKind::Number(2.0),
+        ];
+        assert_eq!(kinds("(A+B)*2"), expected);
+        assert_eq!(kinds("( A  +  B )  * 2"), expected);
+        assert_eq!(kinds("\t(A +B)*   2 "), expected);
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(kinds("12 1.5 .25 2e3 1.5E-2 4e+1"), vec![
+            TokenKind::Number(12.0),
+            TokenKind::Number(1.5),
+            TokenKind::Number(0.25),
+            TokenKind::Number(2000.0),
+            TokenKind::Number(0.015),
+            TokenKind::Number(40.0),
+        ]);
+        // Sin dígitos en el exponente la `E` es un identificador
+        assert_eq!(kinds("2E"), vec![TokenKind::Number(2.0), TokenKind::Ident(String::from("E"))]);
+        assert_eq!(kinds("2*PI"), vec![
+            TokenKind::Number(2.0),
+            TokenKind::Operator(Operators::Mul),
+            TokenKind::Ident(String::from("PI")),
+        ]);
+    }
+
+    #[test]
+    fn imaginary_numbers() {
+        assert_eq!(kinds("3+2i - i * .5i"), vec![
+            TokenKind::Number(3.0),
+            TokenKind::Operator(Operators::Sum),
+            TokenKind::Imaginary(2.0),
+            TokenKind::Operator(Operators::Sub),
+            TokenKind::Imaginary(1.0),
+            TokenKind::Operator(Operators::Mul),
+            TokenKind::Imaginary(0.5),
+        ]);
+        assert_eq!(kinds("2in ix 1e2i"), vec![
+            TokenKind::Number(2.0),
+            TokenKind::Ident(String::from("in")),
+            TokenKind::Ident(String::from("ix")),
+            TokenKind::Imaginary(100.0),
+        ]);
+    }
+
+    #[test]
+    fn element_wise_operators() {
+        assert_eq!(kinds("A.*B ./ 2.^.5 2.*A"), vec![
+            TokenKind::Ident(String::from("A")),
+            TokenKind::Operator(Operators::ElemMul),
+            TokenKind::Ident(String::from("B")),
+            TokenKind::Operator(Operators::ElemDiv),
+            TokenKind::Number(2.0),
+            TokenKind::Operator(Operators::ElemPow),
+            TokenKind::Number(0.5),
+            TokenKind::Number(2.0),
+            TokenKind::Operator(Operators::ElemMul),
+            TokenKind::Ident(String::from("A")),
+        ]);
+        assert_eq!(
+            tokenize("A . B").unwrap_err(),
+            EvalError::InvalidCharacter { ch: '.', span: Span::new(2, 3) }
+        );
+    }
+
+    #[test]
+    fn keywords() {
+        assert_eq!(kinds("A T H DET INV TA"), vec![
+            TokenKind::Ident(String::from("A")),
+            TokenKind::Operator(Operators::Transp),
+            TokenKind::Operator(Operators::ConjTransp),
+            TokenKind::Operator(Operators::Det),
+            TokenKind::Operator(Operators::Inv),
+            TokenKind::Ident(String::from("TA")),
+        ]);
+    }
+
+    #[test]
+    fn brackets() {
+        assert_eq!(kinds("A[0:2, :]"), vec![
+            TokenKind::Ident(String::from("A")),
+            TokenKind::LBracket,
+            TokenKind::Number(0.0),
+            TokenKind::Colon,
+            TokenKind::Number(2.0),
+            TokenKind::Comma,
+            TokenKind::Colon,
+            TokenKind::RBracket,
+        ]);
+        assert_eq!(kinds("[1, 2; 3 4]"), vec![
+            TokenKind::LBracket,
+            TokenKind::Number(1.0),
+            TokenKind::Comma,
+            TokenKind::Number(2.0),
+            TokenKind::Semicolon,
+            TokenKind::Number(3.0),
+            TokenKind::Number(4.0),
+            TokenKind::RBracket,
+        ]);
+    }
+
+    #[test]
+    fn spans() {
+        let tokens = tokenize("A+  DET2").unwrap();
+        assert_eq!(tokens[0].span, Span::new(0, 1));
+        assert_eq!(tokens[1].span, Span::new(1, 2));
+        assert_eq!(tokens[2].span, Span::new(4, 8));
+        assert_eq!(tokens[2].kind, TokenKind::Ident(String::from("DET2")));
+    }
+
+    #[test]
+    fn invalid_character() {
+        assert_eq!(
+            tokenize("A $ B").unwrap_err(),
+            EvalError::InvalidCharacter { ch: '$', span: Span::new(2, 3) }
+        );
+    }
+}
@@ -1,6 +1,10 @@
 mod math;
+mod number;
 mod structs;
 mod exp_interpreter;
+mod lexer;
+mod errors;
+mod functions;
 mod app;
 
 use app::App;
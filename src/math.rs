@@ -1,39 +1,92 @@
+use crate::number::Number;
 use crate::structs::Matrix;
-use std::error::Error;
+use std::{cell::Cell, error::Error, fmt, ops::Range};
 
-pub fn sum(ma: &Matrix, mb: &Matrix) -> Result<Matrix, Box<dyn Error>> {
-    if ma.m != mb.m || ma.n != mb.n {
-        return Err("bad dimensions")?;
+pub fn sum<T: Number>(ma: &Matrix<T>, mb: &Matrix<T>) -> Result<Matrix<T>, Box<dyn Error>> {
+    broadcast(ma, mb, |a, b| a.clone() + b.clone())
+}
+
+pub fn sub<T: Number>(ma: &Matrix<T>, mb: &Matrix<T>) -> Result<Matrix<T>, Box<dyn Error>> {
+    broadcast(ma, mb, |a, b| a.clone() - b.clone())
+}
+
+// Producto de Hadamard: elemento a elemento
+pub fn elem_mul<T: Number>(ma: &Matrix<T>, mb: &Matrix<T>) -> Result<Matrix<T>, Box<dyn Error>> {
+    broadcast(ma, mb, |a, b| a.clone() * b.clone())
+}
+
+pub fn elem_div<T: Number>(ma: &Matrix<T>, mb: &Matrix<T>) -> Result<Matrix<T>, Box<dyn Error>> {
+    broadcast(ma, mb, |a, b| a.clone() / b.clone())
+}
+
+// Falla con `PowerTooLarge` si alguna de las potencias no se puede calcular
+pub fn elem_pow<T: Number>(ma: &Matrix<T>, mb: &Matrix<T>) -> Result<Matrix<T>, Box<dyn Error>> {
+    let too_large = Cell::new(false);
+    let res = broadcast(ma, mb, |a, b| {
+        a.power(b).unwrap_or_else(|| {
+            too_large.set(true);
+            T::zero()
+        })
+    })?;
+    if too_large.get() {
+        return Err(PowerTooLarge.into());
+    }
+    Ok(res)
+}
+
+// Error de una potencia exacta cuyo resultado sería demasiado grande para calcularlo
+#[derive(Debug)]
+pub struct PowerTooLarge;
+
+impl fmt::Display for PowerTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Power too large")
     }
-    let mut mc = Matrix::new_empty(ma.m, ma.n);
-    for i in 0..ma.n {
-        for j in 0..ma.m {
-            mc.set(i, j, ma[i][j] + mb[i][j]);
+}
+
+impl Error for PowerTooLarge {}
+
+// Aplica `f` elemento a elemento. Si en una dimensión uno de los operandos mide 1 (una matriz 1x1, un vector
+// fila o columna) se repite a lo largo de esa dimensión del otro. Por ejemplo 2x3 y 1x3 dan 2x3, pero 2x3 y 2x2
+// son incompatibles
+pub fn broadcast<A, B, T: Number>(
+    ma: &Matrix<A>,
+    mb: &Matrix<B>,
+    f: impl Fn(&A, &B) -> T,
+) -> Result<Matrix<T>, Box<dyn Error>> {
+    let m = broadcast_dimension(ma.m, mb.m).ok_or("Bad dimensions")?;
+    let n = broadcast_dimension(ma.n, mb.n).ok_or("Bad dimensions")?;
+    let mut res = Matrix::new_empty(m, n);
+    for i in 0..m {
+        for j in 0..n {
+            res.set(i, j, f(&ma[i % ma.m][j % ma.n], &mb[i % mb.m][j % mb.n]));
         }
     }
-    return Ok(mc);
+    Ok(res)
 }
 
-pub fn sub(ma: &Matrix, mb: &Matrix) -> Result<Matrix, Box<dyn Error>> {
-    if ma.m != mb.m || ma.n != mb.n {
-        return Err("bad dimensions")?;
+fn broadcast_dimension(a: usize, b: usize) -> Option<usize> {
+    if a == b || b == 1 {
+        Some(a)
+    } else if a == 1 {
+        Some(b)
+    } else {
+        None
     }
-    let mb = &mul_scalar(mb, -1.0);
-    return sum(ma, mb);
 }
 
-pub fn mul(m1: &Matrix, m2: &Matrix) -> Result<Matrix, Box<dyn Error>> {
+pub fn mul<T: Number>(m1: &Matrix<T>, m2: &Matrix<T>) -> Result<Matrix<T>, Box<dyn Error>> {
     if m1.n != m2.m {
         return Err("Bad dimensions")?;
     };
 
-    let mut res: Matrix = Matrix::new_empty(m1.m, m2.n);
+    let mut res: Matrix<T> = Matrix::new_empty(m1.m, m2.n);
 
     for i in 0..res.m {
         for j in 0..res.n {
-            let mut value = 0.0;
+            let mut value = T::zero();
             for k in 0..m1.n {
-                value += m1[i][k] * m2[k][j];
+                value = value + m1[i][k].clone() * m2[k][j].clone();
             }
             res.set(i, j, value);
         }
@@ -42,45 +95,130 @@ pub fn mul(m1: &Matrix, m2: &Matrix) -> Result<Matrix, Box<dyn Error>> {
     return Ok(res);
 }
 
-pub fn pow(mat: &Matrix, exp: i8) -> Result<Matrix, Box<dyn Error>> {
+// Potencia por cuadrados sucesivos, con O(log exp) productos. Falla con `PowerTooLarge` si los elementos crecen
+// tanto que seguir multiplicando colgaría la calculadora, como con las fracciones exactas
+pub fn pow<T: Number>(mat: &Matrix<T>, exp: u32) -> Result<Matrix<T>, Box<dyn Error>> {
     if !mat.is_squared() {
-        return Err("Bad dimensions")?;
-    } else if exp == 0 {
-        return Ok(id_matrix(mat.n));
-    } else if exp == 1 {
-        return Ok(mat.clone());
-    }
-    let mut res = mat.clone();
-    for _ in 1..exp {
-        if let Ok(_res) = mul(&res, &mat) {
-            res = _res;
-        } else {
-            return Err("Bad dimensions")?;
+        return Err("Bad dimensions".into());
+    }
+    let mut res = id_matrix(mat.n);
+    let mut base = mat.clone();
+    let mut exp = exp;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            res = mul(&res, &base)?;
+        }
+        exp >>= 1;
+        if exp > 0 {
+            base = mul(&base, &base)?;
+        }
+        if [&res, &base].iter().any(|m| (0..m.m).any(|i| m[i].iter().any(Number::is_huge))) {
+            return Err(PowerTooLarge.into());
         }
     }
-    return Ok(res);
+    Ok(res)
 }
 
-pub fn mul_scalar(mat: &Matrix, num: f32) -> Matrix {
+pub fn mul_scalar<T: Number>(mat: &Matrix<T>, num: T) -> Matrix<T> {
     let mut res = Matrix::new_empty(mat.m, mat.n);
     for i in 0..mat.m {
         for j in 0..mat.n {
-            res.set(i, j, mat[i][j] * num);
+            res.set(i, j, mat[i][j].clone() * num.clone());
         }
     }
     return res;
 }
 
-// Calcula el determinante de la matriz mediante el desarrollo por cofactores
-pub fn det(m: &Matrix) -> Result<f32, Box<dyn Error>> {
+// Calcula el determinante por eliminación gaussiana, en O(n³). Los tipos exactos usan el algoritmo de Bareiss
+// y el resto pivoteo parcial
+pub fn det<T: Number>(m: &Matrix<T>) -> Result<T, Box<dyn Error>> {
+    if !m.is_squared() || m.m == 0 || m.n == 0 {
+        return Err("Bad dimensions")?;
+    }
+    if T::EXACT {
+        Ok(det_bareiss(m))
+    } else {
+        Ok(det_lu(m))
+    }
+}
+
+// Calcula el determinante de la matriz mediante el desarrollo por cofactores. Es O(n!), así que solo sirve
+// para matrices chicas, pero es el método que se enseña a mano
+pub fn det_cofactores<T: Number>(m: &Matrix<T>) -> Result<T, Box<dyn Error>> {
     if !m.is_squared() || m.m == 0 || m.n == 0 {
         return Err("Bad dimensions")?;
     }
     return Ok(_det_recursivo(&m, &vec![false; m.n], &vec![false; m.m]));
 }
 
-fn _det_recursivo(m: &Matrix, hidden_rows: &Vec<bool>, hidden_cols: &Vec<bool>) -> f32 {
-    let mut sum = 0.0;
+// Producto de los pivotes de la factorización LU. Cada intercambio de filas cambia el signo
+fn det_lu<T: Number>(m: &Matrix<T>) -> T {
+    let n = m.n;
+    let mut a = m.clone();
+    let mut res = T::one();
+    // Con la misma tolerancia que `rank` e `inv`, así una matriz singular da cero y no un error de redondeo
+    let scale = scale(m);
+    for j in 0..n {
+        // El pivote de mayor tamaño es el que menos error de redondeo agrega
+        let mut max_row = j;
+        for i in j+1..n {
+            if a[i][j].magnitude() > a[max_row][j].magnitude() {
+                max_row = i;
+            }
+        }
+        if a[max_row][j].is_negligible(scale) {
+            return T::zero();
+        }
+        if max_row != j {
+            swap_rows(&mut a, j, max_row);
+            res = -res;
+        }
+        let pivot = a[j][j].clone();
+        for i in j+1..n {
+            let factor = a[i][j].clone() / pivot.clone();
+            for k in j+1..n {
+                a.set(i, k, a[i][k].clone() - factor.clone() * a[j][k].clone());
+            }
+        }
+        res = res * pivot;
+    }
+    res
+}
+
+// Algoritmo de Bareiss: cada paso divide por el pivote anterior, y la división es exacta. Con elementos enteros
+// los valores intermedios son enteros, y con fracciones no crecen como en la eliminación común
+fn det_bareiss<T: Number>(m: &Matrix<T>) -> T {
+    let n = m.n;
+    let mut a = m.clone();
+    let mut sign = T::one();
+    let mut previous = T::one();
+    for k in 0..n-1 {
+        if a[k][k].is_zero() {
+            match (k+1..n).find(|&i| !a[i][k].is_zero()) {
+                Some(i) => {
+                    swap_rows(&mut a, k, i);
+                    sign = -sign;
+                }
+                None => return T::zero(),
+            }
+        }
+        for i in k+1..n {
+            for j in k+1..n {
+                let value = a[k][k].clone() * a[i][j].clone() - a[i][k].clone() * a[k][j].clone();
+                a.set(i, j, value / previous.clone());
+            }
+        }
+        previous = a[k][k].clone();
+    }
+    sign * a[n-1][n-1].clone()
+}
+
+fn _det_recursivo<T: Number>(m: &Matrix<T>, hidden_rows: &Vec<bool>, hidden_cols: &Vec<bool>) -> T {
+    // El menor de una matriz de 1x1 no tiene elementos, y su determinante es 1
+    if hidden_rows.iter().all(|&x| x) {
+        return T::one();
+    }
+    let mut sum = T::zero();
     let mut sign_positive = true;
     let mut hr: Vec<bool> = hidden_rows.clone();
     let mut hc: Vec<bool> = hidden_cols.clone();
@@ -91,13 +229,14 @@ fn _det_recursivo(m: &Matrix, hidden_rows: &Vec<bool>, hidden_cols: &Vec<bool>)
             if hc[j] { continue; }
             // Si lo que queda es una submatriz 1x1, devolver ese valor
             if hr.iter().filter(|&&x| !x).count() == 1 && hc.iter().filter(|&&x| !x).count() == 1 {
-                return m[i][j];
+                return m[i][j].clone();
             }
             // Elijo esta celda para "tapar" fila y columna
             hr[i] = true;
             hc[j] = true;
             let inner_det = _det_recursivo(m, &hr, &hc);
-            sum += m[i][j] * inner_det * (if sign_positive {1.0} else {-1.0}) ;
+            let term = m[i][j].clone() * inner_det;
+            sum = if sign_positive { sum + term } else { sum - term };
             sign_positive = !sign_positive;
             // Destapo antes de seguir
             hr[i] = false;
@@ -108,38 +247,44 @@ fn _det_recursivo(m: &Matrix, hidden_rows: &Vec<bool>, hidden_cols: &Vec<bool>)
     return sum;
 }
 
-pub fn id_matrix(n: usize) -> Matrix {
-    let mut res: Matrix = Matrix::new_empty(n, n);
+pub fn id_matrix<T: Number>(n: usize) -> Matrix<T> {
+    let mut res: Matrix<T> = Matrix::new_empty(n, n);
     for i in 0..n {
-        res.set(i, i, 1.0);
+        res.set(i, i, T::one());
     }
     return res;
 }
 
-pub fn transpose(m: &Matrix) -> Result<Matrix, Box<dyn Error>> {
-    let mut res: Matrix = Matrix::new_empty(m.n, m.m);
+pub fn transpose<T: Number>(m: &Matrix<T>) -> Result<Matrix<T>, Box<dyn Error>> {
+    let mut res: Matrix<T> = Matrix::new_empty(m.n, m.m);
     for i in 0..m.m {
         for j in 0..m.n {
-            res.set(j, i, m[i][j]);
+            res.set(j, i, m[i][j].clone());
         }
     }
     return Ok(res);
 }
 
+// Traspuesta conjugada. Para matrices reales es la traspuesta
+pub fn conj_transpose<T: Number>(m: &Matrix<T>) -> Result<Matrix<T>, Box<dyn Error>> {
+    Ok(transpose(m)?.map(Number::conj))
+}
+
 // Matriz de cofactores (sin trasponer)
-pub fn adj(m:&Matrix) -> Result<Matrix, Box<dyn Error>>{
+pub fn adj<T: Number>(m:&Matrix<T>) -> Result<Matrix<T>, Box<dyn Error>>{
     if !m.is_squared() {
         return Err("Bad dimensions")?;
     }
     let mut sign: bool = true;
-    let mut res: Matrix = Matrix::new_empty(m.n, m.m);
+    let mut res: Matrix<T> = Matrix::new_empty(m.n, m.m);
     for i in 0..m.m {
         for j in 0..m.n {
             let mut hidden_rows: Vec<bool> = vec![false; m.m];
             let mut hidden_columns: Vec<bool> = vec![false; m.n];
             hidden_rows[i] = true;
             hidden_columns[j] = true;
-            res.set(i,j, (if sign {1.0} else {-1.0} * _det_recursivo(&m, &hidden_rows, &hidden_columns)));
+            let minor = _det_recursivo(&m, &hidden_rows, &hidden_columns);
+            res.set(i,j, if sign { minor } else { -minor });
             sign = !sign;
         }
         if m.m % 2 == 0 {
@@ -149,21 +294,79 @@ pub fn adj(m:&Matrix) -> Result<Matrix, Box<dyn Error>>{
     return Ok(res);
 }
 
-pub fn inv(m: &Matrix) -> Result<Matrix, Box<dyn Error>>{
-    if !m.is_squared(){
-        return Err("Bad dimensions")?;
-    } else {
-        let aux:f32 = det(&m).unwrap();
-        if aux != 0.0 {
-            let trasp: Matrix = transpose(&m).unwrap();
-            let adj: Matrix = adj(&trasp).unwrap(); // Calculo el adjunto de la traspuesta
-            let inverse: Matrix = mul_scalar(&adj, 1.0 / aux);
-            return Ok(inverse);
-        } else {
-            return Err("No inverse")?;
+// Error cuando la matriz no tiene rango completo, como `inv` de una matriz singular o mínimos cuadrados con columnas
+// dependientes. `rank` es el rango encontrado
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Singular {
+    pub rank: usize,
+}
+
+impl fmt::Display for Singular {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Singular matrix (rank {})", self.rank)
+    }
+}
+
+impl Error for Singular {}
+
+// Inversa por eliminación de Gauss-Jordan con pivoteo parcial, en O(n³): se reduce `[A | I]` hasta `[I | A⁻¹]`
+pub fn inv<T: Number>(m: &Matrix<T>) -> Result<Matrix<T>, Box<dyn Error>> {
+    if !m.is_squared() {
+        return Err("Bad dimensions".into());
+    }
+    let n = m.n;
+    let scale = scale(m);
+    let mut a = m.clone();
+    let mut res: Matrix<T> = id_matrix(n);
+    for j in 0..n {
+        let mut max_row = j;
+        for i in j+1..n {
+            if a[i][j].magnitude() > a[max_row][j].magnitude() {
+                max_row = i;
+            }
+        }
+        if a[max_row][j].is_negligible(scale) {
+            return Err(Box::new(Singular { rank: rank(m) }));
         }
+        if max_row != j {
+            swap_rows(&mut a, j, max_row);
+            swap_rows(&mut res, j, max_row);
+        }
+        // Dejar el pivote en 1 y anular el resto de la columna, arriba y abajo
+        let pivot = a[j][j].clone();
+        for k in 0..n {
+            a.set(j, k, a[j][k].clone() / pivot.clone());
+            res.set(j, k, res[j][k].clone() / pivot.clone());
+        }
+        for i in (0..n).filter(|&i| i != j) {
+            let factor = a[i][j].clone();
+            if factor.is_zero() {
+                continue;
+            }
+            for k in 0..n {
+                a.set(i, k, a[i][k].clone() - factor.clone() * a[j][k].clone());
+                res.set(i, k, res[i][k].clone() - factor.clone() * res[j][k].clone());
+            }
+        }
+    }
+    Ok(res)
+}
+
+// Inversa como la adjunta de la traspuesta dividida por el determinante, el método que se usa a mano. Calcula
+// n² determinantes por cofactores, así que solo sirve para matrices chicas
+pub fn inv_adjunta<T: Number>(m: &Matrix<T>) -> Result<Matrix<T>, Box<dyn Error>> {
+    if !m.is_squared() {
+        return Err("Bad dimensions".into());
     }
+    let aux: T = det_cofactores(m)?;
+    // El determinante es del tamaño de un producto de `n` elementos
+    if aux.is_negligible(scale(m).powi(m.n as i32)) {
+        return Err(Box::new(Singular { rank: rank(m) }));
+    }
+    let adj: Matrix<T> = adj(&transpose(m)?)?; // Calculo el adjunto de la traspuesta
+    Ok(mul_scalar(&adj, T::one() / aux))
 }
+
 fn data_loading(m: &Matrix, results: &Matrix) -> Matrix {
     let mut res: Matrix = Matrix::new_empty(m.n, m.m+1);
     for i in 0..m.n {
@@ -175,30 +378,40 @@ fn data_loading(m: &Matrix, results: &Matrix) -> Matrix {
     return res;
 }  
 
-fn swap_rows(m: &mut Matrix, i: usize, j: usize) {
+fn swap_rows<T: Number>(m: &mut Matrix<T>, i: usize, j: usize) {
     let n_cols = m[0].len();
-    let mut temp = vec![0.0; n_cols];
+    let mut temp = vec![T::zero(); n_cols];
     for col_index in 0..n_cols {
-        temp[col_index] = m[j][col_index];
-        m.set( j, col_index, m[i][col_index]);
-        m.set( i, col_index, temp[col_index]);
+        temp[col_index] = m[j][col_index].clone();
+        m.set( j, col_index, m[i][col_index].clone());
+        m.set( i, col_index, temp[col_index].clone());
     }
 }
 
-#[derive(PartialEq, Eq)]
-pub enum Compatibility {
-    CompatibleDeterminado,
-    CompatibleIndeterminado,
+// Solución general de un sistema compatible indeterminado: `particular` más cualquier combinación de los vectores
+// de `basis`, que generan el espacio nulo. `basis[k]` es el vector que multiplica a la incógnita libre `free[k]`
+#[derive(Debug, Clone)]
+pub struct GeneralSolution<T = f64> {
+    pub particular: Matrix<T>,
+    pub basis: Vec<Matrix<T>>,
+    pub free: Vec<usize>,
+}
+
+// Las soluciones son vectores columna con una fila por incógnita
+#[derive(Debug, Clone)]
+pub enum Compatibility<T = f64> {
+    CompatibleDeterminado(Matrix<T>),
+    CompatibleIndeterminado(GeneralSolution<T>),
     Incompatible,
 }
 
-impl Compatibility {
+impl<T> Compatibility<T> {
     /// Returns `true` if the compatibility is [`CompatibleDeterminado`].
     ///
     /// [`CompatibleDeterminado`]: Compatibility::CompatibleDeterminado
     #[must_use]
     pub fn is_compatible_determinado(&self) -> bool {
-        matches!(self, Self::CompatibleDeterminado)
+        matches!(self, Self::CompatibleDeterminado(_))
     }
 
     /// Returns `true` if the compatibility is [`CompatibleIndeterminado`].
@@ -206,7 +419,7 @@ impl Compatibility {
     /// [`CompatibleIndeterminado`]: Compatibility::CompatibleIndeterminado
     #[must_use]
     pub fn is_compatible_indeterminado(&self) -> bool {
-        matches!(self, Self::CompatibleIndeterminado)
+        matches!(self, Self::CompatibleIndeterminado(_))
     }
 
     /// Returns `true` if the compatibility is [`Incompatible`].
@@ -218,20 +431,25 @@ impl Compatibility {
     }
 }
 
-// toma la matriz aumentada
-pub fn solve_system(matrix: &Matrix) -> Compatibility {
+// Tamaño del mayor elemento, con el que se decide qué valores son errores de redondeo
+fn scale<T: Number>(m: &Matrix<T>) -> f64 {
+    m.scale()
+}
+
+// Escalona la matriz por eliminación gaussiana con pivoteo parcial. Cada pivote queda en 1
+fn row_echelon<T: Number>(matrix: &Matrix<T>) -> Matrix<T> {
     let m = matrix.m;
     let n = matrix.n;
+    let scale = scale(matrix);
     let mut matrix = matrix.clone();
 
-    // Paso 1: Escalonar la matriz aumentada
     let mut i = 0;
     let mut j = 0;
     while i < m && j < n {
         // Buscar el valor máximo en la columna j
         let mut max_row = i;
         for k in i+1..m {
-            if matrix[k][j].abs() > matrix[max_row][j].abs() {
+            if matrix[k][j].magnitude() > matrix[max_row][j].magnitude() {
                 max_row = k;
             }
         }
@@ -241,66 +459,648 @@ pub fn solve_system(matrix: &Matrix) -> Compatibility {
             swap_rows(&mut matrix, i, max_row);
         }
         // Si el valor máximo en la posición (i, j) es cero, entonces toda la columna es cero
-        if matrix[i][j] == 0.0 {
+        if matrix[i][j].is_negligible(scale) {
             j += 1;
             continue;
         }
 
         // Escalonar la fila i para tener un 1 en la posición (i, j)
-        let pivot = matrix[i][j];
+        let pivot = matrix[i][j].clone();
         for k in j..n {
-            matrix.set(i, k,  matrix[i][k] / pivot);
+            matrix.set(i, k,  matrix[i][k].clone() / pivot.clone());
         }
 
         // Restar la fila i a las filas siguientes para tener ceros debajo del pivote
         for k in i+1..m {
-            let factor = matrix[k][j];
-            for l in j..n {
-                matrix.set(k, l, matrix[k][l] - factor * matrix[i][l]);
+            let factor = matrix[k][j].clone();
+            for l in j+1..n {
+                matrix.set(k, l, matrix[k][l].clone() - factor.clone() * matrix[i][l].clone());
             }
+            // Sin el error de redondeo de la resta
+            matrix.set(k, j, T::zero());
         }
 
         i += 1;
         j += 1;
     }
+    matrix
+}
+
+// Cantidad de filas no nulas de la matriz escalonada
+pub fn rank<T: Number>(m: &Matrix<T>) -> usize {
+    let echelon = row_echelon(m);
+    let scale = scale(m);
+    (0..echelon.m).filter(|&i| echelon[i].iter().any(|x| !x.is_negligible(scale))).count()
+}
+
+// Operación elemental sobre las filas, numeradas desde 0
+#[derive(Debug, Clone, PartialEq)]
+pub enum RowOperation<T = f64> {
+    // Intercambiar dos filas
+    Swap(usize, usize),
+    // Multiplicar la fila por un escalar no nulo
+    Scale(usize, T),
+    // Sumar a `target` la fila `source` multiplicada por `factor`
+    AddMultiple { target: usize, source: usize, factor: T },
+}
+
+// Operación junto con la matriz que queda después de aplicarla
+#[derive(Debug, Clone)]
+pub struct Step<T = f64> {
+    pub operation: RowOperation<T>,
+    pub matrix: Matrix<T>,
+}
+
+// Forma escalonada reducida, las columnas de sus pivotes y las operaciones que llevaron a ella
+#[derive(Debug, Clone)]
+pub struct Rref<T = f64> {
+    pub matrix: Matrix<T>,
+    pub pivots: Vec<usize>,
+    pub steps: Vec<Step<T>>,
+}
+
+// Forma escalonada reducida por Gauss-Jordan: cada pivote queda en 1 y es el único elemento no nulo de su columna
+pub fn rref<T: Number>(matrix: &Matrix<T>) -> Rref<T> {
+    gauss_jordan(matrix, true)
+}
+
+// Cada paso guarda una copia de la matriz, así que solo se registran si `record` lo pide
+fn gauss_jordan<T: Number>(matrix: &Matrix<T>, record: bool) -> Rref<T> {
+    let scale = scale(matrix);
+    let mut a = matrix.clone();
+    let mut pivots = Vec::new();
+    let mut steps = Vec::new();
+    let mut i = 0;
+    for j in 0..a.n {
+        if i == a.m {
+            break;
+        }
+        // Con números exactos sirve cualquier pivote no nulo, y el primero es el que se elegiría a mano. Con
+        // decimales el de mayor tamaño es el que menos error de redondeo agrega
+        let mut pivot_row = None;
+        for k in i..a.m {
+            if a[k][j].is_negligible(scale) {
+                continue;
+            }
+            match pivot_row {
+                None => pivot_row = Some(k),
+                Some(p) if !T::EXACT && a[k][j].magnitude() > a[p][j].magnitude() => pivot_row = Some(k),
+                _ => {}
+            }
+        }
+        let Some(p) = pivot_row else {
+            continue;
+        };
+        if p != i {
+            swap_rows(&mut a, i, p);
+            if record {
+                steps.push(Step { operation: RowOperation::Swap(i, p), matrix: a.clone() });
+            }
+        }
+        if !a[i][j].is_one() {
+            let factor = T::one() / a[i][j].clone();
+            // Los ceros quedan como están, así un factor negativo no los convierte en `-0`
+            for k in 0..a.n {
+                if !a[i][k].is_zero() {
+                    a.set(i, k, a[i][k].clone() * factor.clone());
+                }
+            }
+            a.set(i, j, T::one());
+            if record {
+                steps.push(Step { operation: RowOperation::Scale(i, factor), matrix: a.clone() });
+            }
+        }
+        for r in (0..a.m).filter(|&r| r != i) {
+            // Los errores de redondeo se anulan sin contarlos como una operación
+            if a[r][j].is_negligible(scale) {
+                a.set(r, j, T::zero());
+                continue;
+            }
+            let factor = -a[r][j].clone();
+            for k in 0..a.n {
+                a.set(r, k, a[r][k].clone() + factor.clone() * a[i][k].clone());
+            }
+            a.set(r, j, T::zero());
+            if record {
+                steps.push(Step { operation: RowOperation::AddMultiple { target: r, source: i, factor }, matrix: a.clone() });
+            }
+        }
+        pivots.push(j);
+        i += 1;
+    }
+    Rref { matrix: a, pivots, steps }
+}
+
+// Dimensión del espacio nulo. Por el teorema de la dimensión, `rank(A) + nullity(A)` es la cantidad de columnas
+pub fn nullity<T: Number>(m: &Matrix<T>) -> usize {
+    m.n - gauss_jordan(m, false).pivots.len()
+}
+
+// Base del espacio nulo, las soluciones de `A x = 0`, con un vector columna por cada columna sin pivote. Si la única
+// solución es el cero la matriz no tiene columnas, y lo mismo vale para las demás bases
+pub fn nullspace<T: Number>(m: &Matrix<T>) -> Matrix<T> {
+    let reduced = gauss_jordan(m, false);
+    let free: Vec<usize> = (0..m.n).filter(|j| !reduced.pivots.contains(j)).collect();
+    let mut basis = Matrix::new_empty(m.n, free.len());
+    for (k, &j) in free.iter().enumerate() {
+        basis.set(j, k, T::one());
+        for (i, &p) in reduced.pivots.iter().enumerate() {
+            basis.set(p, k, -reduced.matrix[i][j].clone());
+        }
+    }
+    basis
+}
+
+// Base del espacio columna: las columnas de `A` donde la forma escalonada tiene pivotes
+pub fn colspace<T: Number>(m: &Matrix<T>) -> Matrix<T> {
+    let pivots = gauss_jordan(m, false).pivots;
+    let mut basis = Matrix::new_empty(m.m, pivots.len());
+    for (k, &j) in pivots.iter().enumerate() {
+        for i in 0..m.m {
+            basis.set(i, k, m[i][j].clone());
+        }
+    }
+    basis
+}
+
+// Base del espacio fila: las filas no nulas de la forma escalonada reducida, traspuestas
+pub fn rowspace<T: Number>(m: &Matrix<T>) -> Matrix<T> {
+    let reduced = gauss_jordan(m, false);
+    let mut basis = Matrix::new_empty(m.n, reduced.pivots.len());
+    for k in 0..reduced.pivots.len() {
+        for j in 0..m.n {
+            basis.set(j, k, reduced.matrix[k][j].clone());
+        }
+    }
+    basis
+}
+
+// Base del espacio nulo a izquierda, los `y` con `y^T A = 0`, que es el espacio nulo de la traspuesta
+pub fn leftnull<T: Number>(m: &Matrix<T>) -> Matrix<T> {
+    nullspace(&transpose(m).unwrap())
+}
+
+// Suma de los elementos de la diagonal
+pub fn trace<T: Number>(m: &Matrix<T>) -> Result<T, Box<dyn Error>> {
+    if !m.is_squared() {
+        return Err("Bad dimensions".into());
+    }
+    Ok((0..m.m).fold(T::zero(), |sum, i| sum + m[i][i].clone()))
+}
+
+// Elementos de un vector fila o columna
+fn vector<T: Number>(m: &Matrix<T>) -> Result<Vec<T>, Box<dyn Error>> {
+    if m.m != 1 && m.n != 1 {
+        return Err("Not a vector".into());
+    }
+    Ok((0..m.m).flat_map(|i| m[i].clone()).collect())
+}
+
+// Producto escalar. Los vectores pueden ser filas o columnas, incluso uno de cada tipo
+pub fn dot<T: Number>(u: &Matrix<T>, v: &Matrix<T>) -> Result<T, Box<dyn Error>> {
+    let (u, v) = (vector(u)?, vector(v)?);
+    if u.len() != v.len() {
+        return Err("Bad dimensions".into());
+    }
+    Ok(u.into_iter().zip(v).fold(T::zero(), |sum, (a, b)| sum + a * b))
+}
+
+// Producto vectorial de dos vectores de 3 elementos. El resultado tiene la orientación de `u`
+pub fn cross<T: Number>(u: &Matrix<T>, v: &Matrix<T>) -> Result<Matrix<T>, Box<dyn Error>> {
+    let (a, b) = (vector(u)?, vector(v)?);
+    if a.len() != 3 || b.len() != 3 {
+        return Err("Bad dimensions".into());
+    }
+    let term = |i: usize, j: usize| a[i].clone() * b[j].clone() - a[j].clone() * b[i].clone();
+    let elements = [term(1, 2), term(2, 0), term(0, 1)];
+    let mut res = Matrix::new_empty(u.m, u.n);
+    for (k, x) in elements.into_iter().enumerate() {
+        if u.m == 1 {
+            res.set(0, k, x);
+        } else {
+            res.set(k, 0, x);
+        }
+    }
+    Ok(res)
+}
+
+// Norma p: (|x1|^p + ... + |xn|^p)^(1/p), con p >= 1. La norma 2 es la longitud euclídea
+pub fn norm(u: &Matrix, p: f64) -> Result<f64, Box<dyn Error>> {
+    if p < 1.0 {
+        return Err("p must be at least 1".into());
+    }
+    let u = vector(u)?;
+    Ok(u.iter().map(|x| x.abs().powf(p)).sum::<f64>().powf(1.0 / p))
+}
+
+// Vector de norma 2 igual a 1 con la misma dirección que `u`
+pub fn normalize(u: &Matrix) -> Result<Matrix, Box<dyn Error>> {
+    let length = norm(u, 2.0)?;
+    if length == 0.0 {
+        return Err("Zero vector".into());
+    }
+    Ok(mul_scalar(u, 1.0 / length))
+}
+
+// Ángulo entre dos vectores, en radianes
+pub fn angle(u: &Matrix, v: &Matrix) -> Result<f64, Box<dyn Error>> {
+    let lengths = norm(u, 2.0)? * norm(v, 2.0)?;
+    if lengths == 0.0 {
+        return Err("Zero vector".into());
+    }
+    // El redondeo puede dejar el coseno apenas fuera de [-1, 1]
+    Ok((dot(u, v)? / lengths).clamp(-1.0, 1.0).acos())
+}
+
+// Proyección de `u` sobre `v`. El resultado tiene la orientación de `v`
+pub fn proj<T: Number>(u: &Matrix<T>, v: &Matrix<T>) -> Result<Matrix<T>, Box<dyn Error>> {
+    let squared = dot(v, v)?;
+    if squared.is_zero() {
+        return Err("Zero vector".into());
+    }
+    Ok(mul_scalar(v, dot(u, v)? / squared))
+}
+
+// Copia de las filas y columnas indicadas. Los rangos deben estar dentro de la matriz
+pub fn submatrix<T: Number>(m: &Matrix<T>, rows: Range<usize>, cols: Range<usize>) -> Matrix<T> {
+    let mut res = Matrix::new_empty(rows.len(), cols.len());
+    for (i, row) in rows.enumerate() {
+        for (j, col) in cols.clone().enumerate() {
+            res.set(i, j, m[row][col].clone());
+        }
+    }
+    res
+}
+
+// Reemplaza el bloque que empieza en (`row`, `col`) por `block`
+pub fn set_submatrix<T: Number>(
+    m: &mut Matrix<T>,
+    row: usize,
+    col: usize,
+    block: &Matrix<T>,
+) -> Result<(), Box<dyn Error>> {
+    if row + block.m > m.m || col + block.n > m.n {
+        return Err("Bad dimensions".into());
+    }
+    for i in 0..block.m {
+        for j in 0..block.n {
+            m.set(row + i, col + j, block[i][j].clone());
+        }
+    }
+    Ok(())
+}
+
+// toma la matriz aumentada
+pub fn solve_system<T: Number>(matrix: &Matrix<T>) -> Compatibility<T> {
+    let m = matrix.m;
+    let n = matrix.n;
+
+    // Paso 1: Escalonar la matriz aumentada
+    let scale = scale(matrix);
+    let matrix = row_echelon(matrix);
 
     // Paso 2: Determinar la compatibilidad del sistema
-    let mut rank = m;
     for i in (0..m).rev() {
         let mut nonzero = false;
         for j in 0..n-1 {
-            if matrix[i][j] != 0.0 {
+            if !matrix[i][j].is_negligible(scale) {
                 nonzero = true;
                 break;
             }
         }
-        if !nonzero {
-            if matrix[i][n-1] != 0.0 {
-                return Compatibility::Incompatible;
+        if !nonzero && !matrix[i][n-1].is_negligible(scale) {
+            return Compatibility::Incompatible;
+        }
+    }
+
+    // Paso 3: Buscar la columna del pivote de cada fila no nula. Las incógnitas sin pivote quedan libres
+    let pivots: Vec<usize> = (0..m).filter_map(|i| (0..n-1).find(|&j| !matrix[i][j].is_negligible(scale))).collect();
+    let free: Vec<usize> = (0..n-1).filter(|j| !pivots.contains(j)).collect();
+
+    // Paso 4: Resolver el sistema mediante sustitución hacia atrás, con las incógnitas libres en cero
+    let particular = back_substitution(&matrix, &pivots, |i| matrix[i][n-1].clone(), Matrix::new_empty(n-1, 1));
+    if free.is_empty() {
+        return Compatibility::CompatibleDeterminado(particular);
+    }
+
+    // Paso 5: Con una incógnita libre en 1, las demás en 0 y el sistema homogéneo se obtiene un vector del espacio nulo
+    let basis = free.iter().map(|&j| {
+        let mut x = Matrix::new_empty(n-1, 1);
+        x.set(j, 0, T::one());
+        back_substitution(&matrix, &pivots, |_| T::zero(), x)
+    }).collect();
+    Compatibility::CompatibleIndeterminado(GeneralSolution { particular, basis, free })
+}
+
+// Despeja de abajo hacia arriba la incógnita del pivote de cada fila de la matriz escalonada. `rhs` da el término
+// independiente de cada fila, y `x` trae los valores de las incógnitas libres
+fn back_substitution<T: Number>(matrix: &Matrix<T>, pivots: &[usize], rhs: impl Fn(usize) -> T, mut x: Matrix<T>) -> Matrix<T> {
+    for (i, &p) in pivots.iter().enumerate().rev() {
+        let mut sum = T::zero();
+        for j in p+1..x.m {
+            sum = sum + matrix[i][j].clone() * x[j][0].clone();
+        }
+        x.set(p, 0, (rhs(i) - sum) / matrix[i][p].clone());
+    }
+    x
+}
+
+// Factorización `P A = L U`: `P` es una matriz de permutación, `L` triangular inferior con unos en la diagonal y `U`
+// triangular superior
+#[derive(Debug, Clone)]
+pub struct Lu<T = f64> {
+    pub p: Matrix<T>,
+    pub l: Matrix<T>,
+    pub u: Matrix<T>,
+}
+
+// Factorización LU por eliminación gaussiana con pivoteo parcial. `L` guarda los factores de cada eliminación y
+// `U` lo que queda de la matriz. Funciona con cualquier matriz, incluso singular o no cuadrada
+pub fn lu<T: Number>(m: &Matrix<T>) -> Lu<T> {
+    let (rows, cols) = (m.m, m.n);
+    let scale = scale(m);
+    let mut u = m.clone();
+    let mut l: Matrix<T> = Matrix::new_empty(rows, rows);
+    let mut permutation: Vec<usize> = (0..rows).collect();
+    for k in 0..rows.min(cols) {
+        // Como en `rref`: con números exactos el primer pivote no nulo, con decimales el más grande
+        let mut pivot_row = None;
+        for i in k..rows {
+            if u[i][k].is_negligible(scale) {
+                continue;
+            }
+            match pivot_row {
+                None => pivot_row = Some(i),
+                Some(p) if !T::EXACT && u[i][k].magnitude() > u[p][k].magnitude() => pivot_row = Some(i),
+                _ => {}
+            }
+        }
+        // La columna ya es nula debajo de la diagonal
+        let Some(p) = pivot_row else {
+            continue;
+        };
+        if p != k {
+            swap_rows(&mut u, k, p);
+            swap_rows(&mut l, k, p);
+            permutation.swap(k, p);
+        }
+        for i in k+1..rows {
+            let factor = u[i][k].clone() / u[k][k].clone();
+            for j in k+1..cols {
+                u.set(i, j, u[i][j].clone() - factor.clone() * u[k][j].clone());
+            }
+            u.set(i, k, T::zero());
+            l.set(i, k, factor);
+        }
+    }
+    // La fila `i` de `P A` es la fila `permutation[i]` de `A`
+    let mut p: Matrix<T> = Matrix::new_empty(rows, rows);
+    for (i, &row) in permutation.iter().enumerate() {
+        l.set(i, i, T::one());
+        p.set(i, row, T::one());
+    }
+    Lu { p, l, u }
+}
+
+// Resuelve `A X = B` a partir de la factorización `P A = L U`, para cada columna de `B`: primero `L Y = P B` de
+// arriba hacia abajo y luego `U X = Y` de abajo hacia arriba. Factorizar una vez sirve para muchos `B`
+pub fn lu_solve<T: Number>(factors: &Lu<T>, b: &Matrix<T>) -> Result<Matrix<T>, Box<dyn Error>> {
+    let Lu { p, l, u } = factors;
+    let n = l.m;
+    if !l.is_squared() || !u.is_squared() || !p.is_squared() || u.m != n || p.m != n || b.m != n {
+        return Err("Bad dimensions".into());
+    }
+    let scale = scale(u);
+    if (0..n).any(|i| u[i][i].is_negligible(scale)) {
+        return Err(Box::new(Singular { rank: rank(u) }));
+    }
+    let mut x = mul(p, b)?;
+    for j in 0..b.n {
+        for i in 0..n {
+            let mut sum = T::zero();
+            for k in 0..i {
+                sum = sum + l[i][k].clone() * x[k][j].clone();
+            }
+            x.set(i, j, (x[i][j].clone() - sum) / l[i][i].clone());
+        }
+        for i in (0..n).rev() {
+            let mut sum = T::zero();
+            for k in i+1..n {
+                sum = sum + u[i][k].clone() * x[k][j].clone();
             }
-            rank -= 1;
+            x.set(i, j, (x[i][j].clone() - sum) / u[i][i].clone());
         }
     }
-    if rank < n-1 {
-        return Compatibility::CompatibleIndeterminado;
+    Ok(x)
+}
+
+// Solución por mínimos cuadrados de `A x = b`: el `x` que minimiza la norma del residuo `b - A x`
+#[derive(Debug, Clone)]
+pub struct LeastSquares<T = f64> {
+    pub x: Matrix<T>,
+    pub residual: Matrix<T>,
+    pub norm: f64,
+}
+
+impl<T: Number> LeastSquares<T> {
+    fn new(a: &Matrix<T>, b: &Matrix<T>, x: Matrix<T>) -> Result<LeastSquares<T>, Box<dyn Error>> {
+        let residual = sub(b, &mul(a, &x)?)?;
+        let norm = (0..residual.m).map(|i| residual[i][0].magnitude().powi(2)).sum::<f64>().sqrt();
+        Ok(LeastSquares { x, residual, norm })
+    }
+}
+
+// `b` es un vector columna con una fila por ecuación
+fn check_least_squares<T: Number>(a: &Matrix<T>, b: &Matrix<T>) -> Result<(), Box<dyn Error>> {
+    if b.n != 1 || a.m != b.m {
+        return Err("Bad dimensions".into());
     }
+    Ok(())
+}
 
-    // Paso 3: Resolver el sistema mediante sustitución hacia atrás
-    let mut x = Matrix::new_empty(n-1, 1);
-    for i in (0..n-1).rev() {
-        let mut sum = 0.0;
-        for j in i+1..n-1 {
-            sum += matrix[i][j] * x[j][0];
+// Mínimos cuadrados por ecuaciones normales, `A^H A x = A^H b`. Con fracciones el resultado es exacto, pero con
+// decimales `A^H A` eleva al cuadrado el número de condición de `A`
+pub fn least_squares_normal<T: Number>(a: &Matrix<T>, b: &Matrix<T>) -> Result<LeastSquares<T>, Box<dyn Error>> {
+    check_least_squares(a, b)?;
+    let adjoint = conj_transpose(a)?;
+    // `A^H A` es inversible si y solo si las columnas de `A` son independientes
+    let inverse = inv(&mul(&adjoint, a)?).map_err(|_| Singular { rank: rank(a) })?;
+    let x = mul(&inverse, &mul(&adjoint, b)?)?;
+    LeastSquares::new(a, b, x)
+}
+
+// Mínimos cuadrados por factorización QR, `R x = Q^T b`. No forma `A^T A`, así que pierde menos precisión
+pub fn least_squares_qr(a: &Matrix, b: &Matrix) -> Result<LeastSquares, Box<dyn Error>> {
+    check_least_squares(a, b)?;
+    let n = a.n;
+    let (reflections, r) = householder(a);
+    let scale = scale(a);
+    if a.m < n || (0..n).any(|i| r[i][i].is_negligible(scale)) {
+        return Err(Box::new(Singular { rank: rank(a) }));
+    }
+    let mut qtb = b.clone();
+    reflect(&reflections, &mut qtb);
+    let pivots: Vec<usize> = (0..n).collect();
+    let x = back_substitution(&r, &pivots, |i| qtb[i][0], Matrix::new_empty(n, 1));
+    LeastSquares::new(a, b, x)
+}
+
+// Triangulariza `A` con reflexiones de Householder `H = I - 2 v v^T / (v^T v)`. Devuelve el vector `v` de cada
+// reflexión y la matriz triangular `R = H_k ... H_1 A`
+fn householder(a: &Matrix) -> (Vec<Vec<f64>>, Matrix) {
+    let m = a.m;
+    let mut r = a.clone();
+    let mut reflections = Vec::new();
+    for k in 0..a.n.min(m) {
+        // Refleja la columna `k` debajo de la diagonal sobre el eje, eligiendo el signo que evita cancelaciones
+        let mut v: Vec<f64> = (0..m).map(|i| if i < k { 0.0 } else { r[i][k] }).collect();
+        let length = v.iter().map(|x| x * x).sum::<f64>().sqrt();
+        if length == 0.0 {
+            continue;
+        }
+        v[k] += if v[k] < 0.0 { -length } else { length };
+        reflections.push(v);
+        reflect(&reflections[reflections.len()-1..], &mut r);
+    }
+    (reflections, r)
+}
+
+// Aplica las reflexiones en orden a cada columna de `m`
+fn reflect(reflections: &[Vec<f64>], m: &mut Matrix) {
+    for v in reflections {
+        let squared: f64 = v.iter().map(|x| x * x).sum();
+        for j in 0..m.n {
+            let projection: f64 = (0..m.m).map(|i| v[i] * m[i][j]).sum::<f64>() * 2.0 / squared;
+            for i in 0..m.m {
+                m.set(i, j, m[i][j] - projection * v[i]);
+            }
         }
-        x.set(i,0 , matrix[i][n-1] - sum);
     }
-    Compatibility::CompatibleDeterminado
+}
+
+// Factorización `A = Q R`: `Q` es ortogonal y `R` triangular superior, con la diagonal no negativa
+#[derive(Debug, Clone)]
+pub struct Qr {
+    pub q: Matrix,
+    pub r: Matrix,
+    // Si es menor que la cantidad de columnas, las columnas de `Q` no generan el espacio columna de `A`
+    pub rank: usize,
+}
+
+// Factorización QR completa por reflexiones de Householder: `Q` es de m x m y `R` de m x n
+pub fn qr(a: &Matrix) -> Qr {
+    let (reflections, mut r) = householder(a);
+    // `Q^T = H_k ... H_1`, y cada reflexión es su propia traspuesta
+    let mut q = id_matrix(a.m);
+    reflect(&reflections, &mut q);
+    let mut q = transpose(&q).unwrap();
+    // Debajo de la diagonal, y en la diagonal si las columnas son dependientes, quedan errores de redondeo
+    let scale = scale(a);
+    for i in 0..a.m {
+        for j in 0..a.n {
+            if j < i || r[i][j].is_negligible(scale) {
+                r.set(i, j, 0.0);
+            }
+        }
+    }
+    // Cambiar el signo de una fila de `R` y la columna de `Q` correspondiente no cambia el producto
+    for j in 0..a.n.min(a.m) {
+        if r[j][j] < 0.0 {
+            for k in 0..a.n {
+                r.set(j, k, 0.0 - r[j][k]);
+            }
+            for i in 0..a.m {
+                q.set(i, j, 0.0 - q[i][j]);
+            }
+        }
+    }
+    Qr { q, r, rank: rank(a) }
+}
+
+// Base ortonormal del espacio columna de `A`, como columnas. La versión modificada resta cada proyección apenas la
+// calcula, y pierde mucha menos ortogonalidad que la clásica, que proyecta siempre la columna original. Las
+// columnas que dependen de las anteriores se descartan, así que la base tiene tantos vectores como el rango
+pub fn gram_schmidt(a: &Matrix, modified: bool) -> Matrix {
+    let scale = scale(a);
+    let mut basis: Vec<Vec<f64>> = Vec::new();
+    for j in 0..a.n {
+        let column: Vec<f64> = (0..a.m).map(|i| a[i][j]).collect();
+        let mut v = column.clone();
+        for q in &basis {
+            let source = if modified { &v } else { &column };
+            let projection: f64 = q.iter().zip(source).map(|(x, y)| x * y).sum();
+            v = v.iter().zip(q).map(|(x, y)| x - projection * y).collect();
+        }
+        let length = v.iter().map(|x| x * x).sum::<f64>().sqrt();
+        if !length.is_negligible(scale) {
+            basis.push(v.iter().map(|x| x / length).collect());
+        }
+    }
+    let mut res = Matrix::new_empty(a.m, basis.len());
+    for (j, q) in basis.iter().enumerate() {
+        for (i, x) in q.iter().enumerate() {
+            res.set(i, j, *x);
+        }
+    }
+    res
+}
+
+// Error de `cholesky`: la matriz no es simétrica, o el menor principal de orden `Minor` no es positivo
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotPositiveDefinite {
+    NotSymmetric,
+    Minor(usize),
+}
+
+impl fmt::Display for NotPositiveDefinite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NotPositiveDefinite::NotSymmetric => write!(f, "Not symmetric"),
+            NotPositiveDefinite::Minor(order) => write!(f, "Leading minor of order {} is not positive", order),
+        }
+    }
+}
+
+impl Error for NotPositiveDefinite {}
+
+// Factorización de Cholesky `A = L L^T`, con `L` triangular inferior y diagonal positiva. Existe si y solo si `A`
+// es simétrica definida positiva: cada elemento de la diagonal es la raíz de un número que, si no es positivo,
+// muestra que el menor principal de ese orden tampoco lo es
+pub fn cholesky(a: &Matrix) -> Result<Matrix, Box<dyn Error>> {
+    if !a.is_squared() {
+        return Err("Bad dimensions".into());
+    }
+    if !a.is_symmetric() {
+        return Err(Box::new(NotPositiveDefinite::NotSymmetric));
+    }
+    let n = a.n;
+    let scale = scale(a);
+    let mut l: Matrix = Matrix::new_empty(n, n);
+    for j in 0..n {
+        let diagonal = a[j][j] - (0..j).map(|k| l[j][k] * l[j][k]).sum::<f64>();
+        if diagonal <= 0.0 || diagonal.is_negligible(scale) {
+            return Err(Box::new(NotPositiveDefinite::Minor(j + 1)));
+        }
+        l.set(j, j, diagonal.sqrt());
+        for i in j+1..n {
+            let sum: f64 = (0..j).map(|k| l[i][k] * l[j][k]).sum();
+            l.set(i, j, (a[i][j] - sum) / l[j][j]);
+        }
+    }
+    Ok(l)
+}
+
+// Simétrica y con `x^T A x > 0` para todo `x` no nulo, lo que equivale a tener factorización de Cholesky
+pub fn is_posdef(a: &Matrix) -> bool {
+    cholesky(a).is_ok()
 }
 
 #[cfg(test)]
 mod tests {
     use crate::structs::Matrix;
     use crate::math;
+    use crate::number::{rational_to_f64, set_tolerance, tolerance, Complex, Modular, Number, Rational, Tolerance};
+    use num_traits::Zero;
 
     fn create2by2() -> Matrix {
         return Matrix::new_from(2, 2, &[&[1.0, 2.0], &[3.0, 4.0]]).unwrap();
@@ -347,6 +1147,11 @@ mod tests {
         assert_eq!(res[0][1], 4.0);
         assert_eq!(res[1][0], 6.0);
         assert_eq!(res[1][1], 8.0);
+
+        // Non square matrices
+        let m1 = Matrix::new_from(2, 3, &[&[1.0, 2.0, 3.0], &[3.0, 4.0, 3.0]]).unwrap();
+        let res = math::sum(&m1, &m1).unwrap();
+        assert!(res.equals(&Matrix::new_from(2, 3, &[&[2.0, 4.0, 6.0], &[6.0, 8.0, 6.0]]).unwrap()));
     }
 
     #[test]
@@ -361,14 +1166,141 @@ mod tests {
         assert_eq!(res[1][1], 0.0);
     }
 
+    #[test]
+    fn element_wise() {
+        let m = create2by2();
+        let res = math::elem_mul(&m, &m).unwrap();
+        assert!(res.equals(&Matrix::new_from(2, 2, &[&[1.0, 4.0], &[9.0, 16.0]]).unwrap()));
+        let res = math::elem_div(&m, &m).unwrap();
+        assert!(res.equals(&Matrix::new_from(2, 2, &[&[1.0, 1.0], &[1.0, 1.0]]).unwrap()));
+        let res = math::elem_pow(&m, &Matrix::new_from(1, 1, &[&[2.0]]).unwrap()).unwrap();
+        assert!(res.equals(&Matrix::new_from(2, 2, &[&[1.0, 4.0], &[9.0, 16.0]]).unwrap()));
+    }
+
+    #[test]
+    fn broadcasting() {
+        let m = Matrix::new_from(2, 3, &[&[1.0, 2.0, 3.0], &[4.0, 5.0, 6.0]]).unwrap();
+        let row = Matrix::new_from(1, 3, &[&[10.0, 20.0, 30.0]]).unwrap();
+        let col = Matrix::new_from(2, 1, &[&[1.0], &[2.0]]).unwrap();
+        let scalar = Matrix::new_from(1, 1, &[&[1.0]]).unwrap();
+
+        let res = math::sum(&m, &row).unwrap();
+        assert!(res.equals(&Matrix::new_from(2, 3, &[&[11.0, 22.0, 33.0], &[14.0, 25.0, 36.0]]).unwrap()));
+        let res = math::elem_mul(&col, &m).unwrap();
+        assert!(res.equals(&Matrix::new_from(2, 3, &[&[1.0, 2.0, 3.0], &[8.0, 10.0, 12.0]]).unwrap()));
+        let res = math::sub(&m, &scalar).unwrap();
+        assert!(res.equals(&Matrix::new_from(2, 3, &[&[0.0, 1.0, 2.0], &[3.0, 4.0, 5.0]]).unwrap()));
+        // A column and a row give every combination
+        let res = math::sum(&col, &row).unwrap();
+        assert!(res.equals(&Matrix::new_from(2, 3, &[&[11.0, 21.0, 31.0], &[12.0, 22.0, 32.0]]).unwrap()));
+
+        math::sum(&m, &create2by2()).unwrap_err();
+        math::elem_div(&m, &Matrix::new_from(1, 2, &[&[1.0, 2.0]]).unwrap()).unwrap_err();
+    }
+
     #[test]
     fn determinant() {
+        // La eliminación gaussiana redondea en los cocientes, así que los flotantes se comparan con tolerancia
         let m = Matrix::new_from(2, 2, &[&[6.0, 7.0], &[-2.0, 8.0]]).unwrap();
-        assert_eq!(math::det(&m).unwrap(), 62.0);
+        assert!(math::det(&m).unwrap().approx_eq(&62.0));
+        assert_eq!(math::det_cofactores(&m).unwrap(), 62.0);
         let m:Matrix=Matrix::new_from(3, 3, &[&[2.0, -1.0, 3.0], &[3.0, 6.0, 7.0], &[4.0, -2.0, 8.0]]).unwrap();
-        assert_eq!(math::det(&m).unwrap(), 30.0);
+        assert!(math::det(&m).unwrap().approx_eq(&30.0));
+        assert_eq!(math::det_cofactores(&m).unwrap(), 30.0);
         let m = create2by2();
-        assert_eq!(math::det(&m).unwrap(), -2.0);
+        assert!(math::det(&m).unwrap().approx_eq(&-2.0));
+        assert_eq!(math::det_cofactores(&m).unwrap(), -2.0);
+        // Singular: el último pivote es un error de redondeo, y `det` coincide con `rank`
+        let m = Matrix::new_from(3, 3, &[&[1.0, 2.0, 3.0], &[4.0, 5.0, 6.0], &[7.0, 8.0, 9.0]]).unwrap();
+        assert_eq!(math::det(&m).unwrap(), 0.0);
+        assert_eq!(math::rank(&m), 2);
+    }
+
+    // Matriz de n x n con enteros entre -5 y 5, pseudoaleatorios pero siempre los mismos para cada `seed`
+    fn sample(n: usize, seed: u64) -> Matrix {
+        random(n, n, seed)
+    }
+
+    fn random(m: usize, n: usize, seed: u64) -> Matrix {
+        let mut state = seed;
+        let mut matrix = Matrix::new_empty(m, n);
+        for i in 0..m {
+            for j in 0..n {
+                state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                matrix.set(i, j, ((state >> 33) % 11) as f64 - 5.0);
+            }
+        }
+        matrix
+    }
+
+    // Tridiagonal con 2 en la diagonal y -1 al lado: su determinante es n + 1
+    fn tridiagonal(n: usize) -> Matrix {
+        let mut matrix = math::mul_scalar(&math::id_matrix(n), 2.0);
+        for i in 1..n {
+            matrix.set(i, i - 1, -1.0);
+            matrix.set(i - 1, i, -1.0);
+        }
+        matrix
+    }
+
+    #[test]
+    fn determinant_methods() {
+        let frac = |x: &f64| Rational::from_integer((*x as i64).into());
+        for n in 1..=7 {
+            for seed in 0..5 {
+                let m = sample(n, seed);
+                let expected = math::det_cofactores(&m).unwrap();
+                assert!(math::det(&m).unwrap().approx_eq(&expected), "{}x{}, semilla {}", n, n, seed);
+                // Con enteros el resultado exacto es el mismo
+                let exact = m.map(frac);
+                assert_eq!(math::det(&exact).unwrap(), math::det_cofactores(&exact).unwrap());
+                assert_eq!(math::det(&exact).unwrap(), Rational::from_integer((expected as i64).into()));
+                let modular = m.map(|x| Modular::new(*x as i64, 101));
+                assert_eq!(math::det(&modular).unwrap(), Modular::new(expected as i64, 101));
+            }
+        }
+        // Un cero en el primer pivote obliga a intercambiar filas
+        let m = Matrix::new_from(3, 3, &[&[0.0, 1.0, 2.0], &[1.0, 0.0, 3.0], &[4.0, -3.0, 8.0]]).unwrap();
+        assert_eq!(math::det(&m.map(frac)).unwrap(), Rational::from_integer((-2).into()));
+        assert!(math::det(&m).unwrap().approx_eq(&-2.0));
+        assert!(math::det(&Matrix::new_from(2, 3, &[&[1.0, 2.0, 3.0], &[4.0, 5.0, 6.0]]).unwrap()).is_err());
+        assert!(math::det_cofactores(&Matrix::new_from(2, 3, &[&[1.0, 2.0, 3.0], &[4.0, 5.0, 6.0]]).unwrap()).is_err());
+    }
+
+    #[test]
+    fn large_determinants() {
+        assert!(math::det(&tridiagonal(200)).unwrap().approx_eq(&201.0));
+        assert_eq!(math::det(&tridiagonal(60).map(|x| Rational::from_integer((*x as i64).into()))).unwrap(), Rational::from_integer(61.into()));
+        assert_eq!(math::det(&tridiagonal(200).map(|x| Modular::new(*x as i64, 7))).unwrap(), Modular::new(201, 7));
+        // Dos filas iguales
+        let mut m = sample(200, 1);
+        for j in 0..200 {
+            m.set(199, j, m[0][j]);
+        }
+        assert!(math::det(&m).unwrap().abs() < 1e-6 * math::det(&sample(200, 1)).unwrap().abs());
+    }
+
+    // Compara los tiempos de los dos métodos. Correr con `cargo test --release -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn benchmark_determinant() {
+        use std::time::Instant;
+        for n in [6, 8, 9, 10] {
+            let m = sample(n, 0);
+            let start = Instant::now();
+            let result = math::det_cofactores(&m).unwrap();
+            println!("det_cofactores {}x{}: {:?} ({})", n, n, start.elapsed(), result);
+        }
+        for n in [10, 50, 100, 200] {
+            let m = sample(n, 0);
+            let start = Instant::now();
+            let result = math::det(&m).unwrap();
+            println!("det {}x{}: {:?} ({:e})", n, n, start.elapsed(), result);
+            let exact = m.map(|x| Rational::from_integer((*x as i64).into()));
+            let start = Instant::now();
+            math::det(&exact).unwrap();
+            println!("det exacto {}x{}: {:?}", n, n, start.elapsed());
+        }
     }
 
     #[test]
@@ -394,6 +1326,10 @@ mod tests {
         assert!(math::pow(&mat, 0).unwrap().equals(&math::id_matrix(2)));
         assert!(math::pow(&mat, 1).unwrap().equals(&mat));
         assert!(math::pow(&mat, 2).unwrap().equals(&Matrix::new_from(2, 2, &[&[7.0, 10.0], &[15.0, 22.0]]).unwrap()));
+        assert!(math::pow(&mat, 5).unwrap().equals(&Matrix::new_from(2, 2, &[&[1069.0, 1558.0], &[2337.0, 3406.0]]).unwrap()));
+        let exact = mat.map(|x| Rational::from_integer((*x as i64).into()));
+        assert!(math::pow(&exact, 200).is_ok());
+        assert!(math::pow(&exact, u32::MAX).unwrap_err().is::<math::PowerTooLarge>());
     }
 
     #[test]
@@ -421,7 +1357,7 @@ mod tests {
     fn inverse_test() {
         let m: Matrix = Matrix::new_from(3, 3, &[&[2.0, -1.0, 3.0], &[3.0, 6.0, 7.0], &[4.0, -2.0, 8.0]]).unwrap();
         let res: Matrix = math::inv(&m).unwrap();
-        let e:f32=0.0001;
+        let e:f64=0.0001;
 
         assert!((res[0][0] - 31.00/15.00).abs()<e);
         assert!((res[0][1] - 1.00/15.00).abs()<e);
@@ -439,13 +1375,75 @@ mod tests {
         assert!(res.equals(&expected));
     }
 
+    #[test]
+    fn inverse_methods() {
+        let frac = |x: &f64| Rational::from_integer((*x as i64).into());
+        for n in 1..=6 {
+            for seed in 0..5 {
+                let m = sample(n, seed);
+                match math::inv(&m) {
+                    Ok(inverse) => {
+                        assert!(inverse.equals(&math::inv_adjunta(&m).unwrap()), "{}x{}, semilla {}", n, n, seed);
+                        assert!(math::mul(&m, &inverse).unwrap().equals(&math::id_matrix(n)));
+                        assert!(math::inv(&m.map(frac)).unwrap().equals(&math::inv_adjunta(&m.map(frac)).unwrap()));
+                    }
+                    Err(_) => assert!(math::inv_adjunta(&m).is_err()),
+                }
+            }
+        }
+        // El menor de una matriz de 1x1 es vacío
+        let m = Matrix::new_from(1, 1, &[&[4.0]]).unwrap();
+        assert!(math::adj(&m).unwrap().equals(&math::id_matrix(1)));
+        assert!(math::inv_adjunta(&m).unwrap().equals(&Matrix::new_from(1, 1, &[&[0.25]]).unwrap()));
+        assert!(math::inv(&m).unwrap().equals(&Matrix::new_from(1, 1, &[&[0.25]]).unwrap()));
+
+        let tridiagonal = tridiagonal(200);
+        let product = math::mul(&tridiagonal, &math::inv(&tridiagonal).unwrap()).unwrap();
+        assert!(product.equals(&math::id_matrix(200)));
+    }
+
+    #[test]
+    fn singular_inverse() {
+        let singular = |m: &Matrix| math::inv(m).unwrap_err().downcast::<math::Singular>().unwrap().rank;
+        let m = Matrix::new_from(3, 3, &[&[1.0, 2.0, 3.0], &[4.0, 5.0, 6.0], &[7.0, 8.0, 9.0]]).unwrap();
+        assert_eq!(singular(&m), 2);
+        assert_eq!(math::inv_adjunta(&m).unwrap_err().downcast::<math::Singular>().unwrap().rank, 2);
+        assert_eq!(singular(&Matrix::new_from(2, 2, &[&[1.0, 2.0], &[2.0, 4.0]]).unwrap()), 1);
+        assert_eq!(singular(&Matrix::new_empty(3, 3)), 0);
+        // No es singular, pero no es cuadrada
+        assert!(math::inv(&Matrix::new_from(1, 2, &[&[1.0, 2.0]]).unwrap()).unwrap_err().downcast::<math::Singular>().is_err());
+        // Con fracciones el pivote nulo se detecta sin tolerancia
+        let exact = m.map(|x| Rational::from_integer((*x as i64).into()));
+        assert_eq!(math::inv(&exact).unwrap_err().downcast::<math::Singular>().unwrap().rank, 2);
+    }
+
+    #[test]
+    fn tolerance_decides_singularity() {
+        // El segundo pivote es 1e-10: despreciable con la tolerancia por defecto, pero no con una más estricta
+        let nearly_singular = Matrix::new_from(2, 2, &[&[1.0, 1.0], &[1.0, 1.0 + 1e-10]]).unwrap();
+        assert!(math::inv(&nearly_singular).is_err());
+        let previous = tolerance();
+        set_tolerance(Tolerance { absolute: 1e-14, relative: 1e-14 });
+        let (inverse, rank) = (math::inv(&nearly_singular), math::rank(&nearly_singular));
+        set_tolerance(previous);
+        assert!(inverse.is_ok() && rank == 2);
+        assert_eq!(tolerance(), Tolerance::DEFAULT);
+        assert_eq!(math::rank(&nearly_singular), 1);
+    }
+
     #[test]
     fn compatible_determinado(){
         let m: Matrix = Matrix::new_from(3, 3, &[&[2.0, -1.0, 3.0], &[3.0, 6.0, 7.0], &[4.0, -2.0, 8.0]]).unwrap();
         let result: Matrix = Matrix ::new_from(3,1, &[&[1.0], &[2.0], &[3.0]]).unwrap();
         let res: Matrix = math :: data_loading(&m, &result);
         let c = math::solve_system(&res);
-        assert!(c == math::Compatibility::CompatibleDeterminado);
+        match c {
+            math::Compatibility::CompatibleDeterminado(x) => {
+                assert!(x.equals(&Matrix::new_from(3, 1, &[&[-0.3], &[-0.1], &[0.5]]).unwrap()));
+                assert!(math::mul(&m, &x).unwrap().equals(&result));
+            }
+            _ => panic!("El sistema es compatible determinado"),
+        }
     }
 
     #[test]
@@ -454,7 +1452,7 @@ mod tests {
         let res: Matrix = Matrix::new_from(2,1, &[&[-6.0], &[2.0]]).unwrap();
         let total: Matrix= math::data_loading(&new,&res);
         let result = math::solve_system(&total);
-        assert!(result == math::Compatibility::Incompatible);
+        assert!(result.is_incompatible());
     }
 
     #[test]
@@ -462,7 +1460,7 @@ mod tests {
         let m: Matrix= Matrix::new_from(3,4,&[&[1.0, 1.0, 1.0, 4.0], &[2.0, 2.0, 2.0, 8.0], &[3.0, 3.0, 3.0, 45.0]]).unwrap();
         let result = math::solve_system(&m);
 
-        assert!(result == math::Compatibility::Incompatible);
+        assert!(result.is_incompatible());
     }   
 
     #[test]
@@ -470,5 +1468,465 @@ mod tests {
         let m: Matrix = Matrix::new_from(2, 3, &[&[2.0,1.0,4.0], &[4.0,2.0,8.0]]).unwrap();
         let result = math::solve_system(&m);
         assert!(result.is_compatible_indeterminado());
+        let math::Compatibility::CompatibleIndeterminado(solution) = result else { unreachable!() };
+        assert!(solution.particular.equals(&Matrix::new_from(2, 1, &[&[2.0], &[0.0]]).unwrap()));
+        assert_eq!(solution.free, vec![1]);
+        assert!(solution.basis[0].equals(&Matrix::new_from(2, 1, &[&[-0.5], &[1.0]]).unwrap()));
+
+        // x1 + 2 x2 - x4 = 1, x3 + 3 x4 = 2: las incógnitas libres son x2 y x4
+        let frac = |x: i64| Rational::from_integer(x.into());
+        let row = |r: [i64; 5]| r.map(frac);
+        let m = Matrix::new_from(2, 5, &[&row([1, 2, 0, -1, 1]), &row([2, 4, 1, 1, 4])]).unwrap();
+        let math::Compatibility::CompatibleIndeterminado(solution) = math::solve_system(&m) else {
+            panic!("El sistema es compatible indeterminado")
+        };
+        assert_eq!(solution.free, vec![1, 3]);
+        let column = |c: [i64; 4]| Matrix::new_from(4, 1, &c.map(|x| [frac(x)]).iter().map(|r| &r[..]).collect::<Vec<_>>()).unwrap();
+        assert!(solution.particular.equals(&column([1, 0, 2, 0])));
+        assert!(solution.basis[0].equals(&column([-2, 1, 0, 0])));
+        assert!(solution.basis[1].equals(&column([1, 0, -3, 1])));
+        // Cualquier combinación resuelve el sistema
+        let coefficients = math::submatrix(&m, 0..2, 0..4);
+        let combination = math::sum(&math::mul_scalar(&solution.basis[0], frac(5)), &math::mul_scalar(&solution.basis[1], frac(-7))).unwrap();
+        let x = math::sum(&solution.particular, &combination).unwrap();
+        assert!(math::mul(&coefficients, &x).unwrap().equals(&math::submatrix(&m, 0..2, 4..5)));
+    }
+
+    #[test]
+    fn least_squares() {
+        // Recta que mejor aproxima a los puntos (0, 1), (1, 2), (2, 2) y (3, 4)
+        let a = Matrix::new_from(4, 2, &[&[1.0, 0.0], &[1.0, 1.0], &[1.0, 2.0], &[1.0, 3.0]]).unwrap();
+        let b = Matrix::new_from(4, 1, &[&[1.0], &[2.0], &[2.0], &[4.0]]).unwrap();
+        let expected = Matrix::new_from(2, 1, &[&[0.9], &[0.9]]).unwrap();
+        let residual = Matrix::new_from(4, 1, &[&[0.1], &[0.2], &[-0.7], &[0.4]]).unwrap();
+        for solution in [math::least_squares_qr(&a, &b).unwrap(), math::least_squares_normal(&a, &b).unwrap()] {
+            assert!(solution.x.equals(&expected));
+            assert!(solution.residual.equals(&residual));
+            assert!(solution.norm.approx_eq(&0.7f64.sqrt()));
+            // El residuo es ortogonal a las columnas de A
+            assert!(math::mul(&math::transpose(&a).unwrap(), &solution.residual).unwrap().equals(&Matrix::new_empty(2, 1)));
+        }
+        let frac = |x: &f64| Rational::from_integer((*x as i64).into());
+        let exact = math::least_squares_normal(&a.map(frac), &b.map(frac)).unwrap();
+        assert_eq!(exact.x[0][0], Rational::new(9.into(), 10.into()));
+        assert_eq!(exact.residual[2][0], Rational::new((-7).into(), 10.into()));
+
+        // Un sistema compatible determinado tiene residuo nulo
+        let a = sample(5, 3);
+        let x = Matrix::new_from(5, 1, &[&[1.0], &[-2.0], &[0.5], &[3.0], &[0.0]]).unwrap();
+        let solution = math::least_squares_qr(&a, &math::mul(&a, &x).unwrap()).unwrap();
+        assert!(solution.x.equals(&x));
+        assert!(solution.norm < 1e-9);
+
+        // Columnas dependientes, y más incógnitas que ecuaciones
+        let a = Matrix::new_from(3, 2, &[&[1.0, 2.0], &[2.0, 4.0], &[3.0, 6.0]]).unwrap();
+        let b = Matrix::new_from(3, 1, &[&[1.0], &[0.0], &[1.0]]).unwrap();
+        let rank = |e: Box<dyn std::error::Error>| e.downcast::<math::Singular>().unwrap().rank;
+        assert_eq!(rank(math::least_squares_qr(&a, &b).unwrap_err()), 1);
+        assert_eq!(rank(math::least_squares_normal(&a, &b).unwrap_err()), 1);
+        let wide = Matrix::new_from(1, 2, &[&[1.0, 1.0]]).unwrap();
+        assert_eq!(rank(math::least_squares_qr(&wide, &Matrix::new_from(1, 1, &[&[1.0]]).unwrap()).unwrap_err()), 1);
+        assert!(math::least_squares_qr(&a, &math::id_matrix(3)).is_err());
+    }
+
+    // Aplica la operación a una copia de la matriz
+    fn apply(m: &Matrix<Rational>, operation: &math::RowOperation<Rational>) -> Matrix<Rational> {
+        let mut res = m.clone();
+        match operation {
+            math::RowOperation::Swap(i, j) => {
+                for k in 0..m.n {
+                    res.set(*i, k, m[*j][k].clone());
+                    res.set(*j, k, m[*i][k].clone());
+                }
+            }
+            math::RowOperation::Scale(i, factor) => {
+                for k in 0..m.n {
+                    res.set(*i, k, m[*i][k].clone() * factor.clone());
+                }
+            }
+            math::RowOperation::AddMultiple { target, source, factor } => {
+                for k in 0..m.n {
+                    res.set(*target, k, m[*target][k].clone() + factor.clone() * m[*source][k].clone());
+                }
+            }
+        }
+        res
+    }
+
+    #[test]
+    fn reduced_row_echelon() {
+        let frac = |x: &f64| Rational::from_integer((*x as i64).into());
+        let m = Matrix::new_from(3, 4, &[&[0.0, 2.0, 4.0, 2.0], &[1.0, 3.0, 5.0, 4.0], &[2.0, 6.0, 10.0, 9.0]]).unwrap().map(frac);
+        let result = math::rref(&m);
+        let expected = Matrix::new_from(3, 4, &[&[1.0, 0.0, -1.0, 0.0], &[0.0, 1.0, 2.0, 0.0], &[0.0, 0.0, 0.0, 1.0]]).unwrap();
+        assert!(result.matrix.equals(&expected.map(frac)));
+        assert_eq!(result.pivots, vec![0, 1, 3]);
+        // Las operaciones registradas llevan de una matriz a la siguiente
+        assert_eq!(result.steps[0].operation, math::RowOperation::Swap(0, 1));
+        assert_eq!(result.steps[1].operation, math::RowOperation::AddMultiple { target: 2, source: 0, factor: frac(&-2.0) });
+        let mut current = m.clone();
+        for step in &result.steps {
+            current = apply(&current, &step.operation);
+            assert!(current.equals(&step.matrix));
+        }
+        assert!(current.equals(&result.matrix));
+
+        // Con decimales se elige el pivote más grande, y el resultado es el mismo
+        for seed in 0..5 {
+            let m = math::submatrix(&sample(4, seed), 0..3, 0..4);
+            let result = math::rref(&m);
+            let exact = math::rref(&m.map(frac));
+            assert!(result.matrix.equals(&exact.matrix.map(rational_to_f64)));
+            assert_eq!(result.pivots, exact.pivots);
+            assert_eq!(result.pivots.len(), math::rank(&m));
+        }
+        let zero = math::rref(&Matrix::<f64>::new_empty(2, 3));
+        assert!(zero.pivots.is_empty() && zero.steps.is_empty());
+        assert!(math::rref(&math::id_matrix::<f64>(3)).steps.is_empty());
+    }
+
+    #[test]
+    fn fundamental_subspaces() {
+        let frac = |x: &f64| Rational::from_integer((*x as i64).into());
+        let zeros = |m: usize, n: usize| Matrix::<Rational>::new_empty(m, n);
+        for seed in 0..40 {
+            // El producto de matrices de m x k y k x n tiene rango a lo sumo k
+            let (m, n, k) = (1 + seed as usize % 5, 1 + seed as usize % 7, 1 + seed as usize % 4);
+            let a = math::mul(&random(m, k, seed), &random(k, n, seed + 100)).unwrap();
+            let exact = a.map(frac);
+            let rank = math::rank(&exact);
+            assert!(rank <= k);
+            // Teorema de la dimensión, para A y para su traspuesta
+            assert_eq!(rank + math::nullity(&exact), n);
+            assert_eq!(math::rank(&a), rank);
+            assert_eq!(math::rank(&a) + math::nullity(&a), n);
+
+            let nullspace = math::nullspace(&exact);
+            assert_eq!((nullspace.m, nullspace.n), (n, n - rank));
+            assert!(math::mul(&exact, &nullspace).unwrap().equals(&zeros(m, n - rank)));
+            assert_eq!(math::rank(&nullspace), n - rank);
+            let float = math::nullspace(&a);
+            assert!(math::mul(&a, &float).unwrap().equals(&Matrix::new_empty(m, n - rank)));
+
+            let colspace = math::colspace(&exact);
+            assert_eq!((colspace.m, colspace.n, math::rank(&colspace)), (m, rank, rank));
+            let rowspace = math::rowspace(&exact);
+            assert_eq!((rowspace.m, rowspace.n, math::rank(&rowspace)), (n, rank, rank));
+            // El espacio fila es ortogonal al espacio nulo
+            assert!(math::mul(&math::transpose(&rowspace).unwrap(), &nullspace).unwrap().equals(&zeros(rank, n - rank)));
+
+            let leftnull = math::leftnull(&exact);
+            assert_eq!((leftnull.m, leftnull.n), (m, m - rank));
+            assert!(math::mul(&math::transpose(&leftnull).unwrap(), &exact).unwrap().equals(&zeros(m - rank, n)));
+        }
+        // Columnas independientes: el espacio nulo es solo el cero
+        assert_eq!(math::nullspace(&math::id_matrix::<f64>(3)).n, 0);
+        let m = Matrix::new_from(2, 3, &[&[1.0, 2.0, 3.0], &[2.0, 4.0, 6.0]]).unwrap();
+        let expected = Matrix::new_from(3, 2, &[&[-2.0, -3.0], &[1.0, 0.0], &[0.0, 1.0]]).unwrap();
+        assert!(math::nullspace(&m).equals(&expected));
+        assert!(math::colspace(&m).equals(&Matrix::new_from(2, 1, &[&[1.0], &[2.0]]).unwrap()));
+        assert!(math::leftnull(&m).equals(&Matrix::new_from(2, 1, &[&[-2.0], &[1.0]]).unwrap()));
+    }
+
+    #[test]
+    fn lu_decomposition() {
+        let frac = |x: &f64| Rational::from_integer((*x as i64).into());
+        let is_lower = |l: &Matrix| (0..l.m).all(|i| l[i][i] == 1.0 && (i+1..l.n).all(|j| l[i][j] == 0.0));
+        let is_upper = |u: &Matrix| (0..u.m).all(|i| (0..i.min(u.n)).all(|j| u[i][j] == 0.0));
+        for (m, n) in [(1, 1), (3, 3), (5, 5), (4, 2), (2, 4), (8, 8)] {
+            for seed in 0..5 {
+                let a = random(m, n, seed);
+                let factors = math::lu(&a);
+                assert!(math::mul(&factors.p, &a).unwrap().equals(&math::mul(&factors.l, &factors.u).unwrap()));
+                assert!(is_lower(&factors.l) && is_upper(&factors.u));
+                // Con pivoteo parcial los factores de `L` no superan 1
+                assert!((0..m).all(|i| (0..i).all(|j| factors.l[i][j].abs() <= 1.0)));
+                let exact = math::lu(&a.map(frac));
+                let product = math::mul(&exact.p, &a.map(frac)).unwrap();
+                assert!(product.equals(&math::mul(&exact.l, &exact.u).unwrap()));
+            }
+        }
+        // Matriz singular: la factorización existe, pero no sirve para resolver
+        let singular = Matrix::new_from(3, 3, &[&[1.0, 2.0, 3.0], &[2.0, 4.0, 6.0], &[1.0, 0.0, 1.0]]).unwrap();
+        let factors = math::lu(&singular);
+        assert!(math::mul(&factors.p, &singular).unwrap().equals(&math::mul(&factors.l, &factors.u).unwrap()));
+        let error = math::lu_solve(&factors, &math::id_matrix(3)).unwrap_err();
+        assert_eq!(error.downcast::<math::Singular>().unwrap().rank, 2);
+    }
+
+    #[test]
+    fn lu_solve() {
+        let a = sample(6, 2);
+        let factors = math::lu(&a);
+        // Varios términos independientes con la misma factorización
+        let b = random(6, 3, 7);
+        let x = math::lu_solve(&factors, &b).unwrap();
+        assert!(math::mul(&a, &x).unwrap().equals(&b));
+        assert!(math::lu_solve(&factors, &math::id_matrix(6)).unwrap().equals(&math::inv(&a).unwrap()));
+        assert!(math::lu_solve(&factors, &random(5, 1, 0)).is_err());
+
+        let frac = |x: &f64| Rational::from_integer((*x as i64).into());
+        let exact = math::lu(&a.map(frac));
+        let x = math::lu_solve(&exact, &b.map(frac)).unwrap();
+        assert!(math::mul(&a.map(frac), &x).unwrap().equals(&b.map(frac)));
+        let modular = |x: &f64| Modular::new(*x as i64, 13);
+        let factors = math::lu(&a.map(modular));
+        let x = math::lu_solve(&factors, &b.map(modular)).unwrap();
+        assert!(math::mul(&a.map(modular), &x).unwrap().equals(&b.map(modular)));
+    }
+
+    #[test]
+    fn qr_decomposition() {
+        for (m, n) in [(1, 1), (3, 3), (5, 3), (3, 5), (8, 8)] {
+            for seed in 0..5 {
+                let a = random(m, n, seed);
+                let factors = math::qr(&a);
+                assert_eq!((factors.q.m, factors.q.n, factors.r.m, factors.r.n), (m, m, m, n));
+                assert!(math::mul(&factors.q, &factors.r).unwrap().equals(&a));
+                let qtq = math::mul(&math::transpose(&factors.q).unwrap(), &factors.q).unwrap();
+                assert!(qtq.equals(&math::id_matrix(m)));
+                assert!((0..m).all(|i| (0..i.min(n)).all(|j| factors.r[i][j] == 0.0)));
+                assert!((0..m.min(n)).all(|i| factors.r[i][i] >= 0.0));
+                assert_eq!(factors.rank, math::rank(&a));
+            }
+        }
+        let m = Matrix::new_from(3, 2, &[&[3.0, 1.0], &[4.0, 2.0], &[0.0, 2.0]]).unwrap();
+        let factors = math::qr(&m);
+        assert!(math::submatrix(&factors.r, 0..2, 0..2).equals(&Matrix::new_from(2, 2, &[&[5.0, 2.2], &[0.0, 4.16f64.sqrt()]]).unwrap()));
+        let deficient = Matrix::new_from(3, 2, &[&[1.0, 2.0], &[2.0, 4.0], &[3.0, 6.0]]).unwrap();
+        assert_eq!(math::qr(&deficient).rank, 1);
+    }
+
+    #[test]
+    fn gram_schmidt() {
+        let orthogonality = |q: &Matrix| {
+            let qtq = math::sub(&math::mul(&math::transpose(q).unwrap(), q).unwrap(), &math::id_matrix(q.n)).unwrap();
+            (0..q.n).flat_map(|i| qtq[i].clone()).fold(0.0, |max, x| x.abs().max(max))
+        };
+        for seed in 0..10 {
+            let a = random(6, 4, seed);
+            let modified = math::gram_schmidt(&a, true);
+            assert!(modified.equals(&math::gram_schmidt(&a, false)));
+            assert!(orthogonality(&modified) < 1e-12);
+            // Mismo espacio columna: agregar la base no aumenta el rango
+            let mut both = Matrix::new_empty(6, 8);
+            math::set_submatrix(&mut both, 0, 0, &a).unwrap();
+            math::set_submatrix(&mut both, 0, 4, &modified).unwrap();
+            assert_eq!(math::rank(&both), math::rank(&a));
+            // Coincide con las primeras columnas de QR
+            let q = math::submatrix(&math::qr(&a).q, 0..6, 0..4);
+            assert!(modified.equals(&q));
+        }
+        // Columnas dependientes
+        let m = Matrix::new_from(3, 3, &[&[1.0, 2.0, 0.0], &[1.0, 2.0, 1.0], &[0.0, 0.0, 1.0]]).unwrap();
+        let basis = math::gram_schmidt(&m, true);
+        assert_eq!(basis.n, 2);
+        assert!(orthogonality(&basis) < 1e-12);
+
+        // Matriz de Läuchli: la versión clásica pierde la ortogonalidad
+        let e = 1e-8;
+        let lauchli = Matrix::new_from(4, 3, &[&[1.0, 1.0, 1.0], &[e, 0.0, 0.0], &[0.0, e, 0.0], &[0.0, 0.0, e]]).unwrap();
+        assert!(orthogonality(&math::gram_schmidt(&lauchli, true)) < 1e-6);
+        assert!(orthogonality(&math::gram_schmidt(&lauchli, false)) > 0.1);
+    }
+
+    #[test]
+    fn cholesky() {
+        for seed in 0..10 {
+            // `B^T B + I` es simétrica definida positiva
+            let b = random(5, 4, seed);
+            let a = math::sum(&math::mul(&math::transpose(&b).unwrap(), &b).unwrap(), &math::id_matrix(4)).unwrap();
+            let l = math::cholesky(&a).unwrap();
+            assert!(l.is_lower_triangular());
+            assert!((0..4).all(|i| l[i][i] > 0.0));
+            assert!(math::mul(&l, &math::transpose(&l).unwrap()).unwrap().equals(&a));
+        }
+        let m = Matrix::new_from(2, 2, &[&[4.0, 2.0], &[2.0, 5.0]]).unwrap();
+        assert!(math::cholesky(&m).unwrap().equals(&Matrix::new_from(2, 2, &[&[2.0, 0.0], &[1.0, 2.0]]).unwrap()));
+
+        let error = |m: &Matrix| *math::cholesky(m).unwrap_err().downcast::<math::NotPositiveDefinite>().unwrap();
+        assert_eq!(error(&create2by2()), math::NotPositiveDefinite::NotSymmetric);
+        let indefinite = Matrix::new_from(3, 3, &[&[1.0, 2.0, 0.0], &[2.0, 1.0, 0.0], &[0.0, 0.0, 1.0]]).unwrap();
+        assert_eq!(error(&indefinite), math::NotPositiveDefinite::Minor(2));
+        // Semidefinida: el segundo menor es nulo
+        assert_eq!(error(&Matrix::new_from(2, 2, &[&[1.0, 1.0], &[1.0, 1.0]]).unwrap()), math::NotPositiveDefinite::Minor(2));
+        assert_eq!(error(&Matrix::new_from(1, 1, &[&[-1.0]]).unwrap()), math::NotPositiveDefinite::Minor(1));
+        math::cholesky(&Matrix::new_empty(2, 3)).unwrap_err();
+
+        let tridiagonal = Matrix::new_from(3, 3, &[&[2.0, 1.0, 0.0], &[1.0, 2.0, 1.0], &[0.0, 1.0, 2.0]]).unwrap();
+        assert!(math::is_posdef(&tridiagonal));
+        assert!(!math::is_posdef(&indefinite) && !math::is_posdef(&create2by2()));
+    }
+
+    #[test]
+    fn matrix_rank() {
+        assert_eq!(math::rank(&create2by2()), 2);
+        let m = Matrix::new_from(3, 3, &[&[1.0, 2.0, 3.0], &[2.0, 4.0, 6.0], &[1.0, 0.0, 1.0]]).unwrap();
+        assert_eq!(math::rank(&m), 2);
+        let m = Matrix::new_from(2, 3, &[&[1.0, 2.0, 3.0], &[2.0, 4.0, 6.0]]).unwrap();
+        assert_eq!(math::rank(&m), 1);
+        assert_eq!(math::rank(&Matrix::<f64>::new_empty(3, 2)), 0);
+    }
+
+    #[test]
+    fn matrix_trace() {
+        assert_eq!(math::trace(&create2by2()).unwrap(), 5.0);
+        math::trace(&Matrix::<f64>::new_empty(2, 3)).unwrap_err();
+    }
+
+    #[test]
+    fn vectors() {
+        let row = Matrix::new_from(1, 3, &[&[1.0, 2.0, 2.0]]).unwrap();
+        let col = Matrix::new_from(3, 1, &[&[0.0], &[1.0], &[0.0]]).unwrap();
+        assert_eq!(math::dot(&row, &col).unwrap(), 2.0);
+        assert_eq!(math::dot(&row, &math::transpose(&col).unwrap()).unwrap(), 2.0);
+        math::dot(&row, &Matrix::new_empty(2, 1)).unwrap_err();
+        math::dot(&row, &create2by2()).unwrap_err();
+
+        let res = math::cross(&row, &col).unwrap();
+        assert!(res.equals(&Matrix::new_from(1, 3, &[&[-2.0, 0.0, 1.0]]).unwrap()));
+        let res = math::cross(&col, &row).unwrap();
+        assert!(res.equals(&Matrix::new_from(3, 1, &[&[2.0], &[0.0], &[-1.0]]).unwrap()));
+        math::cross(&create2by2(), &row).unwrap_err();
+
+        assert_eq!(math::norm(&row, 2.0).unwrap(), 3.0);
+        assert_eq!(math::norm(&row, 1.0).unwrap(), 5.0);
+        math::norm(&row, 0.5).unwrap_err();
+        let res = math::normalize(&row).unwrap();
+        assert!((math::norm(&res, 2.0).unwrap() - 1.0).abs() < 1e-6);
+        math::normalize(&Matrix::new_empty(1, 3)).unwrap_err();
+
+        let x = Matrix::new_from(1, 2, &[&[1.0, 0.0]]).unwrap();
+        let y = Matrix::new_from(2, 1, &[&[0.0], &[2.0]]).unwrap();
+        assert_eq!(math::angle(&x, &y).unwrap(), std::f64::consts::FRAC_PI_2);
+        assert_eq!(math::angle(&x, &x).unwrap(), 0.0);
+        math::angle(&x, &Matrix::new_empty(1, 2)).unwrap_err();
+
+        let res = math::proj(&row, &col).unwrap();
+        assert!(res.equals(&Matrix::new_from(3, 1, &[&[0.0], &[2.0], &[0.0]]).unwrap()));
+        math::proj(&row, &Matrix::new_empty(3, 1)).unwrap_err();
+    }
+
+    #[test]
+    fn submatrices() {
+        let m = Matrix::new_from(3, 3, &[&[1.0, 2.0, 3.0], &[4.0, 5.0, 6.0], &[7.0, 8.0, 9.0]]).unwrap();
+        let expected = Matrix::new_from(2, 2, &[&[2.0, 3.0], &[5.0, 6.0]]).unwrap();
+        assert!(math::submatrix(&m, 0..2, 1..3).equals(&expected));
+        let expected = Matrix::new_from(3, 1, &[&[2.0], &[5.0], &[8.0]]).unwrap();
+        assert!(math::submatrix(&m, 0..3, 1..2).equals(&expected));
+
+        let mut m = m;
+        math::set_submatrix(&mut m, 1, 1, &create2by2()).unwrap();
+        let expected = Matrix::new_from(3, 3, &[&[1.0, 2.0, 3.0], &[4.0, 1.0, 2.0], &[7.0, 3.0, 4.0]]).unwrap();
+        assert!(m.equals(&expected));
+        math::set_submatrix(&mut m, 2, 0, &create2by2()).unwrap_err();
+    }
+
+    #[test]
+    fn exact_rationals() {
+        let frac = |numer: i64, denom: i64| Rational::new(numer.into(), denom.into());
+        let int = |num: i64| frac(num, 1);
+        let m = Matrix::new_from(2, 2, &[&[int(1), int(2)], &[int(3), int(4)]]).unwrap();
+        assert_eq!(math::det(&m).unwrap(), int(-2));
+        let expected = Matrix::new_from(2, 2, &[&[int(-2), int(1)], &[frac(3, 2), frac(-1, 2)]]).unwrap();
+        assert!(math::inv(&m).unwrap().equals(&expected));
+        let expected = Matrix::new_from(2, 2, &[&[int(4), int(-3)], &[int(-2), int(1)]]).unwrap();
+        assert!(math::adj(&m).unwrap().equals(&expected));
+
+        // La matriz de Hilbert está mal condicionada, pero su inversa tiene elementos enteros
+        let hilbert = Matrix::new_from(3, 3, &[
+            &[int(1), frac(1, 2), frac(1, 3)],
+            &[frac(1, 2), frac(1, 3), frac(1, 4)],
+            &[frac(1, 3), frac(1, 4), frac(1, 5)],
+        ]).unwrap();
+        assert_eq!(math::det(&hilbert).unwrap(), frac(1, 2160));
+        let expected = Matrix::new_from(3, 3, &[
+            &[int(9), int(-36), int(30)],
+            &[int(-36), int(192), int(-180)],
+            &[int(30), int(-180), int(180)],
+        ]).unwrap();
+        let inverse = math::inv(&hilbert).unwrap();
+        assert!(inverse.equals(&expected));
+        assert!(math::mul(&hilbert, &inverse).unwrap().equals(&math::id_matrix(3)));
+
+        // x + y = 1, x - y = 1/3
+        let system = Matrix::new_from(2, 3, &[&[int(1), int(1), int(1)], &[int(1), int(-1), frac(1, 3)]]).unwrap();
+        assert!(math::solve_system(&system).is_compatible_determinado());
+        let system = Matrix::new_from(2, 3, &[&[frac(1, 3), int(1), int(1)], &[int(1), int(3), int(2)]]).unwrap();
+        assert!(math::solve_system(&system).is_incompatible());
+        assert_eq!(math::rank(&hilbert), 3);
+    }
+
+    #[test]
+    fn rounding_errors() {
+        // Singular, pero 0.1 * 0.39 y 1.3 * 0.03 no dan exactamente lo mismo en punto flotante
+        let m = Matrix::new_from(2, 2, &[&[0.1, 1.3], &[0.03, 0.39]]).unwrap();
+        assert!(!math::det_cofactores(&m).unwrap().is_zero());
+        assert!(math::inv(&m).is_err());
+        assert_eq!(math::rank(&m), 1);
+        let system = Matrix::new_from(2, 3, &[&[0.1, 1.3, 1.4], &[0.03, 0.39, 0.42]]).unwrap();
+        assert!(math::solve_system(&system).is_compatible_indeterminado());
+        // Valores chicos no son errores de redondeo si toda la matriz es chica
+        let small = Matrix::new_from(2, 2, &[&[1e-5, 0.0], &[0.0, 1e-5]]).unwrap();
+        assert_eq!(math::rank(&small), 2);
+        assert!(math::inv(&small).unwrap().equals(&math::mul_scalar(&math::id_matrix(2), 1e5)));
+    }
+
+    #[test]
+    fn complex_numbers() {
+        let c = |re: f64, im: f64| Complex::new(re, im);
+        let m = Matrix::new_from(2, 2, &[&[c(1.0, 1.0), c(2.0, 0.0)], &[c(0.0, -1.0), c(1.0, 0.0)]]).unwrap();
+        // (1 + i) - 2 * (-i) = 1 + 3i
+        assert_eq!(math::det(&m).unwrap(), c(1.0, 3.0));
+        let inverse = math::inv(&m).unwrap();
+        assert!(math::mul(&m, &inverse).unwrap().equals(&math::id_matrix(2)));
+        assert!(math::mul(&inverse, &m).unwrap().equals(&math::id_matrix(2)));
+        let adjoint = math::conj_transpose(&m).unwrap();
+        assert_eq!(adjoint[0][0], c(1.0, -1.0));
+        assert_eq!(adjoint[1][0], c(2.0, 0.0));
+        assert_eq!(adjoint[0][1], c(0.0, 1.0));
+
+        // x + i y = 1, i x - y = i: la segunda ecuación es i veces la primera
+        let system = Matrix::new_from(2, 3, &[
+            &[c(1.0, 0.0), c(0.0, 1.0), c(1.0, 0.0)],
+            &[c(0.0, 1.0), c(-1.0, 0.0), c(0.0, 1.0)],
+        ]).unwrap();
+        assert!(math::solve_system(&system).is_compatible_indeterminado());
+        assert_eq!(math::rank(&system), 1);
+        let system = Matrix::new_from(2, 3, &[
+            &[c(1.0, 0.0), c(0.0, 1.0), c(1.0, 0.0)],
+            &[c(0.0, 1.0), c(1.0, 0.0), c(0.0, 0.0)],
+        ]).unwrap();
+        assert!(math::solve_system(&system).is_compatible_determinado());
+    }
+
+    #[test]
+    fn other_numbers() {
+        let m = Matrix::new_from(2, 2, &[&[1.0f64, 2.0], &[3.0, 4.0]]).unwrap();
+        assert_eq!(math::det(&m).unwrap(), -2.0);
+        let expected = Matrix::new_from(2, 2, &[&[-2.0f64, 1.0], &[1.5, -0.5]]).unwrap();
+        let inverse = math::inv(&m).unwrap();
+        assert!((0..2).all(|i| (0..2).all(|j| (inverse[i][j] - expected[i][j]).abs() < 1e-12)));
+
+        // En Z_7 no hay fracciones: el inverso de det = -2 es 3
+        let z7 = |num: i64| Modular::new(num, 7);
+        let m = Matrix::new_from(2, 2, &[&[z7(1), z7(2)], &[z7(3), z7(4)]]).unwrap();
+        assert_eq!(math::det(&m).unwrap(), z7(5));
+        let inverse = math::inv(&m).unwrap();
+        let expected = Matrix::new_from(2, 2, &[&[z7(5), z7(1)], &[z7(5), z7(3)]]).unwrap();
+        assert!(inverse.equals(&expected));
+        assert!(math::mul(&m, &inverse).unwrap().equals(&math::id_matrix(2)));
+        assert!(math::sum(&m, &inverse).unwrap().equals(&Matrix::new_from(2, 2, &[&[z7(6), z7(3)], &[z7(1), z7(0)]]).unwrap()));
+
+        // [1 2; 2 4] es singular en Z_7, y [1 2; 3 6] también porque 6 = 2 * 3
+        let singular = Matrix::new_from(2, 2, &[&[z7(1), z7(2)], &[z7(3), z7(6)]]).unwrap();
+        assert!(math::inv(&singular).is_err());
+        assert_eq!(math::rank(&singular), 1);
+        // Es singular en Z_5 pero no en los racionales
+        let z5 = |num: i64| Modular::new(num, 5);
+        let m = Matrix::new_from(2, 2, &[&[z5(1), z5(2)], &[z5(3), z5(1)]]).unwrap();
+        assert_eq!(math::det(&m).unwrap(), z5(0));
+        let system = Matrix::new_from(2, 3, &[&[z5(1), z5(2), z5(1)], &[z5(3), z5(1), z5(3)]]).unwrap();
+        assert!(math::solve_system(&system).is_compatible_indeterminado());
     }
 }
@@ -0,0 +1,501 @@
+use std::{
+    cell::Cell,
+    fmt,
+    ops::{Add, Div, Mul, Neg, Rem, Sub},
+};
+
+use num_bigint::BigInt;
+use num_complex::Complex64;
+use num_rational::BigRational;
+use num_traits::{FromPrimitive, Num, One, Signed, ToPrimitive, Zero};
+
+// Fracción exacta, con numerador y denominador de tamaño arbitrario
+pub type Rational = BigRational;
+
+// Complejo con partes real e imaginaria f64
+pub type Complex = Complex64;
+
+// Operaciones que necesita un tipo para ser el elemento de una matriz: las de un cuerpo (`Num`), el cero y el
+// uno, y una forma de comparar tamaños para elegir pivotes
+pub trait Number: Num + Neg<Output = Self> + FromPrimitive + Clone + fmt::Debug + fmt::Display {
+    // Sin errores de redondeo: las fracciones y los enteros modulares
+    const EXACT: bool = false;
+
+    // Tamaño usado para elegir pivotes: eliminar con el más grande es lo que menos precisión pierde
+    fn magnitude(&self) -> f64;
+
+    // El valor como entero, si lo es
+    fn as_integer(&self) -> Option<i64>;
+
+    // `self` elevado a `exp`, si se puede calcular. Salvo en los flotantes el exponente es entero, y la base no es
+    // cero si es negativo
+    fn power(&self, exp: &Self) -> Option<Self>;
+
+    // Si ocupa tanto que seguir operando con él colgaría la calculadora. Solo las fracciones crecen sin límite
+    fn is_huge(&self) -> bool {
+        false
+    }
+
+    // Si es cero en un cálculo con valores de tamaño `scale`. Solo los flotantes tienen errores de redondeo, el
+    // resto compara exactamente
+    fn is_negligible(&self, _scale: f64) -> bool {
+        self.is_zero()
+    }
+
+    // Igualdad, con la tolerancia de `is_negligible`
+    fn approx_eq(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    // Conjugado. Solo los complejos tienen parte imaginaria que cambiar de signo
+    fn conj(&self) -> Self {
+        self.clone()
+    }
+}
+
+// Cuánto pueden diferir dos flotantes para considerarse iguales: la diferencia puede llegar a `absolute`, o a
+// `relative` veces el tamaño de los valores comparados
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tolerance {
+    pub absolute: f64,
+    pub relative: f64,
+}
+
+impl Tolerance {
+    pub const DEFAULT: Tolerance = Tolerance { absolute: 1e-12, relative: 1e-9 };
+
+    pub fn is_negligible(&self, x: f64, scale: f64) -> bool {
+        x.abs() <= self.absolute.max(self.relative * scale.abs())
+    }
+
+    pub fn approx_eq(&self, a: f64, b: f64) -> bool {
+        a == b || self.is_negligible(a - b, a.abs().max(b.abs()))
+    }
+}
+
+// Tolerancia de la sesión, que se cambia desde la REPL con `tolerancia`. Es de cada hilo: la REPL usa uno solo, y
+// así los tests, que corren en paralelo, pueden cambiarla sin afectarse entre sí
+thread_local! {
+    static TOLERANCE: Cell<Tolerance> = const { Cell::new(Tolerance::DEFAULT) };
+}
+
+pub fn tolerance() -> Tolerance {
+    TOLERANCE.get()
+}
+
+pub fn set_tolerance(tolerance: Tolerance) {
+    TOLERANCE.set(tolerance);
+}
+
+// Tamaño máximo, en bits, del numerador o denominador de una fracción calculada con potencias
+pub const MAX_BITS: u64 = 1 << 14;
+
+impl Number for f32 {
+    fn magnitude(&self) -> f64 {
+        self.abs() as f64
+    }
+
+    fn as_integer(&self) -> Option<i64> {
+        // Con la tolerancia, así `0.1 * 30` sirve como exponente o índice
+        (self.approx_eq(&self.round()) && self.abs() < i64::MAX as f32).then_some(self.round() as i64)
+    }
+
+    fn power(&self, exp: &Self) -> Option<Self> {
+        Some(self.powf(*exp))
+    }
+
+    fn is_negligible(&self, scale: f64) -> bool {
+        tolerance().is_negligible(*self as f64, scale)
+    }
+
+    fn approx_eq(&self, other: &Self) -> bool {
+        tolerance().approx_eq(*self as f64, *other as f64)
+    }
+}
+
+impl Number for f64 {
+    fn magnitude(&self) -> f64 {
+        self.abs()
+    }
+
+    fn as_integer(&self) -> Option<i64> {
+        // Con la tolerancia, así `0.1 * 30` sirve como exponente o índice
+        (self.approx_eq(&self.round()) && self.abs() < i64::MAX as f64).then_some(self.round() as i64)
+    }
+
+    fn power(&self, exp: &Self) -> Option<Self> {
+        Some(self.powf(*exp))
+    }
+
+    fn is_negligible(&self, scale: f64) -> bool {
+        tolerance().is_negligible(*self, scale)
+    }
+
+    fn approx_eq(&self, other: &Self) -> bool {
+        tolerance().approx_eq(*self, *other)
+    }
+}
+
+impl Number for Rational {
+    const EXACT: bool = true;
+
+    fn magnitude(&self) -> f64 {
+        self.abs().to_f64().unwrap_or(f64::INFINITY)
+    }
+
+    fn as_integer(&self) -> Option<i64> {
+        if self.is_integer() {
+            self.to_integer().to_i64()
+        } else {
+            None
+        }
+    }
+
+    // Sin resultado si el exponente no es entero, o si la fracción resultante tendría más de `MAX_BITS` bits:
+    // calcularla colgaría la calculadora. Los bits de la base menos uno subestiman su logaritmo a lo sumo a la mitad
+    fn power(&self, exp: &Self) -> Option<Self> {
+        let exp = exp.as_integer()?;
+        let bits = self.numer().bits().max(self.denom().bits()) - 1;
+        if bits.saturating_mul(exp.unsigned_abs()) > MAX_BITS {
+            return None;
+        }
+        let res = num_traits::pow(self.clone(), exp.unsigned_abs() as usize);
+        Some(if exp < 0 { res.recip() } else { res })
+    }
+
+    fn is_huge(&self) -> bool {
+        self.numer().bits().max(self.denom().bits()) > MAX_BITS
+    }
+}
+
+impl Number for Complex {
+    fn magnitude(&self) -> f64 {
+        self.norm()
+    }
+
+    fn as_integer(&self) -> Option<i64> {
+        if self.im.is_negligible(self.re.abs()) {
+            self.re.as_integer()
+        } else {
+            None
+        }
+    }
+
+    // Las potencias enteras se calculan con productos, más precisos que los logaritmos de `powc`
+    fn power(&self, exp: &Self) -> Option<Self> {
+        Some(match exp.as_integer() {
+            Some(exp) if exp.unsigned_abs() <= i32::MAX as u64 => self.powi(exp as i32),
+            _ => self.powc(*exp),
+        })
+    }
+
+    fn is_negligible(&self, scale: f64) -> bool {
+        tolerance().is_negligible(self.norm(), scale)
+    }
+
+    fn approx_eq(&self, other: &Self) -> bool {
+        let tolerance = tolerance();
+        tolerance.approx_eq(self.re, other.re) && tolerance.approx_eq(self.im, other.im)
+    }
+
+    fn conj(&self) -> Self {
+        Complex::conj(self)
+    }
+}
+
+// Entero módulo un primo `modulus`, con `value` entre 0 y `modulus - 1`. Las constantes que crea el código
+// genérico, como `zero()` y `one()`, no conocen el módulo: lo tienen en 0 y toman el del otro operando
+#[derive(Clone, Copy, Debug)]
+pub struct Modular {
+    value: i64,
+    modulus: i64,
+}
+
+impl Modular {
+    // `modulus` debe ser primo, así todo elemento distinto de cero tiene inverso
+    pub fn new(value: i64, modulus: i64) -> Modular {
+        Modular { value: reduce(value as i128, modulus), modulus }
+    }
+
+    pub fn value(&self) -> i64 {
+        self.value
+    }
+
+    // La fracción `numer / denom`, si el denominador tiene inverso
+    pub fn from_rational(x: &Rational, modulus: i64) -> Option<Modular> {
+        let numer = (x.numer() % modulus).to_i64()?;
+        let denom = Modular::new((x.denom() % modulus).to_i64()?, modulus);
+        denom.inverse().map(|inverse| Modular::new(numer, modulus) * inverse)
+    }
+
+    // Inverso multiplicativo, por el algoritmo de Euclides extendido
+    fn inverse(&self) -> Option<Modular> {
+        if self.modulus == 0 {
+            // Constante sin módulo: solo 1 y -1 tienen inverso entero
+            return (self.value.abs() == 1).then_some(*self);
+        }
+        let (mut a, mut b) = (self.value as i128, self.modulus as i128);
+        let (mut x, mut y) = (1i128, 0i128);
+        while b != 0 {
+            let q = a / b;
+            (a, b) = (b, a - q * b);
+            (x, y) = (y, x - q * y);
+        }
+        (a == 1).then(|| Modular::new(reduce(x, self.modulus), self.modulus))
+    }
+
+    // `self` elevado a un entero sin reducir, porque reducirlo módulo `modulus` cambiaría la potencia. Si es
+    // negativo se eleva el inverso, que el cero no tiene
+    pub fn pow_int(&self, exp: i64) -> Option<Modular> {
+        let base = if exp < 0 { self.inverse()? } else { *self };
+        let result = num_traits::pow(base, exp.unsigned_abs() as usize);
+        Some(Modular::new(result.value, self.modulus))
+    }
+
+    // Aplica `f` a los valores, con el módulo que tenga alguno de los dos
+    fn combine(self, other: Modular, f: impl Fn(i128, i128) -> i128) -> Modular {
+        let modulus = self.modulus.max(other.modulus);
+        Modular { value: reduce(f(self.value as i128, other.value as i128), modulus), modulus }
+    }
+}
+
+fn reduce(value: i128, modulus: i64) -> i64 {
+    if modulus == 0 {
+        value as i64
+    } else {
+        value.rem_euclid(modulus as i128) as i64
+    }
+}
+
+impl PartialEq for Modular {
+    fn eq(&self, other: &Modular) -> bool {
+        let modulus = self.modulus.max(other.modulus);
+        reduce(self.value as i128, modulus) == reduce(other.value as i128, modulus)
+    }
+}
+
+impl fmt::Display for Modular {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.value)
+    }
+}
+
+impl Add for Modular {
+    type Output = Modular;
+
+    fn add(self, other: Modular) -> Modular {
+        self.combine(other, |a, b| a + b)
+    }
+}
+
+impl Sub for Modular {
+    type Output = Modular;
+
+    fn sub(self, other: Modular) -> Modular {
+        self.combine(other, |a, b| a - b)
+    }
+}
+
+impl Mul for Modular {
+    type Output = Modular;
+
+    fn mul(self, other: Modular) -> Modular {
+        self.combine(other, |a, b| a * b)
+    }
+}
+
+// Multiplica por el inverso. Con módulo primo solo el cero no lo tiene, y el que divide lo descarta antes, como
+// con cualquier otro número
+impl Div for Modular {
+    type Output = Modular;
+
+    fn div(self, other: Modular) -> Modular {
+        let modulus = self.modulus.max(other.modulus);
+        let inverse = Modular::new(other.value, modulus).inverse().expect("Division by zero");
+        self.combine(inverse, |a, b| a * b)
+    }
+}
+
+// En un cuerpo toda división es exacta, así que el resto siempre es cero
+impl Rem for Modular {
+    type Output = Modular;
+
+    fn rem(self, other: Modular) -> Modular {
+        self.combine(other, |_, _| 0)
+    }
+}
+
+impl Neg for Modular {
+    type Output = Modular;
+
+    fn neg(self) -> Modular {
+        Modular::new(-self.value, self.modulus)
+    }
+}
+
+impl Zero for Modular {
+    fn zero() -> Modular {
+        Modular { value: 0, modulus: 0 }
+    }
+
+    fn is_zero(&self) -> bool {
+        self.value == 0
+    }
+}
+
+impl One for Modular {
+    fn one() -> Modular {
+        Modular { value: 1, modulus: 0 }
+    }
+}
+
+impl Num for Modular {
+    type FromStrRadixErr = std::num::ParseIntError;
+
+    fn from_str_radix(text: &str, radix: u32) -> Result<Modular, Self::FromStrRadixErr> {
+        i64::from_str_radix(text, radix).map(|value| Modular { value, modulus: 0 })
+    }
+}
+
+impl FromPrimitive for Modular {
+    fn from_i64(value: i64) -> Option<Modular> {
+        Some(Modular { value, modulus: 0 })
+    }
+
+    fn from_u64(value: u64) -> Option<Modular> {
+        i64::try_from(value).ok().map(|value| Modular { value, modulus: 0 })
+    }
+}
+
+impl Number for Modular {
+    const EXACT: bool = true;
+
+    // No hay un orden: cualquier elemento distinto de cero sirve de pivote
+    fn magnitude(&self) -> f64 {
+        if self.is_zero() {
+            0.0
+        } else {
+            1.0
+        }
+    }
+
+    fn as_integer(&self) -> Option<i64> {
+        Some(self.value)
+    }
+
+    // Con el representante del exponente. Las potencias de la calculadora usan `pow_int`, que no lo reduce
+    fn power(&self, exp: &Self) -> Option<Self> {
+        self.pow_int(exp.value)
+    }
+}
+
+pub fn is_prime(n: i64) -> bool {
+    n >= 2 && (2..).take_while(|d| d * d <= n).all(|d| n % d != 0)
+}
+
+// Fracción con el valor decimal que muestra el flotante, así 0.1 es 1/10 y no la aproximación binaria que
+// guarda el f64. No existe para infinito ni NaN
+pub fn rational_from_f64(x: f64) -> Option<Rational> {
+    if !x.is_finite() {
+        return None;
+    }
+    // El Display de f64 nunca usa notación científica
+    let text = x.to_string();
+    let (int, frac) = text.split_once('.').unwrap_or((&text, ""));
+    let numer = BigInt::from_str_radix(&format!("{}{}", int, frac), 10).ok()?;
+    let denom = num_traits::pow(BigInt::from(10), frac.len());
+    Some(Rational::new(numer, denom))
+}
+
+pub fn rational_to_f64(x: &Rational) -> f64 {
+    x.to_f64().unwrap_or(f64::NAN)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::number::{is_prime, rational_from_f64, rational_to_f64, Complex, Modular, Number, Rational, Tolerance};
+    use num_traits::{One, Zero};
+
+    fn frac(numer: i64, denom: i64) -> Rational {
+        Rational::new(numer.into(), denom.into())
+    }
+
+    #[test]
+    fn from_decimal() {
+        assert_eq!(rational_from_f64(0.1), Some(frac(1, 10)));
+        assert_eq!(rational_from_f64(-2.5), Some(frac(-5, 2)));
+        assert_eq!(rational_from_f64(3.0), Some(frac(3, 1)));
+        assert_eq!(rational_from_f64(-0.0), Some(Rational::zero()));
+        assert_eq!(rational_from_f64(f64::INFINITY), None);
+        assert_eq!(rational_to_f64(&frac(1, 4)), 0.25);
+    }
+
+    #[test]
+    fn rational_number() {
+        assert_eq!(frac(2, 3).power(&frac(2, 1)), Some(frac(4, 9)));
+        assert_eq!(frac(2, 3).power(&frac(-1, 1)), Some(frac(3, 2)));
+        assert_eq!(frac(2, 3).power(&frac(0, 1)), Some(frac(1, 1)));
+        assert_eq!(frac(2, 3).power(&frac(1, 2)), None);
+        assert_eq!(frac(2, 1).power(&frac(100_000_000, 1)), None);
+        assert_eq!(frac(-1, 1).power(&frac(100_000_001, 1)), Some(frac(-1, 1)));
+        assert!(frac(2, 1).power(&frac(20_000, 1)).is_none_or(|x| x.is_huge()) && !frac(1, 3).is_huge());
+        assert_eq!(frac(6, 3).as_integer(), Some(2));
+        assert_eq!(frac(1, 3).as_integer(), None);
+        assert_eq!(frac(-1, 4).magnitude(), 0.25);
+        assert_eq!(frac(1, 3).to_string(), "1/3");
+    }
+
+    #[test]
+    fn tolerance() {
+        let tolerance = Tolerance { absolute: 1e-12, relative: 1e-9 };
+        assert!(tolerance.approx_eq(0.1 + 0.2, 0.3));
+        assert!(tolerance.approx_eq(1e20 + 1e10, 1e20));
+        assert!(!tolerance.approx_eq(1.0, 1.0 + 1e-6));
+        assert!(!tolerance.approx_eq(1e-11, 0.0));
+        assert!(tolerance.is_negligible(1e-13, 0.0));
+        assert!(tolerance.is_negligible(1e-7, 1e3));
+        assert!(!tolerance.is_negligible(1e-7, 1.0));
+        assert!(!0.5f64.is_negligible(0.0) && 1e-16f64.is_negligible(1.0));
+        // Los tipos exactos no tienen tolerancia
+        assert!(!frac(1, 1_000_000_000_000_000).is_negligible(1.0));
+        assert_eq!((0.1f64 * 30.0).as_integer(), Some(3));
+        assert_eq!(2.5f64.as_integer(), None);
+    }
+
+    #[test]
+    fn complex_number() {
+        let i = Complex::new(0.0, 1.0);
+        assert_eq!(i.power(&Complex::new(2.0, 0.0)), Some(Complex::new(-1.0, 0.0)));
+        assert_eq!(i.power(&Complex::new(-1.0, 0.0)), Some(-i));
+        assert!(i.power(&Complex::new(0.5, 0.0)).unwrap().approx_eq(&Complex::new(0.5f64.sqrt(), 0.5f64.sqrt())));
+        assert_eq!(Number::conj(&Complex::new(3.0, 2.0)), Complex::new(3.0, -2.0));
+        assert_eq!(Complex::new(3.0, 4.0).magnitude(), 5.0);
+        assert_eq!(Complex::new(2.0, 1e-17).as_integer(), Some(2));
+        assert_eq!(Complex::new(2.0, 1.0).as_integer(), None);
+        assert_eq!(Complex::new(3.0, -2.0).to_string(), "3-2i");
+        assert!(Complex::new(1e-13, -1e-13).is_negligible(1.0));
+    }
+
+    #[test]
+    fn modular_number() {
+        let z7 = |num: i64| Modular::new(num, 7);
+        assert_eq!(z7(-1).value(), 6);
+        assert_eq!(z7(5) + z7(4), z7(2));
+        assert_eq!(z7(2) - z7(5), z7(4));
+        assert_eq!(z7(3) * z7(5), z7(1));
+        assert_eq!(z7(1) / z7(3), z7(5));
+        assert_eq!(-z7(2), z7(5));
+        assert_eq!(z7(3).power(&z7(6)), Some(z7(1)));
+        assert_eq!(z7(2).pow_int(7), Some(z7(2)));
+        assert_eq!(z7(2).pow_int(-1), Some(z7(4)));
+        assert_eq!(z7(0).pow_int(-1), None);
+        assert_eq!(z7(0).pow_int(0), Some(z7(1)));
+        // Las constantes sin módulo toman el del otro operando
+        assert_eq!(z7(6) + Modular::one(), Modular::zero());
+        assert_eq!((Modular::one() / z7(2)).value(), 4);
+        assert_eq!(Modular::from_rational(&frac(1, 2), 7), Some(z7(4)));
+        assert_eq!(Modular::from_rational(&frac(-3, 1), 7), Some(z7(4)));
+        assert_eq!(Modular::from_rational(&frac(1, 14), 7), None);
+        assert!(is_prime(7) && is_prime(2) && !is_prime(1) && !is_prime(9));
+    }
+}
@@ -1,33 +1,40 @@
 use std::{error::Error, ops::Index};
 
+use num_traits::Zero;
+
+use crate::number::Number;
+
+// Los elementos son f64 salvo que se indique otro tipo, como las fracciones del modo exacto
 #[derive(Debug)]
-pub struct Matrix {
+pub struct Matrix<T = f64> {
     pub m: usize,
     pub n: usize,
-    data: Vec<Vec<f32>>,
+    data: Vec<Vec<T>>,
 }
 
-impl Clone for Matrix {
-    fn clone(&self) -> Matrix {
+impl<T: Clone> Clone for Matrix<T> {
+    fn clone(&self) -> Matrix<T> {
         Matrix { m: self.m.clone(), n: self.n.clone(), data: self.data.clone() }
     }
 }
 
-impl Matrix {
-    pub fn new_empty(rows: usize, cols: usize) -> Matrix {
+impl<T: Clone + Zero> Matrix<T> {
+    pub fn new_empty(rows: usize, cols: usize) -> Matrix<T> {
         Matrix {
             m: rows,
             n: cols,
-            data: vec![vec![0.0; cols]; rows],
+            data: vec![vec![T::zero(); cols]; rows],
         }
     }
+}
 
-    pub fn new_from(rows: usize, cols: usize, arr: &[&[f32]]) -> Result<Matrix, Box<dyn Error>> {
+impl<T: Clone> Matrix<T> {
+    pub fn new_from(rows: usize, cols: usize, arr: &[&[T]]) -> Result<Matrix<T>, Box<dyn Error>> {
         if arr.len() != rows {
             return Err("Bad dimensions")?;
         }
 
-        let mut data: Vec<Vec<f32>> = Vec::new();
+        let mut data: Vec<Vec<T>> = Vec::new();
         for a in arr {
             if a.len() != cols {
                 return Err("Bad dimensions")?;
@@ -41,17 +48,33 @@ impl Matrix {
         });
     }
 
-    pub fn fill(&mut self, num: f32) {
-        self.data = vec![vec![num; self.n]; self.n]
+    pub fn fill(&mut self, num: T) {
+        self.data = vec![vec![num; self.n]; self.m]
+    }
+
+    pub fn set(&mut self, m: usize, n: usize, value: T) {
+        self.data[m][n] = value;
+    }
+
+    pub fn is_squared(&self) -> bool {
+        return self.m == self.n;
+    }
+
+    // Matriz de las mismas dimensiones con `f` aplicada a cada elemento
+    pub fn map<U>(&self, f: impl Fn(&T) -> U) -> Matrix<U> {
+        Matrix { m: self.m, n: self.n, data: self.data.iter().map(|row| row.iter().map(&f).collect()).collect() }
     }
+}
 
-    pub fn equals(&self, other: &Matrix) -> bool {
+impl<T: Number> Matrix<T> {
+    // Compara con la tolerancia de la sesión, así los errores de redondeo no hacen distintas a dos matrices
+    pub fn equals(&self, other: &Matrix<T>) -> bool {
         if self.m != other.m || self.n != other.n {
             return false;
         }
         for i in 0..self.m {
             for j in 0..self.n {
-                if self[i][j] != other[i][j] {
+                if !self[i][j].approx_eq(&other[i][j]) {
                     return false;
                 }
             }
@@ -59,18 +82,58 @@ impl Matrix {
         return true;
     }
 
-    pub fn set(&mut self, m: usize, n: usize, value: f32) {
-        self.data[m][n] = value;
+    // Tamaño del mayor elemento, con el que se decide qué valores son errores de redondeo
+    pub fn scale(&self) -> f64 {
+        self.data.iter().flatten().map(Number::magnitude).fold(0.0, f64::max)
     }
 
-    pub fn is_squared(&self) -> bool {
-        return self.m == self.n;
+    // Las propiedades se comprueban con la tolerancia de la sesión, relativa al mayor elemento de la matriz
+    fn all_negligible(&self, entries: impl Fn(usize, usize) -> bool) -> bool {
+        let scale = self.scale();
+        (0..self.m).all(|i| (0..self.n).all(|j| !entries(i, j) || self[i][j].is_negligible(scale)))
+    }
+
+    pub fn is_symmetric(&self) -> bool {
+        let scale = self.scale();
+        self.is_squared() && (0..self.m).all(|i| (0..i).all(|j| (self[i][j].clone() - self[j][i].clone()).is_negligible(scale)))
+    }
+
+    // Solo la diagonal puede tener elementos no nulos. Vale también para matrices no cuadradas
+    pub fn is_diagonal(&self) -> bool {
+        self.all_negligible(|i, j| i != j)
+    }
+
+    pub fn is_upper_triangular(&self) -> bool {
+        self.all_negligible(|i, j| j < i)
+    }
+
+    pub fn is_lower_triangular(&self) -> bool {
+        self.all_negligible(|i, j| j > i)
+    }
+
+    // Triangular superior o inferior
+    pub fn is_triangular(&self) -> bool {
+        self.is_upper_triangular() || self.is_lower_triangular()
+    }
+
+    // Columnas ortonormales: `A^H A = I`. Para números complejos es una matriz unitaria
+    pub fn is_orthogonal(&self) -> bool {
+        if !self.is_squared() {
+            return false;
+        }
+        (0..self.n).all(|i| {
+            (0..self.n).all(|j| {
+                let product = (0..self.m).fold(T::zero(), |sum, k| sum + self[k][i].conj() * self[k][j].clone());
+                let identity = if i == j { T::one() } else { T::zero() };
+                (product - identity).is_negligible(1.0)
+            })
+        })
     }
 }
 
 // Implemetar acceso por indice y doble indice
-impl Index<usize> for Matrix {
-    type Output = Vec<f32>;
+impl<T> Index<usize> for Matrix<T> {
+    type Output = Vec<T>;
 
     fn index(&self, row_index: usize) -> &Self::Output {
         assert!(row_index < self.m);
@@ -91,7 +154,7 @@ mod tests {
     fn create_empty_matrix() {
         let rows: usize = 4;
         let cols: usize = 3;
-        let matrix_a = Matrix::new_empty(rows, cols);
+        let matrix_a: Matrix = Matrix::new_empty(rows, cols);
         for i in 0..rows {
             for j in 0..cols {
                 assert_eq!(matrix_a[i][j], 0.0)
@@ -116,6 +179,21 @@ mod tests {
         assert!(!Matrix::new_from(1, 2, &[&[1.0, 2.0]]).unwrap().is_squared())
     }
 
+    #[test]
+    fn properties() {
+        let m = Matrix::new_from(3, 3, &[&[2.0, 1.0, 0.0], &[1.0, 2.0, 1.0], &[0.0, 1.0, 2.0]]).unwrap();
+        assert!(m.is_symmetric());
+        assert!(!m.is_diagonal() && !m.is_triangular() && !m.is_orthogonal());
+        assert!(!create2by2().is_symmetric());
+        let upper = Matrix::new_from(2, 3, &[&[1.0, 2.0, 3.0], &[1e-17, 4.0, 5.0]]).unwrap();
+        assert!(upper.is_upper_triangular() && upper.is_triangular() && !upper.is_lower_triangular());
+        let diagonal = Matrix::new_from(3, 2, &[&[1.0, 0.0], &[0.0, 2.0], &[0.0, 0.0]]).unwrap();
+        assert!(diagonal.is_diagonal() && diagonal.is_upper_triangular() && diagonal.is_lower_triangular());
+        let (c, s) = (0.6, 0.8);
+        assert!(Matrix::new_from(2, 2, &[&[c, -s], &[s, c]]).unwrap().is_orthogonal());
+        assert!(!Matrix::new_from(2, 2, &[&[2.0, 0.0], &[0.0, 2.0]]).unwrap().is_orthogonal());
+    }
+
     #[test]
     fn equals() {
         let m1 = create2by2();
@@ -123,5 +201,21 @@ mod tests {
         assert!(m1.equals(&m2));
         let m3 = Matrix::new_from(2, 3, &[&[1.0, 2.0, 3.0], &[3.0, 4.0, 3.0]]).unwrap();
         assert!(!m1.equals(&m3));
+        let m4 = Matrix::new_from(2, 2, &[&[1.0, 0.1 + 0.2], &[3.0, 4.0 + 1e-13]]).unwrap();
+        assert!(m4.equals(&Matrix::new_from(2, 2, &[&[1.0, 0.3], &[3.0, 4.0]]).unwrap()));
+        assert!(!m1.equals(&Matrix::new_from(2, 2, &[&[1.0, 2.0], &[3.0, 4.001]]).unwrap()));
+    }
+
+    #[test]
+    fn fill() {
+        let mut m = Matrix::new_empty(3, 1);
+        m.fill(2.0);
+        assert!(m.equals(&Matrix::new_from(3, 1, &[&[2.0], &[2.0], &[2.0]]).unwrap()));
+    }
+
+    #[test]
+    fn map() {
+        let m = create2by2().map(|x| *x as i32 * 2);
+        assert_eq!((m[0][0], m[0][1], m[1][0], m[1][1]), (2, 4, 6, 8));
     }
 }